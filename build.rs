@@ -0,0 +1,13 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // Only the `grpc` feature needs code generated, with a bundled protoc so nothing has
+    // to be installed
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/smolcar.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no bundled protoc for this platform");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::compile_protos("proto/smolcar.proto").expect("failed to compile proto/smolcar.proto");
+    }
+}
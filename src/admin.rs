@@ -0,0 +1,102 @@
+//! Maintenance routes under `/admin`, which need `Authorization: Bearer <admin_token>` and are
+//! refused altogether when no token is set.
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::header,
+    middleware::{self, Next},
+    response::{Json, Response},
+    routing::{delete, get, post},
+    Router,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::api::AppState;
+use crate::db::BlockConflict;
+use crate::error::Error;
+use crate::retention;
+use crate::subscriptions;
+
+pub fn router(state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/compact", post(compact))
+        .route("/blocks", delete(delete_blocks))
+        .route("/block/:number", delete(delete_block))
+        .route("/conflicts", get(get_conflicts))
+        .route("/subscriptions", get(subscriptions::list).post(subscriptions::add))
+        .route(
+            "/subscriptions/:id",
+            get(subscriptions::get).put(subscriptions::update).delete(subscriptions::remove),
+        )
+        .route_layer(middleware::from_fn_with_state(state, require_token))
+}
+
+async fn require_token(State(state): State<AppState>, request: Request, next: Next) -> Result<Response, Error> {
+    let Some(token) = &state.admin_token else {
+        return Err(Error::Forbidden("Admin routes are off, set admin_token to use them".to_string()));
+    };
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if given != Some(token.as_str()) {
+        return Err(Error::Unauthorized("Missing or wrong admin token".to_string()));
+    }
+    Ok(next.run(request).await)
+}
+
+async fn compact(State(state): State<AppState>) -> Result<Json<serde_json::Value>, Error> {
+    let freed = retention::compact(&state.chain, state.db.clone()).await?;
+    Ok(Json(json!({
+        "freed_bytes": freed,
+        "file_bytes": state.db.blocking(|db| db.file_bytes()).await.unwrap_or_default(),
+    })))
+}
+
+#[derive(Deserialize)]
+struct DeleteBlocksQuery {
+    before: u32,
+}
+
+async fn delete_blocks(
+    State(state): State<AppState>,
+    Query(query): Query<DeleteBlocksQuery>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let deleted = retention::prune_before(&state.chain, state.db.clone(), query.before).await?;
+    Ok(Json(json!({ "deleted": deleted })))
+}
+
+const MAX_CONFLICTS: u32 = 1000;
+
+#[derive(Deserialize)]
+struct ConflictsQuery {
+    /// Page back from an `id` of the previous page
+    before: Option<i64>,
+    limit: Option<u32>,
+}
+
+async fn get_conflicts(
+    State(state): State<AppState>,
+    Query(query): Query<ConflictsQuery>,
+) -> Result<Json<Vec<BlockConflict>>, Error> {
+    let limit = query.limit.unwrap_or(100).min(MAX_CONFLICTS);
+    Ok(Json(state.db.blocking(move |db| db.get_conflicts(query.before, limit)).await?))
+}
+
+/// For a corrupted or unwanted block. Nothing fetches it again by itself: it stays missing, and
+/// listed as a gap by `smolcar check`, until a start whose `[backfill]` range covers it.
+async fn delete_block(
+    State(state): State<AppState>,
+    Path(number): Path<u32>,
+) -> Result<Json<serde_json::Value>, Error> {
+    if !state.db.blocking(move |db| db.delete_block(number)).await? {
+        return Err(Error::NotFound(format!("Block {} is not stored", number)));
+    }
+    tracing::info!(chain = %state.chain, number, "Deleted block");
+    Ok(Json(json!({
+        "deleted": number,
+        "refetch": "not scheduled, the block stays missing until a [backfill] range covering it runs on a later start",
+    })))
+}
@@ -0,0 +1,286 @@
+//! Chat alerts: post a message to Telegram, Discord or Slack when a watched event fires.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use subxt::utils::AccountId32;
+
+use crate::db::Watch;
+use crate::rules::{EventContext, EventRule};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Channel {
+    Telegram { bot_token: String, chat_id: String },
+    Discord { webhook_url: String },
+    Slack { webhook_url: String },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Alert {
+    pub name: String,
+    #[serde(flatten)]
+    pub rule: EventRule,
+    /// Names of entries in `[alert_channels]`
+    pub channels: Vec<String>,
+}
+
+/// Fires on `Offences::Offence` reports, see `offences.rs`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OffenceAlert {
+    pub chain: Option<String>,
+    /// Only offences by these accounts (any SS58 format), every offence if empty
+    #[serde(default)]
+    pub offenders: Vec<String>,
+    pub channels: Vec<String>,
+}
+
+/// Fires when an `ImOnline` session end reports validators offline, see `validators.rs`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OfflineAlert {
+    pub chain: Option<String>,
+    /// Only these validators (any SS58 format), anyone reported offline if empty
+    #[serde(default)]
+    pub validators: Vec<String>,
+    pub channels: Vec<String>,
+}
+
+struct Setup {
+    channels: HashMap<String, Channel>,
+    alerts: Vec<Alert>,
+    offence_alerts: Vec<OffenceAlert>,
+    offline_alerts: Vec<OfflineAlert>,
+    /// By chain, from its `subscriptions` table. Config reloads leave them alone
+    subscriptions: HashMap<String, Vec<Alert>>,
+}
+
+impl Setup {
+    fn all_alerts(&self) -> impl Iterator<Item = &Alert> {
+        self.alerts.iter().chain(self.subscriptions.values().flatten())
+    }
+}
+
+pub struct Alerts {
+    setup: RwLock<Setup>,
+    http: reqwest::Client,
+}
+
+impl Alerts {
+    pub fn new(
+        channels: HashMap<String, Channel>,
+        alerts: Vec<Alert>,
+        offence_alerts: Vec<OffenceAlert>,
+        offline_alerts: Vec<OfflineAlert>,
+    ) -> Result<Self, String> {
+        validate(&channels, &alerts, &offence_alerts, &offline_alerts)?;
+        Ok(Alerts {
+            setup: RwLock::new(Setup {
+                channels,
+                alerts,
+                offence_alerts,
+                offline_alerts,
+                subscriptions: HashMap::new(),
+            }),
+            http: reqwest::Client::new(),
+        })
+    }
+
+    /// Swaps in new channels and alerts, e.g. after a config reload.
+    pub fn replace(
+        &self,
+        channels: HashMap<String, Channel>,
+        alerts: Vec<Alert>,
+        offence_alerts: Vec<OffenceAlert>,
+        offline_alerts: Vec<OfflineAlert>,
+    ) -> Result<(), String> {
+        validate(&channels, &alerts, &offence_alerts, &offline_alerts)?;
+        let mut setup = self.setup.write().unwrap();
+        let subscriptions = std::mem::take(&mut setup.subscriptions);
+        *setup = Setup { channels, alerts, offence_alerts, offline_alerts, subscriptions };
+        Ok(())
+    }
+
+    /// Swaps in a chain's subscriptions, after they were loaded or changed.
+    pub fn set_subscriptions(&self, chain: &str, alerts: Vec<Alert>) {
+        self.setup.write().unwrap().subscriptions.insert(chain.to_string(), alerts);
+    }
+
+    /// Whether any alert could fire on this kind of event, so the indexer knows to decode
+    /// events it would otherwise filter out.
+    pub fn is_watching(&self, chain: &str, pallet: &str, variant: &str) -> bool {
+        self.setup
+            .read()
+            .unwrap()
+            .all_alerts()
+            .any(|alert| alert.rule.matches_kind(chain, pallet, variant))
+    }
+
+    pub fn has_channel(&self, name: &str) -> bool {
+        self.setup.read().unwrap().channels.contains_key(name)
+    }
+
+    /// Posts a watched account's activity to the channels it was registered with.
+    pub fn on_watched(&self, chain: &str, block_number: u32, extrinsic_index: u32, watch: &Watch, activity: &[String]) {
+        let setup = self.setup.read().unwrap();
+        let text = format!(
+            "[{}] {} in block #{} (extrinsic {})\n{}",
+            chain,
+            watch.label.as_deref().unwrap_or(&watch.address),
+            block_number,
+            extrinsic_index,
+            activity.join(", ")
+        );
+        for name in &watch.channels {
+            if let Some(channel) = setup.channels.get(name) {
+                tokio::spawn(send(self.http.clone(), name.clone(), channel.clone(), text.clone()));
+            }
+        }
+    }
+
+    /// Fires every alert matching the event. Sending happens in the background.
+    pub fn on_event(&self, ctx: &EventContext) {
+        let setup = self.setup.read().unwrap();
+        for alert in setup.all_alerts() {
+            if !alert.rule.matches(ctx.chain, ctx.event) {
+                continue;
+            }
+
+            let text = format!(
+                "[{}] {}: {}::{} in block #{} (extrinsic {})\n{}",
+                ctx.chain,
+                alert.name,
+                ctx.event.pallet,
+                ctx.event.variant,
+                ctx.block_number,
+                ctx.extrinsic_index,
+                ctx.event.data
+            );
+
+            for name in &alert.channels {
+                if let Some(channel) = setup.channels.get(name) {
+                    tokio::spawn(send(self.http.clone(), name.clone(), channel.clone(), text.clone()));
+                }
+            }
+        }
+    }
+
+    /// Fires every offence alert watching one of the offenders (given as SS58 addresses).
+    pub fn on_offence(&self, chain: &str, block_number: u32, kind: &str, offenders: &[String]) {
+        let setup = self.setup.read().unwrap();
+        let accounts: Vec<AccountId32> = offenders.iter().filter_map(|o| o.parse().ok()).collect();
+        for alert in &setup.offence_alerts {
+            let watched = alert.offenders.is_empty()
+                || alert
+                    .offenders
+                    .iter()
+                    .filter_map(|o| o.parse::<AccountId32>().ok())
+                    .any(|o| accounts.contains(&o));
+            if alert.chain.as_deref().is_some_and(|c| c != chain) || !watched {
+                continue;
+            }
+
+            let text = format!(
+                "[{}] Offence {:?} reported in block #{}\nOffenders: {}",
+                chain,
+                kind,
+                block_number,
+                offenders.join(", ")
+            );
+            for name in &alert.channels {
+                if let Some(channel) = setup.channels.get(name) {
+                    tokio::spawn(send(self.http.clone(), name.clone(), channel.clone(), text.clone()));
+                }
+            }
+        }
+    }
+
+    /// Fires every offline alert watching one of the validators (given as SS58 addresses)
+    /// a session end reported offline.
+    pub fn on_offline(&self, chain: &str, block_number: u32, offline: &[String]) {
+        if offline.is_empty() {
+            return;
+        }
+        let setup = self.setup.read().unwrap();
+        let accounts: Vec<AccountId32> = offline.iter().filter_map(|v| v.parse().ok()).collect();
+        for alert in &setup.offline_alerts {
+            let watched: Vec<&String> = offline
+                .iter()
+                .zip(&accounts)
+                .filter(|(_, account)| {
+                    alert.validators.is_empty()
+                        || alert.validators.iter().any(|v| v.parse::<AccountId32>().ok().as_ref() == Some(account))
+                })
+                .map(|(address, _)| address)
+                .collect();
+            if alert.chain.as_deref().is_some_and(|c| c != chain) || watched.is_empty() {
+                continue;
+            }
+
+            let text = format!(
+                "[{}] Validators reported offline in block #{}: {}",
+                chain,
+                block_number,
+                watched.iter().map(|v| v.as_str()).collect::<Vec<_>>().join(", ")
+            );
+            for name in &alert.channels {
+                if let Some(channel) = setup.channels.get(name) {
+                    tokio::spawn(send(self.http.clone(), name.clone(), channel.clone(), text.clone()));
+                }
+            }
+        }
+    }
+}
+
+fn validate(
+    channels: &HashMap<String, Channel>,
+    alerts: &[Alert],
+    offence_alerts: &[OffenceAlert],
+    offline_alerts: &[OfflineAlert],
+) -> Result<(), String> {
+    let named = alerts.iter().map(|alert| (alert.name.as_str(), &alert.channels));
+    let offences = offence_alerts.iter().map(|alert| ("offence alert", &alert.channels));
+    let offline = offline_alerts.iter().map(|alert| ("offline alert", &alert.channels));
+    for (alert, names) in named.chain(offences).chain(offline) {
+        for name in names {
+            if !channels.contains_key(name) {
+                return Err(format!(
+                    "alert {:?} uses channel {:?} which isn't defined in [alert_channels]",
+                    alert, name
+                ));
+            }
+        }
+    }
+    for offender in offence_alerts.iter().flat_map(|alert| &alert.offenders) {
+        if offender.parse::<AccountId32>().is_err() {
+            return Err(format!("offence alert offender {:?} isn't an SS58 address", offender));
+        }
+    }
+    for validator in offline_alerts.iter().flat_map(|alert| &alert.validators) {
+        if validator.parse::<AccountId32>().is_err() {
+            return Err(format!("offline alert validator {:?} isn't an SS58 address", validator));
+        }
+    }
+    Ok(())
+}
+
+async fn send(http: reqwest::Client, name: String, channel: Channel, text: String) {
+    let request = match &channel {
+        Channel::Telegram { bot_token, chat_id } => http
+            .post(format!("https://api.telegram.org/bot{}/sendMessage", bot_token))
+            .json(&serde_json::json!({ "chat_id": chat_id, "text": text })),
+        Channel::Discord { webhook_url } => http
+            .post(webhook_url)
+            .json(&serde_json::json!({ "content": text })),
+        Channel::Slack { webhook_url } => http
+            .post(webhook_url)
+            .json(&serde_json::json!({ "text": text })),
+    };
+
+    match request.send().await.and_then(|r| r.error_for_status()) {
+        Ok(_) => tracing::debug!(channel = %name, "Alert sent"),
+        // The URL can contain secrets (bot token, webhook path), so don't log it
+        Err(e) => tracing::warn!(channel = %name, "Failed to send alert: {}", e.without_url()),
+    }
+}
@@ -0,0 +1,563 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, Request},
+    middleware,
+    response::{Html, IntoResponse, Json, Response},
+    routing::{delete, get, post},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use subxt::backend::rpc::RpcClient;
+use subxt::{client::OnlineClient, PolkadotConfig};
+use tower::ServiceBuilder;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tower_http::LatencyUnit;
+use tracing::{Level, Span};
+
+use crate::admin;
+use crate::alerts::Alerts;
+use crate::archive::Archive;
+use crate::assets;
+use crate::babe;
+use crate::db;
+use crate::election;
+use crate::encoding;
+use crate::envelope;
+use crate::era_points;
+use crate::info;
+use crate::error::Error;
+use crate::feed;
+#[cfg(feature = "graphql")]
+use crate::graphql;
+use crate::indexer::{self, BlockInfo, SharedBlockInfo};
+use crate::justifications;
+use crate::limits::{self, Limits};
+use crate::locks;
+use crate::metrics;
+use crate::nfts;
+use crate::offences;
+use crate::parachains;
+use crate::proof;
+use crate::referenda;
+use crate::reporting;
+use crate::rpc;
+use crate::scheduler;
+use crate::sidecar;
+use crate::stream::EventStream;
+use crate::sync;
+use crate::validators;
+use crate::vesting;
+use crate::watchlist;
+use crate::webhooks;
+use crate::xcm;
+
+const DASHBOARD: &str = include_str!("../assets/dashboard.html");
+const MAX_RECENT_BLOCKS: u32 = 100;
+const MAX_BATCH_BLOCKS: usize = 500;
+const MAX_RANGE_SUMMARIES: u32 = 1000;
+const MAX_CALLS: usize = 1000;
+// How far back from the latest block `/calls` looks
+const CALLS_SCAN_BLOCKS: u32 = 10_000;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub chain: String,
+    /// Bearer token the `/admin` routes need, they're refused without one
+    pub admin_token: Option<String>,
+    /// Serving with `--read-only`: the routes that change anything aren't there
+    pub read_only: bool,
+    pub block_info: SharedBlockInfo,
+    pub db: Arc<db::Database>,
+    /// Where pruned blocks went, if anywhere
+    pub archive: Option<Arc<Archive>>,
+    /// For the few routes that need chain state rather than stored blocks
+    pub api: OnlineClient<PolkadotConfig>,
+    /// The light client's JSON-RPC, for what `api` doesn't cover (read proofs, passthrough)
+    pub rpc: RpcClient,
+    /// Whether `/rpc` forwards calls it can't answer to `rpc`
+    pub rpc_passthrough: bool,
+    pub token_symbol: Option<String>,
+    /// Its relay chain or parachains, when they're indexed too
+    pub links: parachains::ChainLinks,
+    /// For checking the channels watchlist entries post to
+    pub alerts: Arc<Alerts>,
+    /// Recent progress, for `/status/sync`
+    pub sync: Arc<sync::SyncTracker>,
+}
+
+/// Every chain is served under `/<name>/...`, the first one is also served at the root.
+/// The dashboard, chain list, live stream, recent errors, XCM tracking and GraphQL are shared
+/// by all chains.
+pub fn router(chains: &[(String, AppState)], stream: EventStream, limits: Limits) -> Router {
+    let names: Vec<String> = chains.iter().map(|(name, _)| name.clone()).collect();
+    let mut app = Router::new();
+    #[cfg(feature = "graphql")]
+    {
+        app = app.merge(graphql::router(chains, stream.clone()));
+    }
+    app = app
+        .route("/", get(|| async { Html(DASHBOARD) }))
+        .route("/chains", get(move || async move { Json(names) }))
+        .route("/stream", get(move || async move { stream.subscribe() }))
+        .route("/errors", get(|| async { Json(reporting::recent_errors()) }))
+        .route("/metrics", get(metrics::render))
+        .merge(xcm::router(chains));
+
+    for (i, (name, state)) in chains.iter().enumerate() {
+        if i == 0 {
+            app = app.merge(chain_router(state.clone()));
+            app = app.nest("/v1", versioned_router(state.clone()));
+        }
+        app = app.nest(&format!("/{}", name), chain_router(state.clone()));
+        app = app.nest(&format!("/v1/{}", name), versioned_router(state.clone()));
+    }
+
+    // Every request gets an x-request-id (kept if the client sent one), which is logged
+    // alongside method, path, status and latency, and echoed back in the response
+    app.layer(
+        ServiceBuilder::new()
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(request_span)
+                    .on_response(
+                        DefaultOnResponse::new()
+                            .level(Level::INFO)
+                            .latency_unit(LatencyUnit::Millis),
+                    ),
+            )
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(middleware::from_fn_with_state(limits, limits::enforce)),
+    )
+}
+
+fn request_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("-");
+
+    tracing::info_span!(
+        "request",
+        id = %request_id,
+        method = %request.method(),
+        path = %request.uri().path(),
+    )
+}
+
+fn chain_router(state: AppState) -> Router {
+    resources(&state)
+        .layer(middleware::from_fn(encoding::negotiate))
+        .route("/feed.atom", get(feed::get_feed))
+        .route("/rpc", post(rpc::handle))
+        .nest("/sidecar", sidecar::router())
+        .with_state(state)
+}
+
+// The resources again, in the `/v1` envelope
+fn versioned_router(state: AppState) -> Router {
+    resources(&state)
+        .layer(middleware::from_fn_with_state(state.clone(), envelope::wrap))
+        .layer(middleware::from_fn(encoding::negotiate))
+        .with_state(state)
+}
+
+fn resources(state: &AppState) -> Router<AppState> {
+    let mut router = Router::new()
+        .route("/blocks/head", get(get_head_block))
+        .route("/blocks/recent", get(get_recent_blocks))
+        .route("/blocks/latest", get(get_latest_blocks))
+        .route("/blocks/batch", post(get_blocks_batch))
+        .route("/blocks/:from/:to", get(get_blocks_in_range))
+        .route("/stats", get(get_stats))
+        .route("/stats/storage", get(get_storage_stats))
+        .route("/status/sync", get(sync::get_sync))
+        .route("/info", get(info::get_info))
+        .route("/calls", get(get_calls))
+        .route("/scheduler/upcoming", get(scheduler::get_upcoming))
+        .route("/referenda", get(referenda::get_referenda))
+        .route("/referenda/:index", get(referenda::get_referendum))
+        .route("/assets", get(assets::get_assets))
+        .route("/assets/:id/transfers", get(assets::get_transfers))
+        .route("/nfts/collections/:id/items", get(nfts::get_items))
+        .route("/account/:address/vesting", get(vesting::get_vesting))
+        .route("/account/:address/locks", get(locks::get_locks))
+        .route("/parachains/:id/inclusions", get(parachains::get_inclusions))
+        .route("/offences", get(offences::get_offences))
+        .route("/epochs", get(babe::get_epochs))
+        .route("/staking/election", get(election::get_election))
+        .route("/validators/offline", get(validators::get_offline))
+        .route("/validators/:address/points", get(era_points::get_points))
+        .route("/validators/:address/performance", get(validators::get_performance))
+        .route("/watchlist", get(watchlist::list))
+        .route("/webhooks", get(webhooks::list))
+        .route("/proof/:pallet/:entry", get(proof::get_proof))
+        .route("/block/:number", get(get_block_by_number))
+        .route("/block/:number/extrinsic/:index", get(get_extrinsic))
+        .route("/block/:number/events", get(get_block_events))
+        .route("/block/:number/relay", get(parachains::get_relay_block))
+        .route("/block/:number/justification", get(justifications::get_justification))
+        .route("/block/:number/parachains", get(parachains::get_parachain_blocks));
+
+    if !state.read_only {
+        router = router
+            .route("/watchlist", post(watchlist::add))
+            .route("/watchlist/:address", delete(watchlist::remove))
+            .route("/webhooks", post(webhooks::add))
+            .route("/webhooks/:id", delete(webhooks::remove))
+            .nest("/admin", admin::router(state.clone()));
+    }
+    router
+}
+
+#[derive(Deserialize)]
+struct IncludeQuery {
+    /// Comma-separated optional fields, only `raw` for now
+    include: Option<String>,
+    #[serde(default)]
+    encoding: Encoding,
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    #[default]
+    Json,
+    /// The header and extrinsics as the chain SCALE-encoded them, for checking against the hash
+    Scale,
+}
+
+impl IncludeQuery {
+    fn raw(&self) -> bool {
+        self.include
+            .as_deref()
+            .is_some_and(|include| include.split(',').any(|field| field.trim() == "raw"))
+    }
+}
+
+// Raw extrinsic bytes are always stored, but they're bulky and most clients don't want them
+fn strip_raw(extrinsics: &mut [serde_json::Value]) {
+    for extrinsic in extrinsics {
+        if let Some(extrinsic) = extrinsic.as_object_mut() {
+            extrinsic.remove("raw");
+        }
+    }
+}
+
+fn included(mut block: db::StoredBlock, include: &IncludeQuery) -> serde_json::Value {
+    if include.encoding == Encoding::Scale {
+        return scale_encoded(&block);
+    }
+    if !include.raw() {
+        strip_raw(&mut block.extrinsics);
+    }
+    serde_json::json!(block)
+}
+
+// Only the stored extrinsics, so filtered out ones are missing here too. The header is null on
+// blocks stored before headers were kept
+fn scale_encoded(block: &db::StoredBlock) -> serde_json::Value {
+    let header = block.header.as_ref().and_then(|header| header.encode(block.number));
+    let extrinsics: Vec<_> = block
+        .extrinsics
+        .iter()
+        .map(|extrinsic| serde_json::json!({ "index": extrinsic["index"], "raw": extrinsic["raw"] }))
+        .collect();
+    serde_json::json!({
+        "number": block.number,
+        "hash": block.hash,
+        "header": header.map(|header| format!("0x{}", hex::encode(header))),
+        "extrinsics": extrinsics,
+    })
+}
+
+// The shared head as it is, with where the chain is next to it
+#[derive(Serialize)]
+struct HeadBlock<'a> {
+    #[serde(flatten)]
+    block: &'a BlockInfo,
+    finalized: Option<sync::Head>,
+    best: Option<sync::Head>,
+    best_lag: Option<u32>,
+}
+
+async fn get_head_block(State(state): State<AppState>, Query(include): Query<IncludeQuery>) -> Result<Response, Error> {
+    // The block is the last one stored, which is always finalized. Next to it, where the chain
+    // is as last sampled: null if the light client couldn't say
+    let sync::Heads { finalized, best } = state.sync.heads();
+    let best_lag = match (&finalized, &best) {
+        (Some(finalized), Some(best)) => Some(best.number.saturating_sub(finalized.number)),
+        _ => None,
+    };
+    let block = state.block_info.load();
+    let head = HeadBlock { block: &block, finalized, best, best_lag };
+    let body = if include.raw() {
+        serde_json::to_vec(&head)
+    } else {
+        indexer::skipping_raw(|| serde_json::to_vec(&head))
+    };
+    let body = body.map_err(|e| e.to_string())?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], body).into_response())
+}
+
+// A stored block, or one fetched back from the archive if it was pruned
+async fn load_block(state: &AppState, block_number: u32) -> Result<Option<db::StoredBlock>, Error> {
+    match state.db.blocking(move |db| db.get_block(block_number)).await? {
+        None => match &state.archive {
+            Some(archive) => archive.fetch_block(&state.db, block_number).await.map_err(Error::Upstream),
+            None => Ok(None),
+        },
+        stored => Ok(stored),
+    }
+}
+
+async fn get_stored_block(state: &AppState, block_number: u32) -> Result<db::StoredBlock, Error> {
+    load_block(state, block_number)
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("Block #{} not found", block_number)))
+}
+
+async fn get_block_by_number(
+    State(state): State<AppState>,
+    Path(block_number): Path<u32>,
+    Query(include): Query<IncludeQuery>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let block = get_stored_block(&state, block_number).await?;
+    Ok(Json(included(block, &include)))
+}
+
+/// One extrinsic of a block, with its events, by its index in the block.
+async fn get_extrinsic(
+    State(state): State<AppState>,
+    Path((block_number, index)): Path<(u32, u32)>,
+    Query(include): Query<IncludeQuery>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let block = get_stored_block(&state, block_number).await?;
+
+    // Filtered out extrinsics leave holes, so this goes by the stored index rather than position
+    let mut extrinsic = block
+        .extrinsics
+        .into_iter()
+        .find(|extrinsic| extrinsic["index"].as_u64() == Some(index.into()))
+        .ok_or_else(|| Error::NotFound(format!("Extrinsic {}-{} isn't stored", block_number, index)))?;
+    if !include.raw() {
+        strip_raw(std::slice::from_mut(&mut extrinsic));
+    }
+    Ok(Json(extrinsic))
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    pallet: Option<String>,
+    variant: Option<String>,
+}
+
+/// The block's stored events as a flat list, each with the index of its extrinsic.
+async fn get_block_events(
+    State(state): State<AppState>,
+    Path(block_number): Path<u32>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Json<Vec<serde_json::Value>>, Error> {
+    let block = get_stored_block(&state, block_number).await?;
+
+    let mut events = Vec::new();
+    for extrinsic in block.extrinsics {
+        let Some(extrinsic_events) = extrinsic["events"].as_array() else {
+            continue;
+        };
+        for event in extrinsic_events {
+            let matches = |field: &str, wanted: &Option<String>| {
+                wanted.as_deref().is_none_or(|wanted| event[field].as_str() == Some(wanted))
+            };
+            if !matches("pallet", &query.pallet) || !matches("variant", &query.variant) {
+                continue;
+            }
+            let mut event = event.clone();
+            event["extrinsic_index"] = extrinsic["index"].clone();
+            events.push(event);
+        }
+    }
+    Ok(Json(events))
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BlockId {
+    Number(u32),
+    Hash(String),
+}
+
+/// Several blocks by number or hash in one go, in the order asked for, null where one isn't stored.
+async fn get_blocks_batch(
+    State(state): State<AppState>,
+    Query(include): Query<IncludeQuery>,
+    Json(ids): Json<Vec<BlockId>>,
+) -> Result<Json<Vec<Option<serde_json::Value>>>, Error> {
+    if ids.len() > MAX_BATCH_BLOCKS {
+        return Err(Error::BadRequest(format!("At most {} blocks per batch", MAX_BATCH_BLOCKS)));
+    }
+
+    let mut blocks = Vec::with_capacity(ids.len());
+    for id in ids {
+        let block = match id {
+            BlockId::Number(number) => load_block(&state, number).await?,
+            BlockId::Hash(hash) => state.db.blocking(move |db| db.get_block_by_hash(&hash)).await?,
+        };
+        blocks.push(block.map(|block| included(block, &include)));
+    }
+    Ok(Json(blocks))
+}
+
+#[derive(Deserialize)]
+struct RangeQuery {
+    limit: Option<u32>,
+    #[serde(default)]
+    full: bool,
+}
+
+fn summary(block: &db::StoredBlock) -> serde_json::Value {
+    let events: usize = block
+        .extrinsics
+        .iter()
+        .map(|extrinsic| extrinsic["events"].as_array().map_or(0, |events| events.len()))
+        .sum();
+    serde_json::json!({
+        "number": block.number,
+        "hash": block.hash,
+        "timestamp": block.timestamp,
+        "extrinsics_count": block.extrinsics.len(),
+        "events_count": events,
+    })
+}
+
+/// Stored blocks from `from` to `to` (inclusive), newest first, as summaries unless `?full=true`.
+/// When the limit cuts the range short, `next_to` is the `to` of the next page.
+async fn get_blocks_in_range(
+    State(state): State<AppState>,
+    Path((from, to)): Path<(u32, u32)>,
+    Query(query): Query<RangeQuery>,
+    Query(include): Query<IncludeQuery>,
+) -> Result<Json<serde_json::Value>, Error> {
+    if from > to {
+        return Err(Error::BadRequest(format!("from ({}) is after to ({})", from, to)));
+    }
+    let max = if query.full { MAX_RECENT_BLOCKS } else { MAX_RANGE_SUMMARIES };
+    let limit = query.limit.unwrap_or(100).min(max);
+
+    let cursor = db::Cursor { before: to.checked_add(1), after: from.checked_sub(1) };
+    let blocks = state.db.blocking(move |db| db.get_blocks_page(cursor, limit)).await?;
+    let next_to = match blocks.last() {
+        Some(oldest) if blocks.len() as u32 == limit && oldest.number > from => Some(oldest.number - 1),
+        _ => None,
+    };
+    let blocks: Vec<_> = if query.full {
+        blocks.into_iter().map(|b| included(b, &include)).collect()
+    } else {
+        blocks.iter().map(summary).collect()
+    };
+    Ok(Json(serde_json::json!({
+        "from": from,
+        "to": to,
+        "blocks": blocks,
+        "next_to": next_to,
+    })))
+}
+
+#[derive(Deserialize)]
+struct LatestQuery {
+    n: Option<u32>,
+}
+
+/// Summaries of the `n` newest stored blocks, newest first.
+async fn get_latest_blocks(
+    State(state): State<AppState>,
+    Query(query): Query<LatestQuery>,
+) -> Result<Json<Vec<serde_json::Value>>, Error> {
+    let n = query.n.unwrap_or(10).min(MAX_RANGE_SUMMARIES);
+    let blocks = state.db.blocking(move |db| db.get_blocks_page(db::Cursor::default(), n)).await?;
+    Ok(Json(blocks.iter().map(summary).collect()))
+}
+
+#[derive(Deserialize)]
+struct RecentQuery {
+    limit: Option<u32>,
+}
+
+/// The latest blocks, or a page of them with `?before=`/`?after=` block numbers.
+async fn get_recent_blocks(
+    State(state): State<AppState>,
+    Query(query): Query<RecentQuery>,
+    Query(cursor): Query<db::Cursor>,
+    Query(include): Query<IncludeQuery>,
+) -> Result<Json<Vec<serde_json::Value>>, Error> {
+    let limit = query.limit.unwrap_or(20).min(MAX_RECENT_BLOCKS);
+    let blocks = state.db.blocking(move |db| db.get_blocks_page(cursor, limit)).await?;
+    Ok(Json(blocks.into_iter().map(|b| included(b, &include)).collect()))
+}
+
+async fn get_stats(State(state): State<AppState>) -> Result<Json<serde_json::Value>, Error> {
+    let stats = state.db.blocking(|db| -> Result<serde_json::Value, rusqlite::Error> {
+        let counters = db.counters()?;
+        let count = |name: &str| counters.get(name).copied().unwrap_or_default();
+        let mut pallets = serde_json::Map::new();
+        for (name, value) in &counters {
+            let Some((kind, pallet)) = name.split_once(':') else {
+                continue;
+            };
+            let entry = pallets
+                .entry(pallet)
+                .or_insert_with(|| serde_json::json!({ "extrinsics": 0, "events": 0 }));
+            entry[kind] = (*value).into();
+        }
+        Ok(serde_json::json!({
+            "latest_block": db.get_latest_block_number()?,
+            "stored_blocks": count("blocks"),
+            "extrinsics": count("extrinsics"),
+            "events": count("events"),
+            "pallets": pallets,
+            "used_bytes": db.used_bytes()?,
+        }))
+    });
+
+    Ok(Json(stats.await?))
+}
+
+/// Sizes, row counts and growth, for planning retention.
+async fn get_storage_stats(State(state): State<AppState>) -> Result<Json<db::StorageStats>, Error> {
+    Ok(Json(state.db.blocking(|db| db.storage_stats()).await?))
+}
+
+#[derive(Deserialize)]
+struct CallsQuery {
+    pallet: String,
+    call: Option<String>,
+    limit: Option<usize>,
+}
+
+/// Calls to a pallet (and call) in recent blocks, including ones nested in sudo, proxy, batch...
+async fn get_calls(
+    State(state): State<AppState>,
+    Query(query): Query<CallsQuery>,
+) -> Result<Json<Vec<db::StoredCall>>, Error> {
+    let limit = query.limit.unwrap_or(100).min(MAX_CALLS);
+    let calls = state
+        .db
+        .blocking(move |db| {
+            db.get_latest_block_number().and_then(|latest| match latest {
+                Some(latest) => db.find_calls(
+                    &query.pallet,
+                    query.call.as_deref(),
+                    latest.saturating_sub(CALLS_SCAN_BLOCKS),
+                    latest,
+                    limit,
+                ),
+                None => Ok(vec![]),
+            })
+        })
+        .await?;
+    Ok(Json(calls))
+}
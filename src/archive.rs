@@ -0,0 +1,152 @@
+//! Archival of pruned blocks to S3-compatible storage. Before pruning deletes a batch of
+//! old blocks they're uploaded as one gzipped NDJSON chunk (one stored block per line),
+//! and the chunk's key is kept in the database so `/block/:number` can still serve them.
+
+#[cfg(not(feature = "s3"))]
+use std::sync::Arc;
+
+#[cfg(feature = "s3")]
+pub use with_s3::Archive;
+
+/// Without the `s3` feature there's no way to build one, so an `Option<Archive>` is always None.
+#[cfg(not(feature = "s3"))]
+pub enum Archive {}
+
+#[cfg(not(feature = "s3"))]
+impl Archive {
+    pub async fn prune(
+        &self,
+        _chain: &str,
+        _db: &Arc<crate::db::Database>,
+        _batch: u32,
+        _keep_recent: u32,
+    ) -> Result<usize, String> {
+        match *self {}
+    }
+
+    pub async fn fetch_block(
+        &self,
+        _db: &Arc<crate::db::Database>,
+        _block_number: u32,
+    ) -> Result<Option<crate::db::StoredBlock>, String> {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "s3")]
+mod with_s3 {
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+    use object_store::aws::{AmazonS3, AmazonS3Builder};
+    use object_store::{path::Path, ObjectStore, PutPayload};
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::Arc;
+
+    use crate::config::ArchiveConfig;
+    use crate::db::{Database, StoredBlock};
+
+    pub struct Archive {
+        store: AmazonS3,
+        prefix: String,
+    }
+
+    impl Archive {
+        pub fn connect(config: &ArchiveConfig) -> Result<Self, String> {
+            let mut builder = AmazonS3Builder::from_env()
+                .with_bucket_name(&config.bucket)
+                .with_region(&config.region);
+            if let Some(endpoint) = &config.endpoint {
+                builder = builder
+                    .with_endpoint(endpoint)
+                    .with_allow_http(endpoint.starts_with("http://"));
+            }
+            if let Some(key) = &config.access_key_id {
+                builder = builder.with_access_key_id(key);
+            }
+            if let Some(secret) = &config.secret_access_key {
+                builder = builder.with_secret_access_key(secret);
+            }
+
+            let store = builder
+                .build()
+                .map_err(|e| format!("invalid [archive] config: {}", e))?;
+            tracing::info!(bucket = %config.bucket, "Archiving pruned blocks to S3");
+            Ok(Archive {
+                store,
+                prefix: config.prefix.clone(),
+            })
+        }
+
+        /// Uploads up to `batch` of the oldest blocks (sparing the newest `keep_recent`) as
+        /// one chunk, then deletes them. Nothing is deleted if the upload fails.
+        pub async fn prune(
+            &self,
+            chain: &str,
+            db: &Arc<Database>,
+            batch: u32,
+            keep_recent: u32,
+        ) -> Result<usize, String> {
+            let blocks = db
+                .blocking(move |db| db.oldest_raw_blocks(batch, keep_recent))
+                .await
+                .map_err(|e| e.to_string())?;
+            let (Some((first, _)), Some((last, _))) = (blocks.first(), blocks.last()) else {
+                return Ok(0);
+            };
+            let (first, last) = (*first, *last);
+
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            for (_, data) in &blocks {
+                encoder
+                    .write_all(data.as_bytes())
+                    .and_then(|_| encoder.write_all(b"\n"))
+                    .map_err(|e| e.to_string())?;
+            }
+            let chunk = encoder.finish().map_err(|e| e.to_string())?;
+
+            let key = format!("{}/{}/{:010}-{:010}.ndjson.gz", self.prefix, chain, first, last);
+            self.store
+                .put(&Path::from(key.as_str()), PutPayload::from(chunk))
+                .await
+                .map_err(|e| format!("failed to upload {}: {}", key, e))?;
+            tracing::debug!(chain, first, last, key, "Archived blocks");
+
+            db.blocking(move |db| db.archive_and_delete(first, last, &key))
+                .await
+                .map_err(|e| e.to_string())
+        }
+
+        /// Looks `block_number` up in the archive, if it was archived at all.
+        pub async fn fetch_block(
+            &self,
+            db: &Arc<Database>,
+            block_number: u32,
+        ) -> Result<Option<StoredBlock>, String> {
+            let Some(key) = db
+                .blocking(move |db| db.archived_chunk(block_number))
+                .await
+                .map_err(|e| e.to_string())?
+            else {
+                return Ok(None);
+            };
+
+            let chunk = self
+                .store
+                .get(&Path::from(key.as_str()))
+                .await
+                .map_err(|e| format!("failed to download {}: {}", key, e))?
+                .bytes()
+                .await
+                .map_err(|e| format!("failed to download {}: {}", key, e))?;
+
+            for line in BufReader::new(GzDecoder::new(&chunk[..])).lines() {
+                let line = line.map_err(|e| format!("corrupt chunk {}: {}", key, e))?;
+                let block: StoredBlock = serde_json::from_str(&line)
+                    .map_err(|e| format!("corrupt chunk {}: {}", key, e))?;
+                if block.number == block_number {
+                    return Ok(Some(block));
+                }
+            }
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Assets pallet (Asset Hub) tracking: assets with their metadata in the `assets` table, and
+//! their mints, burns and transfers in `asset_transfers`, served on `/assets`.
+//!
+//! Assets created before indexing started get their metadata from storage the first time one
+//! of their events is seen. Chains without the Assets pallet simply have nothing to record.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use subxt::dynamic;
+use subxt::events::{EventDetails, Events, Phase};
+use subxt::ext::scale_value::{At, Composite, Value};
+use subxt::utils::H256;
+use subxt::{OnlineClient, PolkadotConfig};
+
+use crate::api::AppState;
+use crate::db::{self, Database};
+use crate::error::Error;
+use crate::decode;
+use crate::indexer::TrackerUpdates;
+use crate::reporting;
+
+const MAX_ASSETS: u32 = 1000;
+const MAX_TRANSFERS: u32 = 1000;
+
+const FAILURE: &str = "Failed to record asset event";
+
+fn text(value: Option<&Value<u32>>) -> Option<String> {
+    let bytes = decode::bytes(value?)?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn decimals(value: Option<&Value<u32>>) -> Option<u8> {
+    value?.as_u128().and_then(|n| u8::try_from(n).ok())
+}
+
+// Asset metadata from storage, for assets we haven't seen created
+async fn fetch_metadata(
+    updates: &mut TrackerUpdates,
+    api: &OnlineClient<PolkadotConfig>,
+    block_number: u32,
+    at: H256,
+    asset_id: u32,
+) -> Result<(), String> {
+    let key = dynamic::Value::u128(asset_id.into());
+    let stored = api
+        .storage()
+        .at(at)
+        .fetch(&dynamic::storage("Assets", "Metadata", vec![key]))
+        .await
+        .map_err(|e| e.to_string())?;
+    let metadata = stored.map(|m| m.to_value()).transpose().map_err(|e| e.to_string())?;
+
+    // Stored even when there's none, so it isn't fetched again
+    let metadata = metadata.as_ref();
+    let name = text(metadata.and_then(|m| m.at("name")));
+    let symbol = text(metadata.and_then(|m| m.at("symbol")));
+    let decimals = decimals(metadata.and_then(|m| m.at("decimals")));
+    updates.push(format!("Failed to record metadata of asset {}", asset_id), move |db| {
+        db.set_asset_metadata(asset_id, name.as_deref(), symbol.as_deref(), decimals, block_number)
+    });
+    Ok(())
+}
+
+fn record_event(
+    updates: &mut TrackerUpdates,
+    decode: &decode::Context,
+    block_number: u32,
+    event: &EventDetails<PolkadotConfig>,
+    asset_id: u32,
+    fields: &Composite<u32>,
+) {
+    let account = |name| fields.at(name).and_then(|v| decode.account(v));
+    let amount = |name| fields.at(name).and_then(|v| v.as_u128());
+    let event_index = event.index();
+    let extrinsic_index = match event.phase() {
+        Phase::ApplyExtrinsic(i) => Some(i),
+        _ => None,
+    };
+
+    let (kind, from, to, amount) = match event.variant_name() {
+        "Created" | "ForceCreated" => {
+            let owner = account("owner");
+            updates.push(FAILURE, move |db| db.record_asset_created(asset_id, block_number, owner.as_deref()));
+            return;
+        }
+        "MetadataSet" => {
+            let (name, symbol) = (text(fields.at("name")), text(fields.at("symbol")));
+            let decimals = decimals(fields.at("decimals"));
+            updates.push(FAILURE, move |db| {
+                db.set_asset_metadata(asset_id, name.as_deref(), symbol.as_deref(), decimals, block_number)
+            });
+            return;
+        }
+        "MetadataCleared" => {
+            updates.push(FAILURE, move |db| db.set_asset_metadata(asset_id, None, None, None, block_number));
+            return;
+        }
+        "Destroyed" => {
+            updates.push(FAILURE, move |db| db.set_asset_destroyed(asset_id, block_number));
+            return;
+        }
+        // Older runtimes called the amount `total_supply`
+        "Issued" => ("mint", None, account("owner"), amount("amount").or_else(|| amount("total_supply"))),
+        "Burned" => ("burn", account("owner"), None, amount("balance")),
+        "Transferred" => ("transfer", account("from"), account("to"), amount("amount")),
+        "TransferredApproved" => ("transfer", account("owner"), account("destination"), amount("amount")),
+        _ => return,
+    };
+    let Some(amount) = amount else {
+        return;
+    };
+    updates.push(FAILURE, move |db| {
+        db.record_asset_transfer(
+            block_number,
+            event_index,
+            extrinsic_index,
+            asset_id,
+            kind,
+            from.as_deref(),
+            to.as_deref(),
+            amount,
+        )
+    });
+}
+
+/// Records the block's Assets pallet events.
+#[allow(clippy::too_many_arguments)]
+pub async fn record(
+    db: &Arc<Database>,
+    updates: &mut TrackerUpdates,
+    chain: &str,
+    api: &OnlineClient<PolkadotConfig>,
+    decode: &decode::Context,
+    block_number: u32,
+    block_hash: H256,
+    events: &Events<PolkadotConfig>,
+) {
+    // Assets seen in this block, whose writes are still held back
+    let mut seen = HashSet::new();
+    for event in events.iter().flatten() {
+        if event.pallet_name() != "Assets" {
+            continue;
+        }
+        let Ok(fields) = event.field_values() else {
+            continue;
+        };
+        let Some(asset_id) = fields.at("asset_id").and_then(|v| v.as_u128()).and_then(|n| u32::try_from(n).ok())
+        else {
+            continue;
+        };
+
+        // An asset created in a block still being processed gets its metadata fetched here as
+        // well, which is only wasted work since the writes land in block order
+        let created = matches!(event.variant_name(), "Created" | "ForceCreated");
+        if seen.insert(asset_id) && !created {
+            match db.blocking(move |db| db.has_asset(asset_id)).await {
+                Ok(false) => {
+                    if let Err(e) = fetch_metadata(updates, api, block_number, block_hash, asset_id).await {
+                        reporting::block_error(
+                            chain,
+                            block_number,
+                            &format!("Failed to fetch metadata of asset {}: {}", asset_id, e),
+                        );
+                    }
+                }
+                Ok(true) => {}
+                Err(e) => {
+                    reporting::block_error(chain, block_number, &format!("Failed to look up asset {}: {}", asset_id, e))
+                }
+            }
+        }
+
+        record_event(updates, decode, block_number, &event, asset_id, &fields);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AssetsQuery {
+    after: Option<u32>,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+pub struct TransfersQuery {
+    limit: Option<u32>,
+}
+
+/// Known assets by id, paged with `?after=<last id>`.
+pub async fn get_assets(
+    State(state): State<AppState>,
+    Query(query): Query<AssetsQuery>,
+) -> Result<Json<Vec<db::Asset>>, Error> {
+    let limit = query.limit.unwrap_or(100).min(MAX_ASSETS);
+    Ok(Json(state.db.blocking(move |db| db.get_assets(query.after, limit)).await?))
+}
+
+/// An asset's latest mints, burns and transfers, newest first.
+pub async fn get_transfers(
+    State(state): State<AppState>,
+    Path(asset_id): Path<u32>,
+    Query(query): Query<TransfersQuery>,
+) -> Result<Json<Vec<db::AssetTransfer>>, Error> {
+    let limit = query.limit.unwrap_or(100).min(MAX_TRANSFERS);
+    Ok(Json(state.db.blocking(move |db| db.get_asset_transfers(asset_id, limit)).await?))
+}
@@ -0,0 +1,177 @@
+//! Block production from the header digests: the slot (and BABE authority and slot kind) of
+//! every block in `block_slots`, and BABE epochs with their randomness in `epochs`, served on
+//! `/epochs`. Aura chains only get their slots.
+//!
+//! Each epoch's validator set is stored from `Session::Validators` when it starts, so the
+//! blocks after that get their author's account (BABE authority indices follow that order).
+
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use subxt::config::substrate::DigestItem;
+use subxt::dynamic;
+use subxt::ext::codec::Decode;
+use subxt::ext::scale_value::{Value, ValueDef};
+use subxt::utils::H256;
+use subxt::{OnlineClient, PolkadotConfig};
+
+use crate::api::AppState;
+use crate::db;
+use crate::error::Error;
+use crate::decode;
+use crate::indexer::TrackerUpdates;
+use crate::reporting;
+
+const MAX_EPOCHS: u32 = 200;
+
+const BABE_ENGINE_ID: [u8; 4] = *b"BABE";
+const AURA_ENGINE_ID: [u8; 4] = *b"aura";
+
+// BABE's `PreDigest` starts with its kind, then the authority index and slot
+fn babe_pre_digest(data: &[u8]) -> Option<(&'static str, u32, u64)> {
+    let (kind, mut rest) = data.split_first()?;
+    let kind = match kind {
+        1 => "primary",
+        2 => "secondary_plain",
+        3 => "secondary_vrf",
+        _ => return None,
+    };
+    let (authority_index, slot) = <(u32, u64)>::decode(&mut rest).ok()?;
+    Some((kind, authority_index, slot))
+}
+
+// `ConsensusLog::NextEpochData` (variant 1): the next epoch's authorities and randomness
+fn next_epoch_data(data: &[u8]) -> Option<(u32, [u8; 32])> {
+    let (1, mut rest) = data.split_first()? else {
+        return None;
+    };
+    let (authorities, randomness) = <(Vec<([u8; 32], u64)>, [u8; 32])>::decode(&mut rest).ok()?;
+    Some((authorities.len() as u32, randomness))
+}
+
+async fn session_validators(api: &OnlineClient<PolkadotConfig>, at: H256) -> Result<Vec<String>, subxt::Error> {
+    let validators = api
+        .storage()
+        .at(at)
+        .fetch(&dynamic::storage("Session", "Validators", Vec::<dynamic::Value>::new()))
+        .await?;
+    Ok(match validators.map(|v| v.to_value()).transpose()? {
+        Some(Value { value: ValueDef::Composite(validators), .. }) => validators
+            .values()
+            .filter_map(decode::bytes)
+            .map(|validator| format!("0x{}", hex::encode(validator)))
+            .collect(),
+        _ => vec![],
+    })
+}
+
+async fn epoch_index(api: &OnlineClient<PolkadotConfig>, at: H256) -> Result<Option<u64>, subxt::Error> {
+    let index = api
+        .storage()
+        .at(at)
+        .fetch(&dynamic::storage("Babe", "EpochIndex", Vec::<dynamic::Value>::new()))
+        .await?;
+    Ok(match index {
+        Some(index) => index.to_value()?.as_u128().map(|n| n as u64),
+        None => None,
+    })
+}
+
+/// Records the block's slot, and the epoch it starts if it's the first of one.
+pub async fn record(
+    updates: &mut TrackerUpdates,
+    chain: &str,
+    api: &OnlineClient<PolkadotConfig>,
+    block_number: u32,
+    block_hash: H256,
+    digest: &[DigestItem],
+) {
+    let mut slot = None;
+    let mut next_epoch = None;
+    for item in digest {
+        match item {
+            DigestItem::PreRuntime(engine, data) if *engine == BABE_ENGINE_ID => {
+                if let Some((kind, authority_index, babe_slot)) = babe_pre_digest(data) {
+                    slot = Some((babe_slot, Some(authority_index), kind));
+                }
+            }
+            DigestItem::PreRuntime(engine, data) if *engine == AURA_ENGINE_ID => {
+                if let Ok(aura_slot) = u64::decode(&mut &data[..]) {
+                    slot = Some((aura_slot, None, "aura"));
+                }
+            }
+            DigestItem::Consensus(engine, data) if *engine == BABE_ENGINE_ID => {
+                next_epoch = next_epoch.or(next_epoch_data(data));
+            }
+            _ => {}
+        }
+    }
+
+    // The first block of an epoch announces the one after it
+    if let Some(next_epoch) = next_epoch {
+        record_epoch(updates, chain, api, block_number, block_hash, slot.map(|(slot, _, _)| slot), next_epoch).await;
+    }
+
+    // The author is looked up when it's written, after the epoch's validators are
+    if let Some((slot, authority_index, kind)) = slot {
+        let chain = chain.to_string();
+        updates.push("Failed to record block slot", move |db| {
+            let author = match authority_index {
+                Some(index) => db.epoch_validator(block_number, index).unwrap_or_else(|e| {
+                    reporting::block_error(&chain, block_number, &format!("Failed to look up the block author: {}", e));
+                    None
+                }),
+                None => None,
+            };
+            db.record_block_slot(block_number, slot, authority_index, kind, author.as_deref())
+        });
+    }
+}
+
+async fn record_epoch(
+    updates: &mut TrackerUpdates,
+    chain: &str,
+    api: &OnlineClient<PolkadotConfig>,
+    block_number: u32,
+    block_hash: H256,
+    slot: Option<u64>,
+    (authorities, randomness): (u32, [u8; 32]),
+) {
+    let index = match epoch_index(api, block_hash).await {
+        Ok(Some(index)) => index,
+        Ok(None) => return,
+        Err(e) => {
+            reporting::block_error(chain, block_number, &format!("Failed to read the epoch index: {}", e));
+            return;
+        }
+    };
+    let randomness = format!("0x{}", hex::encode(randomness));
+    updates.push("Failed to record epoch", move |db| {
+        db.record_epoch_start(index, block_number, slot)?;
+        db.record_epoch_data(index + 1, authorities, &randomness)
+    });
+
+    match session_validators(api, block_hash).await {
+        Ok(validators) if validators.is_empty() => {}
+        Ok(validators) => {
+            updates.push("Failed to record epoch validators", move |db| db.record_epoch_validators(index, &validators))
+        }
+        Err(e) => reporting::block_error(chain, block_number, &format!("Failed to read epoch validators: {}", e)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EpochsQuery {
+    limit: Option<u32>,
+}
+
+/// The latest epochs, newest first.
+pub async fn get_epochs(
+    State(state): State<AppState>,
+    Query(query): Query<EpochsQuery>,
+) -> Result<Json<Vec<db::Epoch>>, Error> {
+    let limit = query.limit.unwrap_or(20).min(MAX_EPOCHS);
+    Ok(Json(state.db.blocking(move |db| db.get_epochs(limit)).await?))
+}
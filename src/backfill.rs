@@ -0,0 +1,313 @@
+//! Fetches the blocks from before smolcar started following a chain, as set in `[backfill]`.
+//! Blocks already stored are skipped, the rest are fetched `concurrency` at a time (and at
+//! most `blocks_per_second`) so the light client and its peers aren't flooded, and go through
+//! the same processing as followed blocks, without alerts, webhooks or sinks. Batches wait
+//! while following new blocks lags, and `--full-archive` checkpoints each one in a cursor,
+//! which stops short of the first block that failed so a restart fetches it again.
+//!
+//! Old blocks come from full nodes through the legacy `chain_getBlockHash`/`chain_getBlock`
+//! calls, which chainHead can't do. Blocks no peer serves are reported and left as gaps for
+//! `smolcar check` to list.
+
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use subxt::backend::legacy::LegacyRpcMethods;
+use subxt::{OnlineClient, PolkadotConfig};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::config::BackfillConfig;
+use crate::db::{self, Database};
+use crate::indexer::{ProcessedBlock, Processor};
+use crate::reporting;
+use crate::sync::SyncTracker;
+
+/// `--from-block`: where a chain with nothing stored yet starts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FromBlock {
+    Genesis,
+    /// Following new blocks only, the default
+    Head,
+    Number(u32),
+}
+
+impl FromStr for FromBlock {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "genesis" => Ok(FromBlock::Genesis),
+            "head" => Ok(FromBlock::Head),
+            number => number
+                .parse()
+                .map(FromBlock::Number)
+                .map_err(|_| format!("expected a block number, genesis or head, got {:?}", s)),
+        }
+    }
+}
+
+impl FromBlock {
+    /// The first block to backfill, None to only follow.
+    pub fn first_block(self) -> Option<u32> {
+        match self {
+            FromBlock::Genesis => Some(0),
+            FromBlock::Head => None,
+            FromBlock::Number(number) => Some(number),
+        }
+    }
+}
+
+pub struct Backfill {
+    pub config: BackfillConfig,
+    /// Over the legacy backend, which can fetch any block by hash
+    pub api: OnlineClient<PolkadotConfig>,
+    pub rpc: LegacyRpcMethods<PolkadotConfig>,
+    /// Cursor the progress is kept in, to resume from after a restart
+    pub checkpoint: Option<String>,
+    /// Drops the checkpoint once done without gaps, for ranges that don't grow with the chain
+    /// (catching up)
+    pub clear_checkpoint: bool,
+    /// Batches wait while following new blocks lags behind, so backfilling never holds it up
+    pub sync: Arc<SyncTracker>,
+}
+
+#[derive(Default)]
+struct Progress {
+    /// Done before a restart, going by the checkpoint
+    resumed: u32,
+    done: u32,
+    stored: u32,
+    failed: u32,
+    /// The checkpoint never moves past this, so a restart fetches it again
+    first_failed: Option<u32>,
+}
+
+impl Progress {
+    fn fail(&mut self, number: u32) {
+        self.failed += 1;
+        self.first_failed.get_or_insert(number);
+    }
+}
+
+// e.g. "2h05m09s"
+fn format_eta(seconds: u64) -> String {
+    format!("{}h{:02}m{:02}s", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+async fn fetch(
+    api: OnlineClient<PolkadotConfig>,
+    rpc: LegacyRpcMethods<PolkadotConfig>,
+    processor: Arc<Processor>,
+    number: u32,
+) -> Result<Option<ProcessedBlock>, String> {
+    let hash = rpc
+        .chain_get_block_hash(Some(number.into()))
+        .await
+        .map_err(|e| format!("Failed to fetch block hash: {}", e))?
+        .ok_or("No peer knows the block hash")?;
+    let block = api.blocks().at(hash).await.map_err(|e| format!("Failed to fetch block: {}", e))?;
+    Ok(processor.process(block).await)
+}
+
+impl Backfill {
+    /// Backfills up to `to`, or the block before the finalized head `follow_api` starts at.
+    pub async fn run(
+        self,
+        chain: String,
+        follow_api: OnlineClient<PolkadotConfig>,
+        db: Arc<Database>,
+        processor: Arc<Processor>,
+    ) {
+        let Backfill { config, api, rpc, checkpoint, clear_checkpoint, sync } = self;
+        let to = match config.to {
+            Some(to) => to,
+            None => match follow_api.blocks().at_latest().await {
+                Ok(head) => head.number().saturating_sub(1),
+                Err(e) => {
+                    reporting::error(&chain, &format!("Backfill failed to find the finalized head: {}", e));
+                    return;
+                }
+            },
+        };
+        let cleared = checkpoint.clone().filter(|_| clear_checkpoint);
+        if config.from > to {
+            clear(&chain, &db, cleared).await;
+            return;
+        }
+
+        let total = to - config.from + 1;
+        let concurrency = config.concurrency.max(1);
+        let batch_size = config.batch_size.max(1);
+        let mut limiter = config.blocks_per_second.filter(|rate| *rate > 0.0).map(|rate| {
+            let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            interval
+        });
+        tracing::info!(chain = %chain, from = config.from, to, concurrency, "Backfilling blocks");
+
+        let started = Instant::now();
+        let mut progress = Progress::default();
+        let mut first = config.from;
+        if let Some(name) = checkpoint.clone() {
+            match db.blocking(move |db| db.get_cursor(&name)).await {
+                Ok(Some((last, _))) if last >= to => {
+                    tracing::info!(chain = %chain, checkpoint = last, "Backfill was done already");
+                    clear(&chain, &db, cleared).await;
+                    return;
+                }
+                Ok(Some((last, _))) => {
+                    first = first.max(last + 1);
+                    progress.resumed = first - config.from;
+                    progress.done = progress.resumed;
+                    tracing::info!(chain = %chain, from = first, "Resuming backfill from its checkpoint");
+                }
+                Ok(None) => {}
+                Err(e) => reporting::error(&chain, &format!("Backfill failed to read its checkpoint: {}", e)),
+            }
+        }
+        sync.backfill_pending(to - first + 1);
+        loop {
+            if sync.is_lagging() {
+                tracing::info!(chain = %chain, "Following new blocks is lagging, backfill waits for it");
+                while sync.is_lagging() {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+            }
+
+            // Pruning can run meanwhile, and what it deleted isn't fetched again
+            match db.blocking(|db| db.get_cursor(db::PRUNED)).await {
+                Ok(Some((pruned, _))) if pruned >= first => {
+                    let skipped = pruned.min(to) - first + 1;
+                    progress.done += skipped;
+                    sync.backfilled(skipped);
+                    tracing::info!(chain = %chain, from = first, to = pruned.min(to), "Skipping blocks that were pruned");
+                    if pruned >= to {
+                        break;
+                    }
+                    first = pruned + 1;
+                }
+                Ok(_) => {}
+                Err(e) => reporting::error(&chain, &format!("Backfill failed to read how far pruning got: {}", e)),
+            }
+
+            let last = first.saturating_add(batch_size - 1).min(to);
+            let stored: HashSet<u32> = match db.blocking(move |db| db.stored_block_numbers(first, last)).await {
+                Ok(stored) => stored.into_iter().collect(),
+                Err(e) => {
+                    reporting::error(&chain, &format!("Backfill failed to look up stored blocks: {}", e));
+                    HashSet::new()
+                }
+            };
+
+            // Fetched `concurrency` at a time, but stored in order, which is when the trackers write.
+            // Newer blocks are usually stored already, and the trackers' state keeps what they wrote
+            let mut in_flight: VecDeque<(u32, JoinHandle<_>)> = VecDeque::with_capacity(concurrency);
+            for number in (first..=last).filter(|number| !stored.contains(number)) {
+                if let Some(limiter) = &mut limiter {
+                    limiter.tick().await;
+                }
+                let task = fetch(api.clone(), rpc.clone(), processor.clone(), number);
+                in_flight.push_back((number, tokio::spawn(task)));
+                if in_flight.len() >= concurrency {
+                    if let Some((number, task)) = in_flight.pop_front() {
+                        finish(&chain, &db, number, task, &mut progress).await;
+                    }
+                }
+            }
+            while let Some((number, task)) = in_flight.pop_front() {
+                finish(&chain, &db, number, task, &mut progress).await;
+            }
+
+            progress.done += last - first + 1;
+            sync.backfilled(last - first + 1);
+            // Up to the block before the first one that failed, which is fetched again from there
+            let reached = match progress.first_failed {
+                Some(failed) => failed.checked_sub(1),
+                None => Some(last),
+            };
+            if let (Some(name), Some(reached)) = (checkpoint.clone(), reached) {
+                let saved = db.blocking(move |db| {
+                    let hash = db.get_block_hash(reached)?.unwrap_or_default();
+                    db.set_cursor(&name, reached, &hash)
+                });
+                if let Err(e) = saved.await {
+                    reporting::error(&chain, &format!("Backfill failed to save its checkpoint: {}", e));
+                }
+            }
+            let elapsed = started.elapsed().as_secs_f64();
+            let rate = (progress.done - progress.resumed) as f64 / elapsed.max(0.001);
+            tracing::info!(
+                chain = %chain,
+                batch = %format!("{}-{}", first, last),
+                done = progress.done,
+                total,
+                percent = %format!("{:.1}", progress.done as f64 * 100.0 / total as f64),
+                stored = progress.stored,
+                failed = progress.failed,
+                rate = %format!("{:.1}/s", rate),
+                eta = %format_eta(((total - progress.done) as f64 / rate) as u64),
+                "Backfill progress"
+            );
+
+            if last >= to {
+                break;
+            }
+            first = last + 1;
+        }
+        tracing::info!(
+            chain = %chain,
+            stored = progress.stored,
+            failed = progress.failed,
+            took = %format_eta(started.elapsed().as_secs()),
+            "Backfill done"
+        );
+        match progress.first_failed {
+            Some(failed) if checkpoint.is_some() => {
+                tracing::warn!(chain = %chain, from = failed, "Backfill left gaps, the next start fetches them again");
+            }
+            _ => clear(&chain, &db, cleared).await,
+        }
+    }
+}
+
+async fn clear(chain: &str, db: &Arc<Database>, checkpoint: Option<String>) {
+    let Some(name) = checkpoint else {
+        return;
+    };
+    if let Err(e) = db.blocking(move |db| db.delete_cursor(&name)).await {
+        reporting::error(chain, &format!("Backfill failed to clear its checkpoint: {}", e));
+    }
+}
+
+async fn finish(
+    chain: &str,
+    db: &Arc<Database>,
+    number: u32,
+    task: JoinHandle<Result<Option<ProcessedBlock>, String>>,
+    progress: &mut Progress,
+) {
+    let result = match task.await {
+        Ok(Ok(Some(block))) => {
+            let (timestamp, chain) = (chrono::Utc::now().timestamp(), chain.to_string());
+            db.blocking(move |db| block.write(db, &chain, timestamp).0).await.map_err(|e| e.to_string())
+        }
+        // Stored meanwhile, or processing failed and reported it already
+        Ok(Ok(None)) => {
+            if !matches!(db.blocking(move |db| db.get_block_hash(number)).await, Ok(Some(_))) {
+                progress.fail(number);
+            }
+            return;
+        }
+        Ok(Err(e)) => Err(e),
+        Err(e) => Err(format!("Backfill task failed: {}", e)),
+    };
+    match result {
+        Ok(()) => progress.stored += 1,
+        Err(e) => {
+            progress.fail(number);
+            reporting::block_error(chain, number, &e);
+        }
+    }
+}
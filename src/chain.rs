@@ -0,0 +1,279 @@
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use subxt::ext::codec::{Compact, Decode};
+
+use crate::config::NetworkConfig;
+
+const POLKADOT_SPEC: &str = include_str!("../configs/polkadot.json");
+
+// Chain specs shipped inside the binary, usable with just `--chain <name>`
+const BUNDLED: &[(&str, &str)] = &[("polkadot", POLKADOT_SPEC)];
+
+/// A `--chain` argument: either a bundled chain name or `name=path/to/spec.json`
+#[derive(Clone, Debug)]
+pub struct ChainArg {
+    pub name: String,
+    pub spec_path: Option<PathBuf>,
+}
+
+// Names a chain can't take: the data directory's own files (`smolcar.db`, the legacy
+// `blocks.db`), and the first segment of routes the API serves next to the chains' `/<name>/`
+const RESERVED_NAMES: &[&str] = &[
+    "smolcar", "blocks", "specs", "v1", "chains", "stream", "errors", "metrics", "xcm", "graphql", "admin",
+    "rpc", "sidecar", "block", "stats", "status", "info", "calls", "scheduler", "referenda", "assets", "nfts",
+    "account", "parachains", "offences", "epochs", "staking", "validators", "watchlist", "webhooks", "proof",
+];
+
+impl FromStr for ChainArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, spec_path) = match s.split_once('=') {
+            Some((name, path)) => (name, Some(PathBuf::from(path))),
+            None => (s, None),
+        };
+
+        // The name ends up in file names and URL paths, keep it boring
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "invalid chain name {:?}, use lowercase letters, digits, '-' and '_'",
+                name
+            ));
+        }
+
+        if RESERVED_NAMES.contains(&name) {
+            return Err(format!("chain name {:?} is reserved, it clashes with smolcar's own files or routes", name));
+        }
+
+        Ok(ChainArg { name: name.to_string(), spec_path })
+    }
+}
+
+/// A `--chain-spec-url` argument: `name=https://host/spec.json`, optionally pinned with a
+/// `#sha256=<hex>` fragment (fragments are never sent to the server)
+#[derive(Clone, Debug)]
+pub struct ChainSpecUrl {
+    pub name: String,
+    pub url: String,
+    pub sha256: Option<String>,
+}
+
+impl FromStr for ChainSpecUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, url)) = s.split_once('=') else {
+            return Err("expected name=https://host/spec.json".to_string());
+        };
+        let name = ChainArg::from_str(name)?.name;
+        let (url, sha256) = match url.split_once("#sha256=") {
+            Some((url, sha256)) => (url, Some(sha256.to_ascii_lowercase())),
+            None => (url, None),
+        };
+        if !reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+            return Err(format!("invalid chain spec URL {:?}", url));
+        }
+        if sha256.as_ref().is_some_and(|sha256| sha256.len() != 64 || hex::decode(sha256).is_err()) {
+            return Err("sha256 should be 64 hex characters".to_string());
+        }
+
+        Ok(ChainSpecUrl { name, url: url.to_string(), sha256 })
+    }
+}
+
+impl ChainSpecUrl {
+    /// Downloads the spec into `cache_dir/<name>.json` and returns it as a `--chain` argument.
+    /// A cached copy matching the pinned checksum is used without downloading, and without a
+    /// checksum the cached copy stands in when the download fails.
+    pub async fn fetch(&self, cache_dir: &Path) -> Result<ChainArg, String> {
+        let cached = cache_dir.join(format!("{}.json", self.name));
+        let arg = ChainArg { name: self.name.clone(), spec_path: Some(cached.clone()) };
+        if let Some(sha256) = &self.sha256 {
+            if std::fs::read(&cached).is_ok_and(|spec| checksum(&spec) == *sha256) {
+                return Ok(arg);
+            }
+        }
+
+        let spec = match self.download().await {
+            Ok(spec) => spec,
+            Err(e) if self.sha256.is_none() && cached.exists() => {
+                tracing::warn!(chain = %self.name, error = %e, "Failed to download the chain spec, using the cached one");
+                return Ok(arg);
+            }
+            Err(e) => return Err(format!("failed to download the chain spec for {}: {}", self.name, e)),
+        };
+        if let Some(sha256) = &self.sha256 {
+            let actual = checksum(&spec);
+            if actual != *sha256 {
+                return Err(format!(
+                    "chain spec for {} from {} has sha256 {}, expected {}",
+                    self.name, self.url, actual, sha256
+                ));
+            }
+        }
+
+        // Written aside first, so an interrupted start never leaves half a spec in the cache
+        let partial = cached.with_extension("json.partial");
+        std::fs::create_dir_all(cache_dir)
+            .and_then(|()| std::fs::write(&partial, &spec))
+            .and_then(|()| std::fs::rename(&partial, &cached))
+            .map_err(|e| format!("failed to cache the chain spec in {}: {}", cached.display(), e))?;
+        tracing::info!(chain = %self.name, url = %self.url, "Downloaded chain spec");
+        Ok(arg)
+    }
+
+    async fn download(&self) -> Result<Vec<u8>, reqwest::Error> {
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(60)).build()?;
+        let response = http.get(&self.url).send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+fn checksum(spec: &[u8]) -> String {
+    hex::encode(Sha256::digest(spec))
+}
+
+#[derive(Clone, Debug)]
+pub struct ChainSpec {
+    pub name: String,
+    pub spec: String,
+    /// The spec's own `id`, which parachain specs refer to in `relay_chain`
+    pub id: String,
+    /// Set for parachains: `id` of the relay chain they belong to
+    pub relay_chain: Option<String>,
+    /// Set for parachains: their id on the relay chain
+    pub para_id: Option<u32>,
+    /// From the spec's `properties`, e.g. "DOT"
+    pub token_symbol: Option<String>,
+    /// SS58 address prefix from the spec's `properties`, 42 (generic Substrate) if missing
+    pub ss58_format: u16,
+}
+
+impl ChainSpec {
+    pub fn load(arg: &ChainArg) -> Result<Self, String> {
+        let spec = match &arg.spec_path {
+            Some(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read chain spec {}: {}", path.display(), e))?,
+            None => BUNDLED
+                .iter()
+                .find(|(name, _)| *name == arg.name)
+                .map(|(_, spec)| spec.to_string())
+                .ok_or_else(|| {
+                    format!(
+                        "no bundled chain spec for {:?}, pass one with --chain {}=path/to/spec.json",
+                        arg.name, arg.name
+                    )
+                })?,
+        };
+
+        let json: serde_json::Value = serde_json::from_str(&spec)
+            .map_err(|e| format!("chain spec for {} is not valid JSON: {}", arg.name, e))?;
+
+        let id = json["id"].as_str().unwrap_or(&arg.name).to_string();
+        // Specs in the wild use both spellings
+        let relay_chain = json
+            .get("relay_chain")
+            .or_else(|| json.get("relayChain"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+        let para_id = json
+            .get("para_id")
+            .or_else(|| json.get("paraId"))
+            .and_then(|v| v.as_u64())
+            .and_then(|id| u32::try_from(id).ok());
+        let token_symbol = json["properties"]["tokenSymbol"].as_str().map(|s| s.to_string());
+        let ss58_format = json["properties"]["ss58Format"]
+            .as_u64()
+            .and_then(|format| u16::try_from(format).ok())
+            .unwrap_or(42);
+
+        Ok(ChainSpec {
+            name: arg.name.clone(),
+            spec,
+            id,
+            relay_chain,
+            para_id,
+            token_symbol,
+            ss58_format,
+        })
+    }
+
+    /// Rewrites the spec's `bootNodes` and `lightSyncState` as `[network.<chain>]` asks, before
+    /// the light client sees it.
+    pub fn apply_network(&mut self, network: &NetworkConfig) -> Result<(), String> {
+        let replace_bootnodes = network.bootnodes.is_some() || !network.extra_bootnodes.is_empty();
+        if !replace_bootnodes && network.checkpoint.is_none() {
+            return Ok(());
+        }
+        let mut json: serde_json::Value = serde_json::from_str(&self.spec)
+            .map_err(|e| format!("chain spec for {} is not valid JSON: {}", self.name, e))?;
+        if replace_bootnodes {
+            self.replace_bootnodes(&mut json, network)?;
+        }
+        if let Some(path) = &network.checkpoint {
+            if self.relay_chain.is_some() {
+                return Err(format!("{} is a parachain, it syncs through its relay chain's checkpoint", self.name));
+            }
+            let (checkpoint, number) = read_checkpoint(path)?;
+            tracing::info!(chain = %self.name, number, "Warp syncing from the checkpoint in [network]");
+            json["lightSyncState"] = checkpoint;
+        }
+
+        self.spec = json.to_string();
+        Ok(())
+    }
+
+    fn replace_bootnodes(&self, json: &mut serde_json::Value, network: &NetworkConfig) -> Result<(), String> {
+        let mut bootnodes = match &network.bootnodes {
+            Some(bootnodes) => bootnodes.clone(),
+            None => json["bootNodes"]
+                .as_array()
+                .map(|nodes| nodes.iter().filter_map(|node| node.as_str().map(str::to_string)).collect())
+                .unwrap_or_default(),
+        };
+        bootnodes.extend(network.extra_bootnodes.iter().cloned());
+        // smoldot skips malformed ones silently, better to hear about a typo now
+        if let Some(node) = bootnodes.iter().find(|node| !node.starts_with('/') || !node.contains("/p2p/")) {
+            return Err(format!("bootnode {:?} for {} should be a multiaddr ending in /p2p/<peer id>", node, self.name));
+        }
+
+        tracing::info!(chain = %self.name, bootnodes = bootnodes.len(), "Using bootnodes from [network]");
+        json["bootNodes"] = serde_json::json!(bootnodes);
+        Ok(())
+    }
+}
+
+// The checkpoint's `lightSyncState` and the number of its finalized block
+fn read_checkpoint(path: &Path) -> Result<(serde_json::Value, u32), String> {
+    let invalid = |reason: &str| format!("checkpoint {} {}", path.display(), reason);
+    let text = std::fs::read_to_string(path).map_err(|e| invalid(&format!("can't be read: {}", e)))?;
+    let json: serde_json::Value =
+        serde_json::from_str(&text).map_err(|e| invalid(&format!("is not valid JSON: {}", e)))?;
+    let checkpoint = match json.get("lightSyncState") {
+        Some(state) => state.clone(),
+        None => json,
+    };
+    for field in ["finalizedBlockHeader", "babeEpochChanges", "babeFinalizedBlockWeight", "grandpaAuthoritySet"] {
+        if checkpoint.get(field).is_none() {
+            return Err(invalid(&format!("has no {}", field)));
+        }
+    }
+
+    // The header starts with the parent hash, then the compact-encoded number
+    let header = checkpoint["finalizedBlockHeader"]
+        .as_str()
+        .and_then(|header| hex::decode(header.trim_start_matches("0x")).ok())
+        .ok_or_else(|| invalid("has a finalizedBlockHeader that isn't hex"))?;
+    let number = header
+        .get(32..)
+        .and_then(|mut rest| Compact::<u32>::decode(&mut rest).ok())
+        .ok_or_else(|| invalid("has a finalizedBlockHeader that doesn't decode"))?;
+    Ok((checkpoint, number.0))
+}
@@ -0,0 +1,115 @@
+use crate::db::{Database, StoredBlock};
+
+const MAX_LISTED: usize = 20;
+
+#[derive(Default)]
+struct Report {
+    integrity: Vec<String>,
+    blocks: u64,
+    first: Option<u32>,
+    last: Option<u32>,
+    bad_hashes: Vec<(u32, String)>,
+    bad_data: Vec<(u32, String)>,
+    // Flagged when they were stored, see `StoredBlock::verified`
+    unverified: Vec<u32>,
+    gaps: Vec<(u32, u32)>,
+}
+
+impl Report {
+    fn is_ok(&self) -> bool {
+        self.integrity == ["ok"]
+            && self.bad_hashes.is_empty()
+            && self.bad_data.is_empty()
+            && self.unverified.is_empty()
+            && self.gaps.is_empty()
+    }
+}
+
+// A block hash is stored as the debug output of H256, i.e. "0x" + 64 hex chars
+fn is_valid_hash(hash: &str) -> bool {
+    match hash.strip_prefix("0x") {
+        Some(hex) => hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+/// Runs all checks against the database and prints a report.
+/// Returns `true` if nothing was found wrong.
+pub fn run(db: &Database) -> Result<bool, rusqlite::Error> {
+    let mut report = Report {
+        integrity: db.integrity_check()?,
+        ..Default::default()
+    };
+
+    db.for_each_raw_block(|number, hash, data| {
+        report.blocks += 1;
+        report.first.get_or_insert(number);
+
+        if let Some(prev) = report.last {
+            if number > prev + 1 {
+                report.gaps.push((prev + 1, number - 1));
+            }
+        }
+        report.last = Some(number);
+
+        if !is_valid_hash(hash) {
+            report.bad_hashes.push((number, hash.to_string()));
+        }
+
+        match serde_json::from_str::<StoredBlock>(data) {
+            Ok(block) if block.number != number => report
+                .bad_data
+                .push((number, format!("block_data says #{}", block.number))),
+            Ok(block) if block.hash != hash => report
+                .bad_data
+                .push((number, format!("block_data hash {} differs from column", block.hash))),
+            Ok(block) if block.verified == Some(false) => report.unverified.push(number),
+            Ok(_) => {}
+            Err(e) => report.bad_data.push((number, e.to_string())),
+        }
+    })?;
+
+    print_report(&report);
+    Ok(report.is_ok())
+}
+
+fn print_report(report: &Report) {
+    println!("Integrity check:");
+    for line in &report.integrity {
+        println!("  {}", line);
+    }
+
+    match (report.first, report.last) {
+        (Some(first), Some(last)) => {
+            println!("\nBlocks: {} stored, range #{} - #{}", report.blocks, first, last)
+        }
+        _ => println!("\nBlocks: none stored"),
+    }
+
+    println!("\nInvalid block hashes: {}", report.bad_hashes.len());
+    for (number, hash) in report.bad_hashes.iter().take(MAX_LISTED) {
+        println!("  #{}: {:?}", number, hash);
+    }
+
+    println!("\nUnreadable block data: {}", report.bad_data.len());
+    for (number, err) in report.bad_data.iter().take(MAX_LISTED) {
+        println!("  #{}: {}", number, err);
+    }
+
+    println!("\nFailed verification when stored: {}", report.unverified.len());
+    for number in report.unverified.iter().take(MAX_LISTED) {
+        println!("  #{}", number);
+    }
+
+    let missing: u64 = report.gaps.iter().map(|(a, b)| (b - a + 1) as u64).sum();
+    println!("\nGaps: {} ({} blocks missing)", report.gaps.len(), missing);
+    for (from, to) in report.gaps.iter().take(MAX_LISTED) {
+        if from == to {
+            println!("  #{}", from);
+        } else {
+            println!("  #{} - #{}", from, to);
+        }
+    }
+
+    println!("\n{}", if report.is_ok() { "OK" } else { "PROBLEMS FOUND" });
+}
@@ -0,0 +1,267 @@
+//! Mirrors extrinsics and events into ClickHouse for analytical queries SQLite can't handle.
+//! Rows are batched and sent over ClickHouse's HTTP interface as JSONEachRow.
+//!
+//! Tables, with the default `smolcar` prefix (created on startup if missing):
+//! - `smolcar_extrinsics` one row per stored extrinsic
+//! - `smolcar_events` one row per stored event
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::config::ClickHouseConfig;
+use crate::decode;
+use crate::indexer::ExtrinsicInfo;
+use crate::sinks::{BlockSummary, Sink};
+
+// Blocks waiting to be batched before we start dropping them
+const QUEUE_SIZE: usize = 256;
+
+#[derive(Serialize)]
+struct ExtrinsicRow {
+    chain: String,
+    block_number: u32,
+    extrinsic_index: u32,
+    hash: String,
+    action: String,
+    params: String,
+    timestamp: i64,
+}
+
+#[derive(Serialize)]
+struct EventRow {
+    chain: String,
+    block_number: u32,
+    extrinsic_index: u32,
+    pallet: String,
+    variant: String,
+    data: String,
+    timestamp: i64,
+}
+
+#[derive(Default)]
+struct Batch {
+    extrinsics: Vec<ExtrinsicRow>,
+    events: Vec<EventRow>,
+}
+
+impl Batch {
+    fn len(&self) -> usize {
+        self.extrinsics.len() + self.events.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+struct Client {
+    http: reqwest::Client,
+    url: String,
+    database: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl Client {
+    async fn execute(&self, query: &str, body: String) -> Result<(), String> {
+        let mut request = self
+            .http
+            .post(&self.url)
+            .query(&[("database", self.database.as_str()), ("query", query)])
+            .body(body);
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_deref());
+        }
+
+        let response = request.send().await.map_err(|e| e.without_url().to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            Err(format!("{}: {}", status, text.trim()))
+        }
+    }
+
+    async fn insert<T: Serialize>(&self, table: &str, rows: &[T]) -> Result<(), String> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut body = String::new();
+        for row in rows {
+            body.push_str(&serde_json::to_string(row).unwrap());
+            body.push('\n');
+        }
+        self.execute(&format!("INSERT INTO {} FORMAT JSONEachRow", table), body)
+            .await
+    }
+}
+
+pub struct ClickHouseSink {
+    tx: mpsc::Sender<Batch>,
+    // Rows of blocks still being processed, keyed by (chain, block number)
+    pending: Mutex<HashMap<(String, u32), Vec<ExtrinsicInfo>>>,
+}
+
+impl ClickHouseSink {
+    /// Creates the tables if needed, then batches inserts in the background.
+    pub async fn connect(config: &ClickHouseConfig) -> Result<Self, String> {
+        let client = Client {
+            http: reqwest::Client::new(),
+            url: config.url.clone(),
+            database: config.database.clone(),
+            username: config.username.clone(),
+            password: config.password.clone(),
+        };
+        let extrinsics_table = format!("{}_extrinsics", config.table_prefix);
+        let events_table = format!("{}_events", config.table_prefix);
+
+        client
+            .execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (
+                        chain LowCardinality(String),
+                        block_number UInt32,
+                        extrinsic_index UInt32,
+                        hash String,
+                        action LowCardinality(String),
+                        params String,
+                        timestamp DateTime
+                    ) ENGINE = ReplacingMergeTree ORDER BY (chain, block_number, extrinsic_index)",
+                    extrinsics_table
+                ),
+                String::new(),
+            )
+            .await
+            .map_err(|e| format!("failed to create ClickHouse table {}: {}", extrinsics_table, e))?;
+        client
+            .execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (
+                        chain LowCardinality(String),
+                        block_number UInt32,
+                        extrinsic_index UInt32,
+                        pallet LowCardinality(String),
+                        variant LowCardinality(String),
+                        data String,
+                        timestamp DateTime
+                    ) ENGINE = MergeTree ORDER BY (chain, pallet, variant, block_number)",
+                    events_table
+                ),
+                String::new(),
+            )
+            .await
+            .map_err(|e| format!("failed to create ClickHouse table {}: {}", events_table, e))?;
+        tracing::info!(url = %config.url, "Connected to ClickHouse");
+
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(writer(
+            client,
+            rx,
+            extrinsics_table,
+            events_table,
+            config.batch_size,
+            Duration::from_secs(config.flush_interval_secs.max(1)),
+        ));
+
+        Ok(ClickHouseSink {
+            tx,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+impl Sink for ClickHouseSink {
+    fn on_extrinsic(&self, chain: &str, block_number: u32, extrinsic: &ExtrinsicInfo) {
+        self.pending
+            .lock()
+            .unwrap()
+            .entry((chain.to_string(), block_number))
+            .or_default()
+            .push(extrinsic.clone());
+    }
+
+    fn on_block(&self, block: &BlockSummary) {
+        let extrinsics = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&(block.chain.clone(), block.number))
+            .unwrap_or_default();
+
+        // Rows only get the block's timestamp once it's stored
+        let mut batch = Batch::default();
+        for extrinsic in extrinsics {
+            for event in extrinsic.events {
+                batch.events.push(EventRow {
+                    chain: block.chain.clone(),
+                    block_number: block.number,
+                    extrinsic_index: extrinsic.index,
+                    pallet: event.pallet,
+                    variant: event.variant,
+                    data: event.data,
+                    timestamp: block.timestamp,
+                });
+            }
+            batch.extrinsics.push(ExtrinsicRow {
+                chain: block.chain.clone(),
+                block_number: block.number,
+                extrinsic_index: extrinsic.index,
+                hash: extrinsic.hash,
+                action: extrinsic.action,
+                params: decode::params_text(&extrinsic.params),
+                timestamp: block.timestamp,
+            });
+        }
+
+        if self.tx.try_send(batch).is_err() {
+            tracing::warn!(chain = %block.chain, block = block.number, "ClickHouse is falling behind, dropping block");
+        }
+    }
+}
+
+async fn writer(
+    client: Client,
+    mut rx: mpsc::Receiver<Batch>,
+    extrinsics_table: String,
+    events_table: String,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    let mut batch = Batch::default();
+    let mut interval = tokio::time::interval(flush_interval);
+    loop {
+        tokio::select! {
+            received = rx.recv() => match received {
+                Some(block) => {
+                    batch.extrinsics.extend(block.extrinsics);
+                    batch.events.extend(block.events);
+                    if batch.len() < batch_size {
+                        continue;
+                    }
+                }
+                None => break,
+            },
+            _ = interval.tick() => {
+                if batch.is_empty() {
+                    continue;
+                }
+            }
+        }
+        flush(&client, &mut batch, &extrinsics_table, &events_table).await;
+    }
+    flush(&client, &mut batch, &extrinsics_table, &events_table).await;
+}
+
+async fn flush(client: &Client, batch: &mut Batch, extrinsics_table: &str, events_table: &str) {
+    let rows = std::mem::take(batch);
+    if let Err(e) = client.insert(extrinsics_table, &rows.extrinsics).await {
+        tracing::warn!(rows = rows.extrinsics.len(), "Failed to insert extrinsics into ClickHouse: {}", e);
+    }
+    if let Err(e) = client.insert(events_table, &rows.events).await {
+        tracing::warn!(rows = rows.events.len(), "Failed to insert events into ClickHouse: {}", e);
+    }
+}
@@ -0,0 +1,430 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::alerts::{Alert, Channel, OffenceAlert, OfflineAlert};
+use crate::db::EventFilter;
+
+/// Settings read from `--config smolcar.toml`. Everything is optional.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Prune the oldest blocks once a chain's database grows past this, e.g. "5GB"
+    pub max_db_size: Option<ByteSize>,
+    /// Number of most recent blocks pruning never touches
+    pub keep_recent_blocks: u32,
+    /// Compact each chain's database once a day at this UTC hour (0-23), handing the space
+    /// pruning freed back to the OS. Pick a quiet hour, writes wait while it runs.
+    pub compact_hour: Option<u8>,
+    /// Serialize extrinsics as they're decoded instead of holding whole blocks in memory.
+    /// `/blocks/head` then only returns the head's summary, not its extrinsics.
+    pub low_memory: bool,
+    /// Blocks processed at once when catching up. They're still stored in order, along with
+    /// what the per-block trackers (epochs, authors...) recorded from them.
+    pub parallel_blocks: usize,
+    /// On startup, fetch the blocks finalized since the last stored one, i.e. while smolcar
+    /// was down, like `[backfill]` does
+    pub catch_up: bool,
+    /// On a relay chain, record which parachain blocks each block backed and included,
+    /// from the `ParaInclusion` events (served on `/parachains/:id/inclusions`)
+    pub track_inclusions: bool,
+    /// Fetch and store GRANDPA justifications (served on `/block/:n/justification`)
+    pub store_justifications: bool,
+    /// Forward JSON-RPC calls `/rpc` can't answer from the database to the light client
+    pub rpc_passthrough: bool,
+    /// Sentry DSN to report panics and processing errors to (needs the `sentry` feature).
+    /// Falls back to the SENTRY_DSN environment variable.
+    pub sentry_dsn: Option<String>,
+    /// Bearer token for the `/admin` routes, which are off without one.
+    /// Falls back to the SMOLCAR_ADMIN_TOKEN environment variable.
+    pub admin_token: Option<String>,
+    /// What not to store. Without a `[filters]` section the compiled-in defaults apply.
+    pub filters: Option<Filters>,
+    /// Named Telegram/Discord/Slack destinations for alerts
+    pub alert_channels: HashMap<String, Channel>,
+    /// Event rules that post to one or more alert channels when they fire
+    pub alerts: Vec<Alert>,
+    /// Post offence reports, with who offended, to alert channels
+    pub offence_alerts: Vec<OffenceAlert>,
+    /// Post validators that `ImOnline` reports offline at the end of a session to alert channels
+    pub offline_alerts: Vec<OfflineAlert>,
+    /// Publish blocks and events to an MQTT broker (needs the `mqtt` feature)
+    pub mqtt: Option<MqttConfig>,
+    /// Publish blocks to Redis and cache recent ones there (needs the `redis` feature)
+    pub redis: Option<RedisConfig>,
+    /// Mirror extrinsics and events into ClickHouse (needs the `clickhouse` feature)
+    pub clickhouse: Option<ClickHouseConfig>,
+    /// Upload blocks to S3-compatible storage before pruning them (needs the `s3` feature)
+    pub archive: Option<ArchiveConfig>,
+    /// Fetch the blocks from before smolcar started following each chain
+    pub backfill: Option<BackfillConfig>,
+    /// Snapshot the databases into another directory for a standby smolcar to start from
+    pub standby: Option<StandbyConfig>,
+    /// SQLite settings for every chain database
+    pub sqlite: SqliteConfig,
+    /// Light client networking per chain, keyed by chain name
+    pub network: HashMap<String, NetworkConfig>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filters {
+    /// "Pallet" drops all of a pallet's events, "Pallet/Variant" just that one
+    pub exclude_events: Vec<String>,
+    /// Extrinsic actions in "Pallet/Method" format
+    pub exclude_extrinsics: Vec<String>,
+    /// Only store these events ("Pallet" or "Pallet/Variant"), all of them when empty
+    pub only_events: Vec<String>,
+    /// Only store these extrinsics ("Pallet" or "Pallet/Method"), all of them when empty
+    pub only_extrinsics: Vec<String>,
+}
+
+impl Filters {
+    pub fn event_filters(&self) -> Vec<EventFilter> {
+        Self::parse(&self.exclude_events)
+    }
+
+    pub fn parse(filters: &[String]) -> Vec<EventFilter> {
+        filters
+            .iter()
+            .map(|filter| match filter.split_once('/') {
+                Some((pallet, method)) => EventFilter {
+                    pallet: pallet.to_string(),
+                    method: Some(method.to_string()),
+                },
+                None => EventFilter {
+                    pallet: filter.to_string(),
+                    method: None,
+                },
+            })
+            .collect()
+    }
+}
+
+/// Light client networking of one chain. Peer and connection limits aren't here yet:
+/// smoldot-light 0.16 fixes them when a chain is added (4 outgoing peer slots, 8 connections
+/// opening at once) and neither it nor subxt take them as options, so they need an upstream
+/// change first.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Replace the chain spec's bootnodes, e.g. with ones reachable through a firewall
+    pub bootnodes: Option<Vec<String>>,
+    /// Bootnodes to try on top of the spec's (or `bootnodes`)
+    pub extra_bootnodes: Vec<String>,
+    /// A recent finalized checkpoint to warp sync from instead of the spec's, as returned by a
+    /// full node's `sync_state_genSyncSpec` (or just its `lightSyncState`)
+    pub checkpoint: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StandbyConfig {
+    /// Ideally on another disk. The snapshots keep the data directory's file names
+    pub dir: PathBuf,
+    /// How often the snapshots are taken, which bounds how far behind the standby can be.
+    /// Each one rewrites the whole database, so keep it well above the time that takes.
+    #[serde(default = "default_standby_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_standby_interval_secs() -> u64 {
+    300
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default = "default_topic_prefix")]
+    pub topic_prefix: String,
+    /// 0 (at most once), 1 (at least once) or 2 (exactly once)
+    #[serde(default)]
+    pub qos: u8,
+}
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_client_id() -> String {
+    "smolcar".to_string()
+}
+
+fn default_topic_prefix() -> String {
+    "smolcar".to_string()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(feature = "redis"), allow(dead_code))]
+pub struct RedisConfig {
+    /// e.g. "redis://127.0.0.1/"
+    pub url: String,
+    #[serde(default = "default_topic_prefix")]
+    pub key_prefix: String,
+    /// How many recent blocks per chain to keep cached, 0 to only publish
+    #[serde(default = "default_cache_blocks")]
+    pub cache_blocks: u32,
+}
+
+fn default_cache_blocks() -> u32 {
+    100
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(feature = "clickhouse"), allow(dead_code))]
+pub struct ClickHouseConfig {
+    /// HTTP interface, e.g. "http://localhost:8123"
+    pub url: String,
+    #[serde(default = "default_clickhouse_database")]
+    pub database: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    #[serde(default = "default_topic_prefix")]
+    pub table_prefix: String,
+    /// Rows to collect before inserting
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// Insert whatever has been collected at least this often
+    #[serde(default = "default_flush_interval_secs")]
+    pub flush_interval_secs: u64,
+}
+
+fn default_clickhouse_database() -> String {
+    "default".to_string()
+}
+
+fn default_batch_size() -> usize {
+    10_000
+}
+
+fn default_flush_interval_secs() -> u64 {
+    5
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[cfg_attr(not(feature = "s3"), allow(dead_code))]
+pub struct ArchiveConfig {
+    pub bucket: String,
+    /// For S3-compatible services (MinIO, R2, ...), e.g. "http://localhost:9000"
+    pub endpoint: Option<String>,
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// Fall back to the AWS_ACCESS_KEY_ID / AWS_SECRET_ACCESS_KEY environment variables
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    /// Chunks are stored as <prefix>/<chain>/<first>-<last>.ndjson.gz
+    #[serde(default = "default_topic_prefix")]
+    pub prefix: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+    Off,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+/// Applied when a database is opened. The defaults favour write throughput: with WAL and
+/// `synchronous = normal` a crash can lose the last blocks, never corrupt the database.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqliteConfig {
+    pub journal_mode: JournalMode,
+    pub synchronous: Synchronous,
+    /// Page cache per database
+    pub cache_size: ByteSize,
+    /// How much of the database file to memory-map, 0 to read it through the page cache only
+    pub mmap_size: ByteSize,
+    /// Log statements slower than this, with their parameters
+    pub slow_query_ms: Option<u64>,
+    /// How long a statement retries while another connection holds the lock before giving
+    /// up, which the API answers with a 503 and `Retry-After`
+    pub busy_timeout_ms: u64,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        SqliteConfig {
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            cache_size: ByteSize(64 << 20),
+            mmap_size: ByteSize(256 << 20),
+            slow_query_ms: None,
+            busy_timeout_ms: 5000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BackfillConfig {
+    /// First block to fetch
+    pub from: u32,
+    /// Last block to fetch, by default the one before the finalized head at startup
+    pub to: Option<u32>,
+    /// Blocks fetched at once
+    #[serde(default = "default_backfill_concurrency")]
+    pub concurrency: usize,
+    /// Blocks fetched per second at most, unlimited by default
+    pub blocks_per_second: Option<f64>,
+    /// Blocks between progress reports
+    #[serde(default = "default_backfill_batch_size")]
+    pub batch_size: u32,
+}
+
+impl BackfillConfig {
+    /// Everything from `from` up to where following started, at the pace `[backfill]` sets if
+    /// there is one. For catching up and `--from-block`.
+    pub fn starting_at(from: u32, backfill: Option<&BackfillConfig>) -> BackfillConfig {
+        BackfillConfig {
+            from,
+            to: None,
+            concurrency: backfill.map_or_else(default_backfill_concurrency, |backfill| backfill.concurrency),
+            blocks_per_second: backfill.and_then(|backfill| backfill.blocks_per_second),
+            batch_size: default_backfill_batch_size(),
+        }
+    }
+}
+
+fn default_backfill_concurrency() -> usize {
+    4
+}
+
+fn default_backfill_batch_size() -> u32 {
+    1000
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_db_size: None,
+            keep_recent_blocks: 1000,
+            compact_hour: None,
+            low_memory: false,
+            parallel_blocks: 1,
+            catch_up: true,
+            track_inclusions: false,
+            store_justifications: false,
+            rpc_passthrough: false,
+            sentry_dsn: None,
+            admin_token: None,
+            filters: None,
+            alert_channels: HashMap::new(),
+            alerts: vec![],
+            offence_alerts: vec![],
+            offline_alerts: vec![],
+            mqtt: None,
+            redis: None,
+            clickhouse: None,
+            archive: None,
+            backfill: None,
+            standby: None,
+            sqlite: SqliteConfig::default(),
+            network: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read config {}: {}", path.display(), e))?;
+        toml::from_str(&raw).map_err(|e| format!("invalid config {}: {}", path.display(), e))
+    }
+}
+
+/// A size in bytes, written either as a plain number or as "500MB", "5GB", "1.5GiB"...
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl std::str::FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("invalid size {:?}", s))?;
+        let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "KB" => 1_000,
+            "MB" => 1_000_000,
+            "GB" => 1_000_000_000,
+            "TB" => 1_000_000_000_000,
+            "KIB" => 1 << 10,
+            "MIB" => 1 << 20,
+            "GIB" => 1 << 30,
+            "TIB" => 1 << 40,
+            _ => return Err(format!("unknown size unit in {:?}", s)),
+        };
+
+        Ok(ByteSize((number * multiplier as f64) as u64))
+    }
+}
+
+impl std::fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= 1000.0 && unit < UNITS.len() - 1 {
+            value /= 1000.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            write!(f, "{}B", self.0)
+        } else {
+            write!(f, "{:.1}{}", value, UNITS[unit])
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bytes(u64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Bytes(bytes) => Ok(ByteSize(bytes)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+//! `--daemon`, and the lock that keeps two smolcars from writing to the same data directory.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::Path;
+
+use crate::error::Error;
+
+const LOCK_FILE: &str = "smolcar.lock";
+const PID_FILE: &str = "smolcar.pid";
+
+/// Forks into the background, leaving the PID in `pid_file` (`smolcar.pid` in the data
+/// directory by default). Has to run before the tokio runtime starts, a fork only keeps the
+/// thread that called it.
+pub fn detach(data_dir: &Path, pid_file: Option<&Path>) -> Result<(), Error> {
+    let pid_file = pid_file.map(Path::to_path_buf).unwrap_or_else(|| data_dir.join(PID_FILE));
+    std::fs::create_dir_all(data_dir)?;
+    daemonize::Daemonize::new()
+        .pid_file(&pid_file)
+        // Relative paths in the arguments and config still point where they did
+        .working_directory(std::env::current_dir()?)
+        .umask(0o022)
+        .start()
+        .map_err(|e| Error::Other(format!("Failed to daemonize (PID file {}): {}", pid_file.display(), e)))
+}
+
+/// Held for as long as smolcar writes to the data directory, and released when it exits,
+/// however it exits.
+pub struct DataDirLock {
+    _file: File,
+}
+
+pub fn lock_data_dir(data_dir: &Path) -> Result<DataDirLock, Error> {
+    std::fs::create_dir_all(data_dir)?;
+    let path = data_dir.join(LOCK_FILE);
+    let file = OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(DataDirLock { _file: file }),
+        Err(TryLockError::WouldBlock) => Err(Error::Other(format!(
+            "Another smolcar is already using {} (it holds {}). Use --read-only to serve its databases alongside it",
+            data_dir.display(),
+            path.display()
+        ))),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
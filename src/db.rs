@@ -1,12 +1,602 @@
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use subxt::ext::codec::{Compact, Encode};
+
+use crate::config::{Filters, SqliteConfig};
+use crate::metrics::{self, DbOperation};
+
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+
+/// Schema migrations, applied in order on startup. Never edit a migration that has
+/// shipped, add a new one at the end instead.
+const MIGRATIONS: &[(&str, &str)] = &[
+    (
+        "create blocks table",
+        "CREATE TABLE IF NOT EXISTS blocks (
+            block_number INTEGER PRIMARY KEY,
+            block_hash TEXT NOT NULL,
+            block_data TEXT NOT NULL,
+            timestamp INTEGER NOT NULL
+        );
+        -- Index on timestamp for range queries
+        CREATE INDEX IF NOT EXISTS idx_timestamp ON blocks(timestamp);",
+    ),
+    (
+        "track archived chunks",
+        "CREATE TABLE archived_chunks (
+            first_block INTEGER PRIMARY KEY,
+            last_block INTEGER NOT NULL,
+            object_key TEXT NOT NULL
+        );",
+    ),
+    (
+        "index blocks by hash",
+        "CREATE INDEX idx_block_hash ON blocks(block_hash);",
+    ),
+    (
+        "audit log of replaced blocks",
+        "CREATE TABLE block_replacements (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            block_number INTEGER NOT NULL,
+            old_hash TEXT NOT NULL,
+            new_hash TEXT NOT NULL,
+            replaced_at INTEGER NOT NULL
+        );",
+    ),
+    (
+        "track scheduler tasks",
+        "CREATE TABLE scheduled_tasks (
+            when_block INTEGER NOT NULL,
+            task_index INTEGER NOT NULL,
+            scheduled_in INTEGER,
+            call TEXT,
+            status TEXT NOT NULL,
+            updated_in INTEGER NOT NULL,
+            PRIMARY KEY (when_block, task_index)
+        );
+        CREATE INDEX idx_scheduled_tasks_status ON scheduled_tasks(status, when_block);",
+    ),
+    (
+        "track referenda proposals",
+        "CREATE TABLE referenda (
+            referendum_index INTEGER PRIMARY KEY,
+            track INTEGER NOT NULL,
+            submitted_in INTEGER NOT NULL,
+            proposal_hash TEXT,
+            proposal_len INTEGER,
+            proposal TEXT
+        );
+        CREATE INDEX idx_referenda_proposal_hash ON referenda(proposal_hash);",
+    ),
+    (
+        "track assets and their transfers",
+        "CREATE TABLE assets (
+            asset_id INTEGER PRIMARY KEY,
+            created_in INTEGER,
+            owner TEXT,
+            name TEXT,
+            symbol TEXT,
+            decimals INTEGER,
+            destroyed_in INTEGER
+        );
+        CREATE TABLE asset_transfers (
+            block_number INTEGER NOT NULL,
+            event_index INTEGER NOT NULL,
+            extrinsic_index INTEGER,
+            asset_id INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            from_account TEXT,
+            to_account TEXT,
+            amount TEXT NOT NULL,
+            PRIMARY KEY (block_number, event_index)
+        );
+        CREATE INDEX idx_asset_transfers_asset ON asset_transfers(asset_id, block_number);",
+    ),
+    (
+        "track nft collections and items",
+        "CREATE TABLE nft_collections (
+            pallet TEXT NOT NULL,
+            collection_id INTEGER NOT NULL,
+            created_in INTEGER,
+            owner TEXT,
+            destroyed_in INTEGER,
+            PRIMARY KEY (pallet, collection_id)
+        );
+        CREATE TABLE nft_items (
+            pallet TEXT NOT NULL,
+            collection_id INTEGER NOT NULL,
+            item_id INTEGER NOT NULL,
+            owner TEXT,
+            minted_in INTEGER,
+            burned_in INTEGER,
+            updated_in INTEGER NOT NULL,
+            PRIMARY KEY (pallet, collection_id, item_id)
+        );
+        CREATE TABLE nft_attributes (
+            pallet TEXT NOT NULL,
+            collection_id INTEGER NOT NULL,
+            item_id INTEGER NOT NULL,
+            key TEXT NOT NULL,
+            value TEXT NOT NULL,
+            set_in INTEGER NOT NULL,
+            PRIMARY KEY (pallet, collection_id, item_id, key)
+        );",
+    ),
+    (
+        "track vesting schedules",
+        "CREATE TABLE vesting (
+            account TEXT PRIMARY KEY,
+            schedules TEXT NOT NULL,
+            unvested TEXT,
+            updated_in INTEGER NOT NULL
+        );",
+    ),
+    (
+        "track balance locks and reserves",
+        "CREATE TABLE balance_changes (
+            block_number INTEGER NOT NULL,
+            event_index INTEGER NOT NULL,
+            extrinsic_index INTEGER,
+            account TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            source TEXT,
+            amount TEXT NOT NULL,
+            PRIMARY KEY (block_number, event_index)
+        );
+        CREATE INDEX idx_balance_changes_account ON balance_changes(account, block_number);",
+    ),
+    (
+        "track parachain candidate inclusions",
+        "CREATE TABLE para_inclusions (
+            relay_block INTEGER NOT NULL,
+            para_id INTEGER NOT NULL,
+            status TEXT NOT NULL,
+            para_block_hash TEXT NOT NULL,
+            para_block_number INTEGER,
+            relay_parent TEXT NOT NULL,
+            PRIMARY KEY (relay_block, para_id, status)
+        );
+        CREATE INDEX idx_para_inclusions_para ON para_inclusions(para_id, relay_block);
+        CREATE INDEX idx_para_inclusions_hash ON para_inclusions(para_block_hash);",
+    ),
+    (
+        "track xcm messages",
+        "CREATE TABLE xcm_messages (
+            block_number INTEGER NOT NULL,
+            event_index INTEGER NOT NULL,
+            extrinsic_index INTEGER,
+            message_id TEXT NOT NULL,
+            direction TEXT NOT NULL,
+            counterparty TEXT,
+            success INTEGER,
+            PRIMARY KEY (block_number, event_index)
+        );
+        CREATE INDEX idx_xcm_messages_id ON xcm_messages(message_id);
+        CREATE INDEX idx_xcm_messages_direction ON xcm_messages(direction, block_number);",
+    ),
+    (
+        "track offences",
+        "CREATE TABLE offences (
+            block_number INTEGER NOT NULL,
+            event_index INTEGER NOT NULL,
+            kind TEXT NOT NULL,
+            time_slot TEXT NOT NULL,
+            offenders TEXT NOT NULL,
+            PRIMARY KEY (block_number, event_index)
+        );",
+    ),
+    (
+        "store grandpa justifications",
+        "CREATE TABLE justifications (
+            block_number INTEGER PRIMARY KEY,
+            block_hash TEXT NOT NULL,
+            justification BLOB NOT NULL
+        );",
+    ),
+    (
+        "track block slots and babe epochs",
+        "CREATE TABLE block_slots (
+            block_number INTEGER PRIMARY KEY,
+            slot INTEGER NOT NULL,
+            authority_index INTEGER,
+            kind TEXT NOT NULL
+        );
+        CREATE TABLE epochs (
+            epoch_index INTEGER PRIMARY KEY,
+            start_block INTEGER,
+            start_slot INTEGER,
+            authorities INTEGER,
+            randomness TEXT
+        );",
+    ),
+    (
+        "track staking elections",
+        "CREATE TABLE election_events (
+            block_number INTEGER NOT NULL,
+            event_index INTEGER NOT NULL,
+            round INTEGER,
+            kind TEXT NOT NULL,
+            detail TEXT,
+            PRIMARY KEY (block_number, event_index)
+        );
+        CREATE INDEX idx_election_events_kind ON election_events(kind, block_number);",
+    ),
+    (
+        "track era reward points",
+        "CREATE TABLE era_points (
+            era INTEGER NOT NULL,
+            validator TEXT NOT NULL,
+            points INTEGER NOT NULL,
+            PRIMARY KEY (era, validator)
+        );
+        CREATE TABLE eras (
+            era INTEGER PRIMARY KEY,
+            paid_in INTEGER NOT NULL,
+            total_points INTEGER NOT NULL,
+            validators INTEGER NOT NULL
+        );",
+    ),
+    (
+        "track validator performance",
+        "ALTER TABLE block_slots ADD COLUMN author TEXT;
+        CREATE INDEX idx_block_slots_author ON block_slots(author);
+        CREATE TABLE epoch_validators (
+            epoch_index INTEGER NOT NULL,
+            authority_index INTEGER NOT NULL,
+            validator TEXT NOT NULL,
+            PRIMARY KEY (epoch_index, authority_index)
+        );
+        CREATE INDEX idx_epoch_validators_validator ON epoch_validators(validator);
+        CREATE TABLE heartbeat_sessions (
+            block_number INTEGER PRIMARY KEY,
+            offline INTEGER NOT NULL
+        );
+        CREATE TABLE offline_validators (
+            block_number INTEGER NOT NULL,
+            validator TEXT NOT NULL,
+            PRIMARY KEY (block_number, validator)
+        );
+        CREATE INDEX idx_offline_validators_validator ON offline_validators(validator);",
+    ),
+    (
+        "track im-online heartbeats",
+        "ALTER TABLE offline_validators ADD COLUMN address TEXT;
+        CREATE TABLE heartbeats (
+            block_number INTEGER NOT NULL,
+            authority_id TEXT NOT NULL,
+            validator TEXT,
+            PRIMARY KEY (block_number, authority_id)
+        );
+        CREATE INDEX idx_heartbeats_validator ON heartbeats(validator, block_number);",
+    ),
+    (
+        "add account watchlist",
+        "CREATE TABLE watchlist (
+            account TEXT PRIMARY KEY,
+            address TEXT NOT NULL,
+            label TEXT,
+            channels TEXT NOT NULL,
+            added_at INTEGER NOT NULL
+        );",
+    ),
+    (
+        "add account webhooks",
+        "CREATE TABLE webhooks (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account TEXT NOT NULL,
+            address TEXT NOT NULL,
+            url TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );",
+    ),
+    (
+        "keep running counts of blocks, extrinsics and events",
+        "CREATE TABLE counters (
+            name TEXT PRIMARY KEY,
+            value INTEGER NOT NULL
+        );
+        -- Counted from what's already stored once, then kept up to date by the triggers
+        INSERT INTO counters (name, value)
+        SELECT name, COUNT(*) FROM (
+            SELECT 'blocks' AS name FROM blocks
+            UNION ALL
+            SELECT 'extrinsics' FROM blocks b, json_each(b.block_data, '$.extrinsics') x
+            UNION ALL
+            SELECT 'extrinsics:' || substr(x.value ->> 'action', 1, instr((x.value ->> 'action') || '/', '/') - 1)
+            FROM blocks b, json_each(b.block_data, '$.extrinsics') x
+            UNION ALL
+            SELECT 'events' FROM blocks b, json_each(b.block_data, '$.extrinsics') x, json_each(x.value, '$.events')
+            UNION ALL
+            SELECT 'events:' || (e.value ->> 'pallet')
+            FROM blocks b, json_each(b.block_data, '$.extrinsics') x, json_each(x.value, '$.events') e
+        ) WHERE true GROUP BY name
+        ON CONFLICT (name) DO UPDATE SET value = value + excluded.value;
+        CREATE TRIGGER count_block_insert AFTER INSERT ON blocks BEGIN
+            INSERT INTO counters (name, value)
+            SELECT name, COUNT(*) FROM (
+                SELECT 'blocks' AS name
+                UNION ALL
+                SELECT 'extrinsics' FROM json_each(NEW.block_data, '$.extrinsics') x
+                UNION ALL
+                SELECT 'extrinsics:' || substr(x.value ->> 'action', 1, instr((x.value ->> 'action') || '/', '/') - 1)
+                FROM json_each(NEW.block_data, '$.extrinsics') x
+                UNION ALL
+                SELECT 'events' FROM json_each(NEW.block_data, '$.extrinsics') x, json_each(x.value, '$.events')
+                UNION ALL
+                SELECT 'events:' || (e.value ->> 'pallet')
+                FROM json_each(NEW.block_data, '$.extrinsics') x, json_each(x.value, '$.events') e
+            ) WHERE true GROUP BY name
+            ON CONFLICT (name) DO UPDATE SET value = value + excluded.value;
+        END;
+        CREATE TRIGGER count_block_delete AFTER DELETE ON blocks BEGIN
+            INSERT INTO counters (name, value)
+            SELECT name, -COUNT(*) FROM (
+                SELECT 'blocks' AS name
+                UNION ALL
+                SELECT 'extrinsics' FROM json_each(OLD.block_data, '$.extrinsics') x
+                UNION ALL
+                SELECT 'extrinsics:' || substr(x.value ->> 'action', 1, instr((x.value ->> 'action') || '/', '/') - 1)
+                FROM json_each(OLD.block_data, '$.extrinsics') x
+                UNION ALL
+                SELECT 'events' FROM json_each(OLD.block_data, '$.extrinsics') x, json_each(x.value, '$.events')
+                UNION ALL
+                SELECT 'events:' || (e.value ->> 'pallet')
+                FROM json_each(OLD.block_data, '$.extrinsics') x, json_each(x.value, '$.events') e
+            ) WHERE true GROUP BY name
+            ON CONFLICT (name) DO UPDATE SET value = value + excluded.value;
+        END;
+        CREATE TRIGGER count_block_update AFTER UPDATE OF block_data ON blocks BEGIN
+            INSERT INTO counters (name, value)
+            SELECT name, -COUNT(*) FROM (
+                SELECT 'blocks' AS name
+                UNION ALL
+                SELECT 'extrinsics' FROM json_each(OLD.block_data, '$.extrinsics') x
+                UNION ALL
+                SELECT 'extrinsics:' || substr(x.value ->> 'action', 1, instr((x.value ->> 'action') || '/', '/') - 1)
+                FROM json_each(OLD.block_data, '$.extrinsics') x
+                UNION ALL
+                SELECT 'events' FROM json_each(OLD.block_data, '$.extrinsics') x, json_each(x.value, '$.events')
+                UNION ALL
+                SELECT 'events:' || (e.value ->> 'pallet')
+                FROM json_each(OLD.block_data, '$.extrinsics') x, json_each(x.value, '$.events') e
+            ) WHERE true GROUP BY name
+            ON CONFLICT (name) DO UPDATE SET value = value + excluded.value;
+            INSERT INTO counters (name, value)
+            SELECT name, COUNT(*) FROM (
+                SELECT 'blocks' AS name
+                UNION ALL
+                SELECT 'extrinsics' FROM json_each(NEW.block_data, '$.extrinsics') x
+                UNION ALL
+                SELECT 'extrinsics:' || substr(x.value ->> 'action', 1, instr((x.value ->> 'action') || '/', '/') - 1)
+                FROM json_each(NEW.block_data, '$.extrinsics') x
+                UNION ALL
+                SELECT 'events' FROM json_each(NEW.block_data, '$.extrinsics') x, json_each(x.value, '$.events')
+                UNION ALL
+                SELECT 'events:' || (e.value ->> 'pallet')
+                FROM json_each(NEW.block_data, '$.extrinsics') x, json_each(x.value, '$.events') e
+            ) WHERE true GROUP BY name
+            ON CONFLICT (name) DO UPDATE SET value = value + excluded.value;
+        END;",
+    ),
+    (
+        "add alert subscriptions",
+        "CREATE TABLE subscriptions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            pallet TEXT,
+            variant TEXT,
+            data_contains TEXT,
+            channels TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        );",
+    ),
+    (
+        "add processing cursors",
+        "CREATE TABLE cursors (
+            name TEXT PRIMARY KEY,
+            block_number INTEGER NOT NULL,
+            block_hash TEXT NOT NULL,
+            updated_at INTEGER NOT NULL
+        );",
+    ),
+    (
+        "rename block_replacements to block_conflicts",
+        "ALTER TABLE block_replacements RENAME TO block_conflicts;",
+    ),
+    (
+        "track which block asset metadata is from",
+        "ALTER TABLE assets ADD COLUMN metadata_in INTEGER;",
+    ),
+];
+
+/// Cursor of the last block the follower stored, in order. Unlike `MAX(block_number)` it
+/// doesn't move when backfilled blocks land.
+pub const LAST_PROCESSED: &str = "last_processed";
+/// Cursor of the last block `--full-archive` got through, gaps included.
+pub const FULL_ARCHIVE: &str = "full_archive";
+/// Cursor of the last block catching up got through. It only exists while catching up is
+/// unfinished, since the follower moves `LAST_PROCESSED` past the blocks still missing.
+pub const CATCH_UP: &str = "catch_up";
+/// Cursor of the newest block pruned or archived away. Backfills start after it, so they don't
+/// fetch again what pruning just made room by deleting.
+pub const PRUNED: &str = "pruned";
+
+#[derive(Debug)]
+pub enum SchemaError {
+    Sqlite(rusqlite::Error),
+    TooNew { found: u32, supported: u32 },
+    /// Opened read-only, so the migrations it's missing can't be applied
+    Behind { found: u32, latest: u32 },
+    Migration { version: u32, description: &'static str, source: rusqlite::Error },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Sqlite(e) => write!(f, "{}", e),
+            SchemaError::TooNew { found, supported } => write!(
+                f,
+                "database schema is at version {} but this build only knows up to version {}, \
+                 upgrade smolcar or point it at a different database",
+                found, supported
+            ),
+            SchemaError::Behind { found, latest } => write!(
+                f,
+                "database schema is at version {} but this build expects version {}, \
+                 let the indexer migrate it before opening it read-only",
+                found, latest
+            ),
+            SchemaError::Migration { version, description, source } => write!(
+                f,
+                "schema migration {} ({}) failed: {}. The database was left at version {}",
+                version, description, source, version - 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<rusqlite::Error> for SchemaError {
+    fn from(e: rusqlite::Error) -> Self {
+        SchemaError::Sqlite(e)
+    }
+}
+
+fn load_watchlist(conn: &Connection) -> Result<HashMap<[u8; 32], Watch>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT account, address, label, channels, added_at FROM watchlist")?;
+    let rows = stmt.query_map([], |row| {
+        let account: String = row.get(0)?;
+        let channels: String = row.get(3)?;
+        let watch = Watch {
+            address: row.get(1)?,
+            label: row.get(2)?,
+            channels: serde_json::from_str(&channels).unwrap_or_default(),
+            added_at: row.get(4)?,
+        };
+        Ok((account, watch))
+    })?;
+
+    let mut watchlist = HashMap::new();
+    for row in rows {
+        let (account, watch) = row?;
+        if let Some(account) = account_from_hex(&account) {
+            watchlist.insert(account, watch);
+        }
+    }
+    Ok(watchlist)
+}
+
+fn load_webhooks(conn: &Connection) -> Result<HashMap<[u8; 32], Vec<Webhook>>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT id, account, address, url, created_at FROM webhooks ORDER BY id")?;
+    let rows = stmt.query_map([], |row| {
+        let account: String = row.get(1)?;
+        let webhook = Webhook {
+            id: row.get(0)?,
+            address: row.get(2)?,
+            url: row.get(3)?,
+            created_at: row.get(4)?,
+        };
+        Ok((account, webhook))
+    })?;
+
+    let mut webhooks: HashMap<[u8; 32], Vec<Webhook>> = HashMap::new();
+    for row in rows {
+        let (account, webhook) = row?;
+        if let Some(account) = account_from_hex(&account) {
+            webhooks.entry(account).or_default().push(webhook);
+        }
+    }
+    Ok(webhooks)
+}
+
+fn account_from_hex(account: &str) -> Option<[u8; 32]> {
+    hex::decode(account.trim_start_matches("0x")).ok()?.try_into().ok()
+}
+
+pub fn migrate(
+    conn: &mut Connection,
+    migrations: &[(&'static str, &'static str)],
+) -> Result<(), SchemaError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            applied_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    let current: u32 = conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get(0),
+    )?;
+    let latest = migrations.len() as u32;
+
+    if current > latest {
+        return Err(SchemaError::TooNew { found: current, supported: latest });
+    }
+
+    for (i, (description, sql)) in migrations.iter().enumerate().skip(current as usize) {
+        let version = i as u32 + 1;
+        let apply = |conn: &mut Connection| -> Result<(), rusqlite::Error> {
+            let tx = conn.transaction()?;
+            tx.execute_batch(sql)?;
+            tx.execute(
+                "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+                params![version, description, chrono::Utc::now().timestamp()],
+            )?;
+            tx.commit()
+        };
+
+        apply(conn).map_err(|source| SchemaError::Migration { version, description, source })?;
+        tracing::info!(version, "Applied schema migration: {}", description);
+    }
+
+    Ok(())
+}
+
+/// Keyset pagination over block numbers: the page between `after` and `before` (both
+/// exclusive). With only `after` it's the blocks right after it, otherwise the newest ones
+/// below `before`. Pages are always returned newest first.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct Cursor {
+    pub before: Option<u32>,
+    pub after: Option<u32>,
+}
+
+impl Cursor {
+    fn ascending(&self) -> bool {
+        self.after.is_some() && self.before.is_none()
+    }
+
+    // As SQL bounds, which block numbers can't reach
+    fn bounds(&self) -> (i64, i64) {
+        (
+            self.before.map_or(i64::from(u32::MAX) + 1, i64::from),
+            self.after.map_or(-1, i64::from),
+        )
+    }
+}
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct EventFilter {
     pub pallet: String,
-    pub method: Option<String>, // None means exclude all events from this pallet
+    pub method: Option<String>, // None means all events from this pallet
+}
+
+impl EventFilter {
+    pub fn matches(&self, pallet: &str, method: &str) -> bool {
+        self.pallet == pallet && self.method.as_deref().is_none_or(|m| m == method)
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -15,79 +605,768 @@ pub struct StoredBlock {
     pub hash: String,
     pub extrinsics: Vec<serde_json::Value>,
     pub timestamp: i64,
+    /// Missing on blocks stored by versions that didn't keep headers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<StoredHeader>,
+    /// Whether the block hash and extrinsics root matched the data when it was stored.
+    /// Missing on blocks stored by versions that didn't check
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+}
+
+/// The header fields that aren't already in `StoredBlock`, hex-encoded like Substrate's RPC does.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredHeader {
+    pub parent_hash: String,
+    pub state_root: String,
+    pub extrinsics_root: String,
+    /// SCALE-encoded digest items
+    pub digest: Vec<String>,
+}
+
+impl StoredHeader {
+    /// The header as the chain encodes it, so its Blake2-256 hash is the block hash.
+    /// None if a stored field isn't valid hex.
+    pub fn encode(&self, number: u32) -> Option<Vec<u8>> {
+        let bytes = |hex_str: &str| hex::decode(hex_str.trim_start_matches("0x")).ok();
+        let mut encoded = bytes(&self.parent_hash)?;
+        Compact(number).encode_to(&mut encoded);
+        encoded.extend(bytes(&self.state_root)?);
+        encoded.extend(bytes(&self.extrinsics_root)?);
+        Compact(self.digest.len() as u32).encode_to(&mut encoded);
+        for item in &self.digest {
+            encoded.extend(bytes(item)?);
+        }
+        Some(encoded)
+    }
+}
+
+/// One event out of a stored block, see `Database::find_events`.
+#[derive(Clone, Debug)]
+pub struct StoredEvent {
+    pub block_number: u32,
+    pub block_hash: String,
+    pub timestamp: i64,
+    pub extrinsic_index: u32,
+    /// Position among the extrinsic's stored events
+    pub event_index: usize,
+    pub pallet: String,
+    pub variant: String,
+    pub data: String,
+}
+
+/// One call out of a stored block, see `Database::find_calls`.
+#[derive(Clone, Debug, Serialize)]
+pub struct StoredCall {
+    pub block_number: u32,
+    pub block_hash: String,
+    pub extrinsic_index: u32,
+    /// 0 for the extrinsic's own call, 1 for a call it wraps, and so on
+    pub depth: u32,
+    pub pallet: String,
+    pub call: String,
+    pub params: serde_json::Value,
+}
+
+/// A Scheduler task, see `scheduler.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct ScheduledTask {
+    pub when: u32,
+    pub index: u32,
+    /// Missing if we only saw the task run or get canceled
+    pub scheduled_in: Option<u32>,
+    /// The scheduled call, when it could be found
+    pub call: Option<serde_json::Value>,
+    /// "scheduled", "dispatched", "failed" or "canceled"
+    pub status: String,
+}
+
+/// A submitted referendum, see `referenda.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Referendum {
+    pub index: u32,
+    pub track: u32,
+    pub submitted_in: u32,
+    /// Missing for proposals inlined in the referendum
+    pub proposal_hash: Option<String>,
+    pub proposal_len: Option<u32>,
+    /// The decoded call, once its preimage is known
+    pub proposal: Option<serde_json::Value>,
+}
+
+/// An asset of the Assets pallet, see `assets.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Asset {
+    pub id: u32,
+    /// Missing for assets created before indexing started
+    pub created_in: Option<u32>,
+    pub owner: Option<String>,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+    pub destroyed_in: Option<u32>,
+}
+
+/// A mint, burn or transfer of an asset.
+#[derive(Clone, Debug, Serialize)]
+pub struct AssetTransfer {
+    pub block_number: u32,
+    pub extrinsic_index: Option<u32>,
+    /// "transfer", "mint" or "burn"
+    pub kind: String,
+    /// Missing for mints
+    pub from: Option<String>,
+    /// Missing for burns
+    pub to: Option<String>,
+    /// In the asset's smallest unit, as a string since it doesn't fit in JSON numbers
+    pub amount: String,
+}
+
+/// An item of an `Nfts` or `Uniques` collection, see `nfts.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct NftItem {
+    pub id: u32,
+    pub owner: Option<String>,
+    /// Missing for items minted before indexing started
+    pub minted_in: Option<u32>,
+    pub burned_in: Option<u32>,
+    /// Attribute values as text, or hex when they aren't UTF-8
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// An account's vesting schedules as of its last `Vesting` event, see `vesting.rs`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VestingSchedule {
+    /// Amounts are strings since they don't fit in JSON numbers
+    pub locked: String,
+    pub per_block: String,
+    pub starting_block: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Vesting {
+    pub schedules: Vec<VestingSchedule>,
+    /// From the last `VestingUpdated` event, None after `VestingCompleted`
+    pub unvested: Option<String>,
+    pub updated_in: u32,
+}
+
+/// A lock, reserve or freeze of an account's balance, or its release, see `locks.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct BalanceChange {
+    pub block_number: u32,
+    pub extrinsic_index: Option<u32>,
+    /// "reserve", "unreserve", "lock", "unlock", "freeze" or "thaw"
+    pub kind: String,
+    /// The pallet it was for, when it could be told
+    pub source: Option<String>,
+    pub amount: String,
+}
+
+/// A parachain block backed or included in a relay chain block, see `parachains.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct ParaInclusion {
+    pub relay_block: u32,
+    pub para_id: u32,
+    /// "backed" or "included"
+    pub status: String,
+    pub para_block_hash: String,
+    /// Read from the parachain header, missing if it couldn't be decoded
+    pub para_block_number: Option<u32>,
+    /// The relay block the candidate was built on
+    pub relay_parent: String,
+}
+
+/// An XCM message sent or processed on this chain, see `xcm.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct XcmMessage {
+    pub block_number: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extrinsic_index: Option<u32>,
+    pub message_id: String,
+    /// "sent" or "received"
+    pub direction: String,
+    /// Destination of a sent message, origin of a received one
+    pub counterparty: Option<String>,
+    /// Whether a received message executed fine
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<bool>,
+}
+
+/// An `Offences::Offence` report, see `offences.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Offence {
+    pub block_number: u32,
+    /// e.g. "babe:equivocatio" (kinds are cut to 16 bytes)
+    pub kind: String,
+    pub time_slot: String,
+    /// Every account reported for this kind and time slot so far
+    pub offenders: Vec<String>,
+}
+
+/// A BABE epoch, see `babe.rs`. The randomness and authorities are announced an epoch ahead,
+/// and the start once it begins, so either half can be missing at the edges of what we indexed.
+#[derive(Clone, Debug, Serialize)]
+pub struct Epoch {
+    pub index: u64,
+    pub start_block: Option<u32>,
+    pub start_slot: Option<u64>,
+    pub authorities: Option<u32>,
+    pub randomness: Option<String>,
+    /// Blocks per slot kind ("primary", "secondary_plain", "secondary_vrf") among the stored ones
+    pub slots: BTreeMap<String, u32>,
+}
+
+/// An `ElectionProviderMultiPhase` event, see `election.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct ElectionEvent {
+    pub block_number: u32,
+    /// Only phase transitions say which round they're in
+    pub round: Option<u32>,
+    /// "phase", "solution_stored", "finalized" or "failed"
+    pub kind: String,
+    /// The new phase, or how the solution was computed
+    pub detail: Option<String>,
+}
+
+/// A validator's reward points in an era, see `era_points.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct EraPoints {
+    pub era: u32,
+    pub points: u32,
+    /// Of all validators that era
+    pub total_points: u32,
+    pub validators: u32,
+    /// 1 for the validator with the most points
+    pub rank: u32,
+}
+
+/// What the database takes up, and how fast it grows, for `/stats/storage`.
+#[derive(Clone, Debug, Serialize)]
+pub struct StorageStats {
+    /// Including free pages left behind by pruning
+    pub file_bytes: u64,
+    pub used_bytes: u64,
+    pub rows: BTreeMap<String, u64>,
+    /// Of the block JSON alone, over the latest blocks
+    pub avg_block_bytes: Option<u64>,
+    /// Everything stored (side tables and indexes too) per block
+    pub bytes_per_block: Option<u64>,
+    /// From how fast the latest blocks were stored, so it's inflated while catching up
+    pub blocks_per_day: Option<f64>,
+    pub growth_per_day_bytes: Option<u64>,
+}
+
+/// An `ImOnline` session end that reported validators offline, see `validators.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct OfflineReport {
+    pub block_number: u32,
+    /// SS58 addresses
+    pub validators: Vec<String>,
+}
+
+/// How a validator did over what we indexed, see `validators.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct ValidatorPerformance {
+    pub blocks_authored: u32,
+    /// BABE epochs it was in the validator set for
+    pub epochs_active: u32,
+    /// `ImOnline` session ends while it was active, and how many of those reported it offline
+    pub heartbeat_sessions: u32,
+    pub offline: u32,
+    /// None without any heartbeat sessions, e.g. on runtimes without `ImOnline`
+    pub uptime: Option<f64>,
+    pub heartbeats: u32,
+    pub last_heartbeat: Option<u32>,
+    pub last_offline: Option<u32>,
+    /// Over the latest eras it earned points in
+    pub eras: u32,
+    pub points: u32,
+    pub average_points: f64,
+    /// `points / average_points`, 1.0 is an average validator
+    pub points_vs_average: Option<f64>,
+}
+
+/// An account on the watchlist, see `watchlist.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Watch {
+    /// As it was registered
+    pub address: String,
+    pub label: Option<String>,
+    /// Alert channels to post its activity to
+    pub channels: Vec<String>,
+    pub added_at: i64,
+}
+
+/// A webhook bound to an account, see `webhooks.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Webhook {
+    pub id: i64,
+    /// As it was registered
+    pub address: String,
+    pub url: String,
+    pub created_at: i64,
+}
+
+/// An alert rule registered through `/admin/subscriptions`, see `subscriptions.rs`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Subscription {
+    pub id: i64,
+    pub name: String,
+    pub pallet: Option<String>,
+    pub variant: Option<String>,
+    pub data_contains: Option<String>,
+    /// Names of entries in `[alert_channels]`
+    pub channels: Vec<String>,
+    pub created_at: i64,
+}
+
+/// A block that arrived with another hash than the one stored under its number, which it
+/// replaced. Served on `/admin/conflicts`.
+#[derive(Clone, Debug, Serialize)]
+pub struct BlockConflict {
+    pub id: i64,
+    pub block_number: u32,
+    pub old_hash: String,
+    pub new_hash: String,
+    pub replaced_at: i64,
 }
 
 pub struct Database {
     conn: Arc<Mutex<Connection>>,
-    event_filters: Vec<EventFilter>,
-    extrinsic_filters: Vec<String>,
+    event_filters: RwLock<Vec<EventFilter>>,
+    extrinsic_filters: RwLock<Vec<String>>,
+    /// When set, only these are stored (minus the excluded ones)
+    only_events: RwLock<Vec<EventFilter>>,
+    only_extrinsics: RwLock<Vec<EventFilter>>,
+    /// The filters the above were parsed from, as configured
+    filters: RwLock<Filters>,
+    /// Mirrors the `watchlist` table, the indexer checks it for every extrinsic and event
+    watchlist: RwLock<HashMap<[u8; 32], Watch>>,
+    /// Mirrors the `webhooks` table, by account
+    webhooks: RwLock<HashMap<[u8; 32], Vec<Webhook>>>,
+}
+
+/// Tables with rows recorded from a single block, and the column that says which
+const BLOCK_TABLES: &[(&str, &str)] = &[
+    ("asset_transfers", "block_number"),
+    ("balance_changes", "block_number"),
+    ("para_inclusions", "relay_block"),
+    ("xcm_messages", "block_number"),
+    ("offences", "block_number"),
+    ("justifications", "block_number"),
+    ("block_slots", "block_number"),
+    ("election_events", "block_number"),
+    ("heartbeat_sessions", "block_number"),
+    ("offline_validators", "block_number"),
+    ("heartbeats", "block_number"),
+];
+// `block_conflicts` isn't one of them: it's an audit log, kept when the blocks it's about go
+
+// Moves the `PRUNED` cursor up to `last`, never back
+fn mark_pruned(conn: &Connection, last: u32) -> Result<(), rusqlite::Error> {
+    let hash: Option<String> = conn
+        .query_row("SELECT block_hash FROM blocks WHERE block_number = ?1", params![last], |row| row.get(0))
+        .optional()?;
+    conn.execute(
+        "INSERT INTO cursors (name, block_number, block_hash, updated_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (name) DO UPDATE SET
+            block_number = excluded.block_number,
+            block_hash = excluded.block_hash,
+            updated_at = excluded.updated_at
+         WHERE excluded.block_number > cursors.block_number",
+        params![PRUNED, last, hash.unwrap_or_default(), chrono::Utc::now().timestamp()],
+    )?;
+    Ok(())
+}
+
+// Deletes what was recorded from blocks `first..=last` out of every `BLOCK_TABLES` table
+fn delete_recorded(conn: &Connection, first: u32, last: u32) -> Result<(), rusqlite::Error> {
+    for (table, column) in BLOCK_TABLES {
+        conn.execute(
+            &format!("DELETE FROM {} WHERE {} BETWEEN ?1 AND ?2", table, column),
+            params![first, last],
+        )?;
+    }
+    Ok(())
+}
+
+fn apply_pragmas(conn: &Connection, sqlite: &SqliteConfig) -> Result<(), rusqlite::Error> {
+    let journal_mode = format!("{:?}", sqlite.journal_mode).to_lowercase();
+    // Answers with the mode it ended up in, which is "memory" for in-memory databases
+    conn.pragma_update_and_check(None, "journal_mode", &journal_mode, |_| Ok(()))?;
+    conn.pragma_update(None, "synchronous", format!("{:?}", sqlite.synchronous).to_lowercase())?;
+    // A negative cache_size is in KiB rather than pages
+    conn.pragma_update(None, "cache_size", -((sqlite.cache_size.0 / 1024) as i64))?;
+    conn.pragma_update_and_check(None, "mmap_size", sqlite.mmap_size.0 as i64, |_| Ok(()))?;
+    conn.busy_timeout(Duration::from_millis(sqlite.busy_timeout_ms))?;
+    Ok(())
+}
+
+// The slowest statement `[sqlite] slow_query_ms` lets by unlogged, 0 when off. Global since the
+// profiling hook can't capture anything
+static SLOW_QUERY_MS: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // The statement the trace hook last saw start on this thread, with its parameters filled in
+    static CURRENT_STATEMENT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+fn trace_statement(sql: &str) {
+    // Statements triggers run are traced as "-- trigger name", keep the one that fired them
+    if !sql.starts_with("--") {
+        CURRENT_STATEMENT.with(|current| sql.clone_into(&mut current.borrow_mut()));
+    }
+}
+
+fn profile_statement(sql: &str, took: Duration) {
+    let threshold = SLOW_QUERY_MS.load(Ordering::Relaxed);
+    if took.as_millis() < u128::from(threshold) {
+        return;
+    }
+    CURRENT_STATEMENT.with(|current| {
+        let current = current.borrow();
+        let sql = if current.is_empty() { sql } else { current.as_str() };
+        tracing::warn!(took_ms = took.as_millis() as u64, sql = %sql.split_whitespace().collect::<Vec<_>>().join(" "), "Slow query");
+    });
+}
+
+fn log_slow_queries(conn: &mut Connection, sqlite: &SqliteConfig) {
+    let Some(threshold) = sqlite.slow_query_ms.filter(|ms| *ms > 0) else {
+        return;
+    };
+    SLOW_QUERY_MS.store(threshold, Ordering::Relaxed);
+    conn.trace(Some(trace_statement));
+    conn.profile(Some(profile_statement));
 }
 
 impl Database {
     pub fn new<P: AsRef<Path>>(
         path: P,
-        event_filters: Vec<EventFilter>,
-        extrinsic_filters: Vec<String>,
-    ) -> Result<Self, rusqlite::Error> {
-        let conn = Connection::open(path)?;
+        sqlite: &SqliteConfig,
+    ) -> Result<Self, SchemaError> {
+        let mut conn = Connection::open(path)?;
+        // Only takes on new, empty databases. Older ones switch on their first compaction.
+        conn.pragma_update(None, "auto_vacuum", "incremental")?;
+        apply_pragmas(&conn, sqlite)?;
+        migrate(&mut conn, MIGRATIONS)?;
+        log_slow_queries(&mut conn, sqlite);
+        // Statements are prepared once and reused, with room for all of the hot ones
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        let watchlist = load_watchlist(&conn)?;
+        let webhooks = load_webhooks(&conn)?;
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS blocks (
-                block_number INTEGER PRIMARY KEY,
-                block_hash TEXT NOT NULL,
-                block_data TEXT NOT NULL,
-                timestamp INTEGER NOT NULL
-            )",
-            [],
-        )?;
+        Ok(Database {
+            conn: Arc::new(Mutex::new(conn)),
+            event_filters: RwLock::new(vec![]),
+            extrinsic_filters: RwLock::new(vec![]),
+            only_events: RwLock::new(vec![]),
+            only_extrinsics: RwLock::new(vec![]),
+            filters: RwLock::new(Filters::default()),
+            watchlist: RwLock::new(watchlist),
+            webhooks: RwLock::new(webhooks),
+        })
+    }
 
-        // Create index on timestamp for range queries
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_timestamp ON blocks(timestamp)",
-            [],
-        )?;
+    /// For serving a database another smolcar indexes: nothing is written, not even migrations,
+    /// so the schema has to be current already.
+    pub fn open_read_only<P: AsRef<Path>>(path: P, sqlite: &SqliteConfig) -> Result<Self, SchemaError> {
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let mut conn = Connection::open_with_flags(path, flags)?;
+        log_slow_queries(&mut conn, sqlite);
+        // The journal mode and syncing are the indexer's to pick
+        conn.pragma_update(None, "cache_size", -((sqlite.cache_size.0 / 1024) as i64))?;
+        conn.pragma_update_and_check(None, "mmap_size", sqlite.mmap_size.0 as i64, |_| Ok(()))?;
+        conn.busy_timeout(Duration::from_millis(sqlite.busy_timeout_ms))?;
+        let found: u32 =
+            conn.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", [], |row| row.get(0))?;
+        let latest = MIGRATIONS.len() as u32;
+        if found > latest {
+            return Err(SchemaError::TooNew { found, supported: latest });
+        }
+        if found < latest {
+            return Err(SchemaError::Behind { found, latest });
+        }
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        let watchlist = load_watchlist(&conn)?;
+        let webhooks = load_webhooks(&conn)?;
 
         Ok(Database {
             conn: Arc::new(Mutex::new(conn)),
-            event_filters,
-            extrinsic_filters,
+            event_filters: RwLock::new(vec![]),
+            extrinsic_filters: RwLock::new(vec![]),
+            only_events: RwLock::new(vec![]),
+            only_extrinsics: RwLock::new(vec![]),
+            filters: RwLock::new(Filters::default()),
+            watchlist: RwLock::new(watchlist),
+            webhooks: RwLock::new(webhooks),
         })
     }
 
+    /// Runs `f` on tokio's blocking pool. Every call from async code goes through here, so a
+    /// slow query or a big block write doesn't hold up the other tasks on its worker thread.
+    /// Trackers don't write while processing a block, they hand their writes to the block's
+    /// (see `indexer::TrackerUpdates`).
+    pub async fn blocking<T, F>(self: &Arc<Self>, f: F) -> T
+    where
+        F: FnOnce(&Database) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let db = self.clone();
+        match tokio::task::spawn_blocking(move || f(&db)).await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
     pub fn should_include_event(&self, pallet: &str, method: &str) -> bool {
-        for filter in &self.event_filters {
-            if filter.pallet == pallet {
-                match &filter.method {
-                    None => return false, // Exclude all events from this pallet
-                    Some(m) if m == method => return false, // Exclude this specific method
-                    _ => {}
-                }
-            }
+        let only = self.only_events.read().unwrap();
+        if !only.is_empty() && !only.iter().any(|filter| filter.matches(pallet, method)) {
+            return false;
         }
-        true
+        !self.event_filters.read().unwrap().iter().any(|filter| filter.matches(pallet, method))
     }
 
     pub fn should_include_extrinsic(&self, action: &str) -> bool {
-        !self.extrinsic_filters.contains(&action.to_string())
+        let only = self.only_extrinsics.read().unwrap();
+        let (pallet, method) = action.split_once('/').unwrap_or((action, ""));
+        if !only.is_empty() && !only.iter().any(|filter| filter.matches(pallet, method)) {
+            return false;
+        }
+        !self.extrinsic_filters.read().unwrap().iter().any(|f| f == action)
+    }
+
+    /// Swaps the filters, e.g. after the config was reloaded. Applies to blocks stored from now on.
+    pub fn set_filters(&self, filters: &Filters) {
+        *self.event_filters.write().unwrap() = filters.event_filters();
+        *self.extrinsic_filters.write().unwrap() = filters.exclude_extrinsics.clone();
+        *self.only_events.write().unwrap() = Filters::parse(&filters.only_events);
+        *self.only_extrinsics.write().unwrap() = Filters::parse(&filters.only_extrinsics);
+        *self.filters.write().unwrap() = filters.clone();
+    }
+
+    pub fn filters(&self) -> Filters {
+        self.filters.read().unwrap().clone()
+    }
+
+    pub fn has_watchlist(&self) -> bool {
+        !self.watchlist.read().unwrap().is_empty()
+    }
+
+    pub fn watched(&self, account: &[u8; 32]) -> Option<Watch> {
+        self.watchlist.read().unwrap().get(account).cloned()
+    }
+
+    /// Adds the account to the watchlist, or replaces its entry.
+    pub fn watch(&self, account: [u8; 32], watch: Watch) -> Result<(), rusqlite::Error> {
+        let channels = serde_json::to_string(&watch.channels)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO watchlist (account, address, label, channels, added_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![format!("0x{}", hex::encode(account)), watch.address, watch.label, channels, watch.added_at],
+        )?;
+        self.watchlist.write().unwrap().insert(account, watch);
+        Ok(())
+    }
+
+    /// Whether the account was on the watchlist.
+    pub fn unwatch(&self, account: &[u8; 32]) -> Result<bool, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM watchlist WHERE account = ?1",
+            params![format!("0x{}", hex::encode(account))],
+        )?;
+        Ok(self.watchlist.write().unwrap().remove(account).is_some())
+    }
+
+    /// The watchlist, oldest entry first.
+    pub fn get_watchlist(&self) -> Vec<Watch> {
+        let mut watchlist: Vec<Watch> = self.watchlist.read().unwrap().values().cloned().collect();
+        watchlist.sort_by_key(|watch| watch.added_at);
+        watchlist
+    }
+
+    pub fn has_webhooks(&self) -> bool {
+        !self.webhooks.read().unwrap().is_empty()
+    }
+
+    pub fn webhooks_for(&self, account: &[u8; 32]) -> Vec<Webhook> {
+        self.webhooks.read().unwrap().get(account).cloned().unwrap_or_default()
+    }
+
+    pub fn add_webhook(&self, account: [u8; 32], address: &str, url: &str) -> Result<Webhook, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let created_at = chrono::Utc::now().timestamp();
+        conn.execute(
+            "INSERT INTO webhooks (account, address, url, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![format!("0x{}", hex::encode(account)), address, url, created_at],
+        )?;
+        let webhook = Webhook {
+            id: conn.last_insert_rowid(),
+            address: address.to_string(),
+            url: url.to_string(),
+            created_at,
+        };
+        self.webhooks.write().unwrap().entry(account).or_default().push(webhook.clone());
+        Ok(webhook)
+    }
+
+    /// Whether there was a webhook with this id.
+    pub fn remove_webhook(&self, id: i64) -> Result<bool, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        if conn.execute("DELETE FROM webhooks WHERE id = ?1", params![id])? == 0 {
+            return Ok(false);
+        }
+        let mut webhooks = self.webhooks.write().unwrap();
+        webhooks.values_mut().for_each(|hooks| hooks.retain(|hook| hook.id != id));
+        webhooks.retain(|_, hooks| !hooks.is_empty());
+        Ok(true)
+    }
+
+    /// Every webhook, oldest first.
+    pub fn get_webhooks(&self) -> Vec<Webhook> {
+        let mut webhooks: Vec<Webhook> = self.webhooks.read().unwrap().values().flatten().cloned().collect();
+        webhooks.sort_by_key(|hook| hook.id);
+        webhooks
+    }
+
+    /// Every alert subscription, oldest first.
+    pub fn get_subscriptions(&self) -> Result<Vec<Subscription>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, pallet, variant, data_contains, channels, created_at FROM subscriptions ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let channels: String = row.get(5)?;
+            Ok(Subscription {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                pallet: row.get(2)?,
+                variant: row.get(3)?,
+                data_contains: row.get(4)?,
+                channels: serde_json::from_str(&channels).unwrap_or_default(),
+                created_at: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Stores a new subscription, and returns it with its id and creation time.
+    pub fn add_subscription(&self, mut subscription: Subscription) -> Result<Subscription, rusqlite::Error> {
+        let channels = serde_json::to_string(&subscription.channels)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let conn = self.conn.lock().unwrap();
+        subscription.created_at = chrono::Utc::now().timestamp();
+        conn.execute(
+            "INSERT INTO subscriptions (name, pallet, variant, data_contains, channels, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                subscription.name,
+                subscription.pallet,
+                subscription.variant,
+                subscription.data_contains,
+                channels,
+                subscription.created_at
+            ],
+        )?;
+        subscription.id = conn.last_insert_rowid();
+        Ok(subscription)
+    }
+
+    /// Replaces the rule and channels of the subscription with the same id, whether there was one.
+    pub fn update_subscription(&self, subscription: &Subscription) -> Result<bool, rusqlite::Error> {
+        let channels = serde_json::to_string(&subscription.channels)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE subscriptions SET name = ?2, pallet = ?3, variant = ?4, data_contains = ?5, channels = ?6
+             WHERE id = ?1",
+            params![
+                subscription.id,
+                subscription.name,
+                subscription.pallet,
+                subscription.variant,
+                subscription.data_contains,
+                channels
+            ],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Whether there was a subscription with this id.
+    pub fn remove_subscription(&self, id: i64) -> Result<bool, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        Ok(conn.execute("DELETE FROM subscriptions WHERE id = ?1", params![id])? > 0)
     }
 
     pub fn store_block(&self, block: &StoredBlock) -> Result<(), rusqlite::Error> {
         let block_data_json = serde_json::to_string(block)
             .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
 
+        self.insert_block(block.number, &block.hash, &block_data_json, block.timestamp)
+    }
+
+    /// Same as `store_block`, but takes the extrinsics already serialized as a JSON array,
+    /// so callers don't need to hold the decoded block in memory.
+    pub fn store_block_json(
+        &self,
+        number: u32,
+        hash: &str,
+        extrinsics_json: &str,
+        timestamp: i64,
+        header: Option<&StoredHeader>,
+        verified: Option<bool>,
+    ) -> Result<(), rusqlite::Error> {
+        // Must produce exactly what serializing a StoredBlock would
+        let header_json = match header {
+            Some(header) => format!(r#","header":{}"#, serde_json::to_string(header).unwrap()),
+            None => String::new(),
+        };
+        let verified_json = match verified {
+            Some(verified) => format!(r#","verified":{}"#, verified),
+            None => String::new(),
+        };
+        let block_data_json = format!(
+            r#"{{"number":{},"hash":{},"extrinsics":{},"timestamp":{}{}{}}}"#,
+            number,
+            serde_json::Value::from(hash),
+            extrinsics_json,
+            timestamp,
+            header_json,
+            verified_json
+        );
+
+        self.insert_block(number, hash, &block_data_json, timestamp)
+    }
+
+    fn insert_block(
+        &self,
+        number: u32,
+        hash: &str,
+        block_data_json: &str,
+        timestamp: i64,
+    ) -> Result<(), rusqlite::Error> {
+        let _timer = metrics::time(DbOperation::Store);
         let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT OR REPLACE INTO blocks (block_number, block_hash, block_data, timestamp)
-             VALUES (?1, ?2, ?3, ?4)",
-            params![block.number, block.hash, block_data_json, block.timestamp],
+        let mut stmt = conn.prepare_cached(
+            "INSERT INTO blocks (block_number, block_hash, block_data, timestamp)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (block_number) DO UPDATE SET
+                block_hash = excluded.block_hash,
+                block_data = excluded.block_data,
+                timestamp = excluded.timestamp",
         )?;
+        stmt.execute(params![number, hash, block_data_json, timestamp])?;
 
         Ok(())
     }
 
     pub fn get_block(&self, block_number: u32) -> Result<Option<StoredBlock>, rusqlite::Error> {
+        let _timer = metrics::time(DbOperation::Get);
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
+        let mut stmt = conn.prepare_cached(
             "SELECT block_data FROM blocks WHERE block_number = ?1"
         )?;
 
@@ -107,40 +1386,1502 @@ impl Database {
         }
     }
 
-    pub fn get_latest_block_number(&self) -> Result<Option<u32>, rusqlite::Error> {
+    /// Just the hash column, without parsing the block.
+    pub fn get_block_hash(&self, block_number: u32) -> Result<Option<String>, rusqlite::Error> {
+        let _timer = metrics::time(DbOperation::Get);
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare("SELECT MAX(block_number) FROM blocks")?;
-        let mut rows = stmt.query([])?;
+        let mut stmt = conn.prepare_cached("SELECT block_hash FROM blocks WHERE block_number = ?1")?;
+        stmt.query_row(params![block_number], |row| row.get(0)).optional()
+    }
 
-        if let Some(row) = rows.next()? {
-            Ok(row.get(0)?)
-        } else {
-            Ok(None)
-        }
+    pub fn get_cursor(&self, name: &str) -> Result<Option<(u32, String)>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT block_number, block_hash FROM cursors WHERE name = ?1")?;
+        stmt.query_row(params![name], |row| Ok((row.get(0)?, row.get(1)?))).optional()
     }
 
-    pub fn get_blocks_range(&self, start: u32, end: u32, limit: u32) -> Result<Vec<StoredBlock>, rusqlite::Error> {
+    pub fn set_cursor(&self, name: &str, block_number: u32, block_hash: &str) -> Result<(), rusqlite::Error> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT block_data FROM blocks
-             WHERE block_number >= ?1 AND block_number <= ?2
-             ORDER BY block_number DESC
-             LIMIT ?3"
+        conn.execute(
+            "INSERT INTO cursors (name, block_number, block_hash, updated_at) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (name) DO UPDATE SET
+                block_number = excluded.block_number,
+                block_hash = excluded.block_hash,
+                updated_at = excluded.updated_at",
+            params![name, block_number, block_hash, chrono::Utc::now().timestamp()],
         )?;
+        Ok(())
+    }
 
-        let rows = stmt.query_map(params![start, end, limit], |row| {
-            let block_data_json: String = row.get(0)?;
-            Ok(block_data_json)
+    pub fn delete_cursor(&self, name: &str) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM cursors WHERE name = ?1", params![name])?;
+        Ok(())
+    }
+
+    /// Audit log entry for a stored block about to be overwritten by one with another hash.
+    pub fn record_conflict(&self, block_number: u32, old_hash: &str, new_hash: &str) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO block_conflicts (block_number, old_hash, new_hash, replaced_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![block_number, old_hash, new_hash, chrono::Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// The latest `limit` conflicts, newest first, optionally only those before id `before`.
+    pub fn get_conflicts(&self, before: Option<i64>, limit: u32) -> Result<Vec<BlockConflict>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, block_number, old_hash, new_hash, replaced_at FROM block_conflicts
+             WHERE id < ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![before.unwrap_or(i64::MAX), limit], |row| {
+            Ok(BlockConflict {
+                id: row.get(0)?,
+                block_number: row.get(1)?,
+                old_hash: row.get(2)?,
+                new_hash: row.get(3)?,
+                replaced_at: row.get(4)?,
+            })
         })?;
+        rows.collect()
+    }
 
-        let mut blocks = Vec::new();
-        for row in rows {
-            let block_data_json = row?;
-            if let Ok(block) = serde_json::from_str(&block_data_json) {
-                blocks.push(block);
-            }
-        }
+    /// A task was scheduled, replacing whatever last used the same `(when, index)` slot unless
+    /// that was updated in a later block.
+    pub fn record_scheduled_task(
+        &self,
+        when: u32,
+        index: u32,
+        block_number: u32,
+        call_json: Option<&str>,
+    ) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO scheduled_tasks (when_block, task_index, scheduled_in, call, status, updated_in)
+             VALUES (?1, ?2, ?3, ?4, 'scheduled', ?3)
+             ON CONFLICT (when_block, task_index) DO UPDATE SET
+                scheduled_in = ?3, call = ?4, status = 'scheduled', updated_in = ?3
+             WHERE ?3 >= scheduled_tasks.updated_in",
+            params![when, index, block_number, call_json],
+        )?;
+        Ok(())
+    }
 
-        Ok(blocks)
+    pub fn set_scheduled_task_status(
+        &self,
+        when: u32,
+        index: u32,
+        status: &str,
+        block_number: u32,
+    ) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO scheduled_tasks (when_block, task_index, status, updated_in)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (when_block, task_index) DO UPDATE SET status = ?3, updated_in = ?4
+             WHERE ?4 >= scheduled_tasks.updated_in",
+            params![when, index, status, block_number],
+        )?;
+        Ok(())
+    }
+
+    /// Tasks still scheduled to run after block `after`, soonest first.
+    pub fn get_upcoming_tasks(&self, after: u32, limit: u32) -> Result<Vec<ScheduledTask>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT when_block, task_index, scheduled_in, call, status FROM scheduled_tasks
+             WHERE status = 'scheduled' AND when_block > ?1
+             ORDER BY when_block ASC, task_index ASC
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![after, limit], |row| {
+            let call: Option<String> = row.get(3)?;
+            Ok(ScheduledTask {
+                when: row.get(0)?,
+                index: row.get(1)?,
+                scheduled_in: row.get(2)?,
+                call: call.and_then(|call| serde_json::from_str(&call).ok()),
+                status: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Keeps a proposal already resolved from its preimage when the same submission is recorded
+    /// again, and ignores submissions older than the stored one.
+    pub fn record_referendum(
+        &self,
+        index: u32,
+        track: u32,
+        block_number: u32,
+        proposal_hash: Option<&str>,
+        proposal_len: Option<u32>,
+        proposal_json: Option<&str>,
+    ) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO referenda
+             (referendum_index, track, submitted_in, proposal_hash, proposal_len, proposal)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (referendum_index) DO UPDATE SET
+                track = ?2,
+                submitted_in = ?3,
+                proposal_hash = ?4,
+                proposal_len = ?5,
+                proposal = CASE WHEN ?4 IS referenda.proposal_hash THEN COALESCE(?6, referenda.proposal) ELSE ?6 END
+             WHERE ?3 >= referenda.submitted_in",
+            params![index, track, block_number, proposal_hash, proposal_len, proposal_json],
+        )?;
+        Ok(())
+    }
+
+    /// `(index, proposal_len)` of referenda whose proposal is this preimage but isn't decoded yet.
+    pub fn referenda_awaiting_preimage(&self, proposal_hash: &str) -> Result<Vec<(u32, u32)>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT referendum_index, proposal_len FROM referenda
+             WHERE proposal_hash = ?1 AND proposal IS NULL AND proposal_len IS NOT NULL",
+        )?;
+        let rows = stmt.query_map(params![proposal_hash], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    pub fn set_referendum_proposal(&self, index: u32, proposal_json: &str) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE referenda SET proposal = ?2 WHERE referendum_index = ?1",
+            params![index, proposal_json],
+        )?;
+        Ok(())
+    }
+
+    /// The latest `limit` referenda, newest first.
+    pub fn get_referenda(&self, limit: u32) -> Result<Vec<Referendum>, rusqlite::Error> {
+        self.query_referenda("ORDER BY referendum_index DESC LIMIT ?1", params![limit])
+    }
+
+    pub fn get_referendum(&self, index: u32) -> Result<Option<Referendum>, rusqlite::Error> {
+        Ok(self.query_referenda("WHERE referendum_index = ?1", params![index])?.pop())
+    }
+
+    fn query_referenda(&self, clause: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<Referendum>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT referendum_index, track, submitted_in, proposal_hash, proposal_len, proposal
+             FROM referenda {}",
+            clause
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            let proposal: Option<String> = row.get(5)?;
+            Ok(Referendum {
+                index: row.get(0)?,
+                track: row.get(1)?,
+                submitted_in: row.get(2)?,
+                proposal_hash: row.get(3)?,
+                proposal_len: row.get(4)?,
+                proposal: proposal.and_then(|proposal| serde_json::from_str(&proposal).ok()),
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn has_asset(&self, asset_id: u32) -> Result<bool, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM assets WHERE asset_id = ?1)",
+            params![asset_id],
+            |row| row.get(0),
+        )
+    }
+
+    /// Ids can be reused once destroyed, so a creation only replaces an asset created and
+    /// destroyed before it, as does a destruction.
+    pub fn record_asset_created(&self, asset_id: u32, block_number: u32, owner: Option<&str>) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO assets (asset_id, created_in, owner) VALUES (?1, ?2, ?3)
+             ON CONFLICT (asset_id) DO UPDATE SET created_in = ?2, owner = ?3, destroyed_in = NULL
+             WHERE ?2 >= MAX(COALESCE(assets.created_in, 0), COALESCE(assets.destroyed_in, 0))",
+            params![asset_id, block_number, owner],
+        )?;
+        Ok(())
+    }
+
+    /// Sets (or with all None, clears) an asset's metadata as of `block_number`, adding the
+    /// asset if it's new to us. Metadata from a later block is kept.
+    pub fn set_asset_metadata(
+        &self,
+        asset_id: u32,
+        name: Option<&str>,
+        symbol: Option<&str>,
+        decimals: Option<u8>,
+        block_number: u32,
+    ) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO assets (asset_id, name, symbol, decimals, metadata_in) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (asset_id) DO UPDATE SET name = ?2, symbol = ?3, decimals = ?4, metadata_in = ?5
+             WHERE ?5 >= COALESCE(assets.metadata_in, 0)",
+            params![asset_id, name, symbol, decimals, block_number],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_asset_destroyed(&self, asset_id: u32, block_number: u32) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO assets (asset_id, destroyed_in) VALUES (?1, ?2)
+             ON CONFLICT (asset_id) DO UPDATE SET destroyed_in = ?2
+             WHERE ?2 >= MAX(COALESCE(assets.created_in, 0), COALESCE(assets.destroyed_in, 0))",
+            params![asset_id, block_number],
+        )?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_asset_transfer(
+        &self,
+        block_number: u32,
+        event_index: u32,
+        extrinsic_index: Option<u32>,
+        asset_id: u32,
+        kind: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+        amount: u128,
+    ) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO asset_transfers
+             (block_number, event_index, extrinsic_index, asset_id, kind, from_account, to_account, amount)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![block_number, event_index, extrinsic_index, asset_id, kind, from, to, amount.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// Assets by id, starting after `after`.
+    pub fn get_assets(&self, after: Option<u32>, limit: u32) -> Result<Vec<Asset>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT asset_id, created_in, owner, name, symbol, decimals, destroyed_in FROM assets
+             WHERE asset_id > ?1
+             ORDER BY asset_id ASC
+             LIMIT ?2",
+        )?;
+        let after = after.map_or(-1, i64::from);
+        let rows = stmt.query_map(params![after, limit], |row| {
+            Ok(Asset {
+                id: row.get(0)?,
+                created_in: row.get(1)?,
+                owner: row.get(2)?,
+                name: row.get(3)?,
+                symbol: row.get(4)?,
+                decimals: row.get(5)?,
+                destroyed_in: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// An asset's latest mints, burns and transfers, newest first.
+    pub fn get_asset_transfers(&self, asset_id: u32, limit: u32) -> Result<Vec<AssetTransfer>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT block_number, extrinsic_index, kind, from_account, to_account, amount FROM asset_transfers
+             WHERE asset_id = ?1
+             ORDER BY block_number DESC, event_index DESC
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![asset_id, limit], |row| {
+            Ok(AssetTransfer {
+                block_number: row.get(0)?,
+                extrinsic_index: row.get(1)?,
+                kind: row.get(2)?,
+                from: row.get(3)?,
+                to: row.get(4)?,
+                amount: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Like assets, a creation or destruction only replaces ones before it.
+    pub fn record_nft_collection(
+        &self,
+        pallet: &str,
+        collection_id: u32,
+        block_number: u32,
+        owner: Option<&str>,
+    ) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO nft_collections (pallet, collection_id, created_in, owner) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (pallet, collection_id) DO UPDATE SET created_in = ?3, owner = ?4, destroyed_in = NULL
+             WHERE ?3 >= MAX(COALESCE(nft_collections.created_in, 0), COALESCE(nft_collections.destroyed_in, 0))",
+            params![pallet, collection_id, block_number, owner],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_nft_collection_destroyed(&self, pallet: &str, collection_id: u32, block_number: u32) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO nft_collections (pallet, collection_id, destroyed_in) VALUES (?1, ?2, ?3)
+             ON CONFLICT (pallet, collection_id) DO UPDATE SET destroyed_in = ?3
+             WHERE ?3 >= MAX(COALESCE(nft_collections.created_in, 0), COALESCE(nft_collections.destroyed_in, 0))",
+            params![pallet, collection_id, block_number],
+        )?;
+        Ok(())
+    }
+
+    /// Records an item's mint ("issued"), transfer or burn. The owner is None once burned.
+    /// Ignored if the item was last updated in a later block.
+    pub fn record_nft_item(
+        &self,
+        pallet: &str,
+        collection_id: u32,
+        item_id: u32,
+        event: &str,
+        owner: Option<&str>,
+        block_number: u32,
+    ) -> Result<(), rusqlite::Error> {
+        let (minted_in, burned_in) = match event {
+            "issued" => (Some(block_number), None),
+            "burned" => (None, Some(block_number)),
+            _ => (None, None),
+        };
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO nft_items (pallet, collection_id, item_id, owner, minted_in, burned_in, updated_in)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (pallet, collection_id, item_id) DO UPDATE SET
+                owner = ?4,
+                minted_in = COALESCE(?5, minted_in),
+                burned_in = CASE WHEN ?5 IS NOT NULL THEN NULL ELSE COALESCE(?6, burned_in) END,
+                updated_in = ?7
+             WHERE ?7 >= nft_items.updated_in",
+            params![pallet, collection_id, item_id, owner, minted_in, burned_in, block_number],
+        )?;
+        Ok(())
+    }
+
+    /// Sets an item attribute, or clears it when `value` is None, unless it was set in a later block.
+    pub fn set_nft_attribute(
+        &self,
+        pallet: &str,
+        collection_id: u32,
+        item_id: u32,
+        key: &str,
+        value: Option<&str>,
+        block_number: u32,
+    ) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        match value {
+            Some(value) => conn.execute(
+                "INSERT INTO nft_attributes (pallet, collection_id, item_id, key, value, set_in)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (pallet, collection_id, item_id, key) DO UPDATE SET value = ?5, set_in = ?6
+                 WHERE ?6 >= nft_attributes.set_in",
+                params![pallet, collection_id, item_id, key, value, block_number],
+            )?,
+            None => conn.execute(
+                "DELETE FROM nft_attributes
+                 WHERE pallet = ?1 AND collection_id = ?2 AND item_id = ?3 AND key = ?4 AND set_in <= ?5",
+                params![pallet, collection_id, item_id, key, block_number],
+            )?,
+        };
+        Ok(())
+    }
+
+    /// A collection's items by id, starting after `after`, with their attributes.
+    pub fn get_nft_items(
+        &self,
+        pallet: &str,
+        collection_id: u32,
+        after: Option<u32>,
+        limit: u32,
+    ) -> Result<Vec<NftItem>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let after = after.map_or(-1, i64::from);
+        let mut stmt = conn.prepare_cached(
+            "SELECT item_id, owner, minted_in, burned_in FROM nft_items
+             WHERE pallet = ?1 AND collection_id = ?2 AND item_id > ?3
+             ORDER BY item_id ASC
+             LIMIT ?4",
+        )?;
+        let mut items = stmt
+            .query_map(params![pallet, collection_id, after, limit], |row| {
+                Ok(NftItem {
+                    id: row.get(0)?,
+                    owner: row.get(1)?,
+                    minted_in: row.get(2)?,
+                    burned_in: row.get(3)?,
+                    attributes: BTreeMap::new(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let Some(last) = items.last().map(|item| item.id) else {
+            return Ok(items);
+        };
+        let mut stmt = conn.prepare_cached(
+            "SELECT item_id, key, value FROM nft_attributes
+             WHERE pallet = ?1 AND collection_id = ?2 AND item_id > ?3 AND item_id <= ?4",
+        )?;
+        let mut rows = stmt.query(params![pallet, collection_id, after, last])?;
+        while let Some(row) = rows.next()? {
+            let item_id: u32 = row.get(0)?;
+            if let Ok(i) = items.binary_search_by_key(&item_id, |item| item.id) {
+                items[i].attributes.insert(row.get(1)?, row.get(2)?);
+            }
+        }
+        Ok(items)
+    }
+
+    /// `account` is the account's hex, so any SS58 format finds it. Schedules from a later block
+    /// are kept.
+    pub fn set_vesting(
+        &self,
+        account: &str,
+        schedules: &[VestingSchedule],
+        unvested: Option<&str>,
+        block_number: u32,
+    ) -> Result<(), rusqlite::Error> {
+        let schedules = serde_json::to_string(schedules).unwrap();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO vesting (account, schedules, unvested, updated_in) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (account) DO UPDATE SET schedules = ?2, unvested = ?3, updated_in = ?4
+             WHERE ?4 >= vesting.updated_in",
+            params![account, schedules, unvested, block_number],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_vesting(&self, account: &str) -> Result<Option<Vesting>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT schedules, unvested, updated_in FROM vesting WHERE account = ?1",
+            params![account],
+            |row| {
+                let schedules: String = row.get(0)?;
+                Ok(Vesting {
+                    schedules: serde_json::from_str(&schedules).unwrap_or_default(),
+                    unvested: row.get(1)?,
+                    updated_in: row.get(2)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    /// `account` is the account's hex, like for vesting.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_balance_change(
+        &self,
+        block_number: u32,
+        event_index: u32,
+        extrinsic_index: Option<u32>,
+        account: &str,
+        kind: &str,
+        source: Option<&str>,
+        amount: u128,
+    ) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO balance_changes
+             (block_number, event_index, extrinsic_index, account, kind, source, amount)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![block_number, event_index, extrinsic_index, account, kind, source, amount.to_string()],
+        )?;
+        Ok(())
+    }
+
+    /// An account's latest balance changes, newest first.
+    pub fn get_balance_changes(&self, account: &str, limit: u32) -> Result<Vec<BalanceChange>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT block_number, extrinsic_index, kind, source, amount FROM balance_changes
+             WHERE account = ?1
+             ORDER BY block_number DESC, event_index DESC
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![account, limit], |row| {
+            Ok(BalanceChange {
+                block_number: row.get(0)?,
+                extrinsic_index: row.get(1)?,
+                kind: row.get(2)?,
+                source: row.get(3)?,
+                amount: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn record_para_inclusion(&self, inclusion: &ParaInclusion) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO para_inclusions
+             (relay_block, para_id, status, para_block_hash, para_block_number, relay_parent)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                inclusion.relay_block,
+                inclusion.para_id,
+                inclusion.status,
+                inclusion.para_block_hash,
+                inclusion.para_block_number,
+                inclusion.relay_parent,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// A parachain's latest backed and included candidates, newest first.
+    pub fn get_para_inclusions(&self, para_id: u32, limit: u32) -> Result<Vec<ParaInclusion>, rusqlite::Error> {
+        self.query_para_inclusions(
+            "WHERE para_id = ?1 ORDER BY relay_block DESC, status DESC LIMIT ?2",
+            params![para_id, limit],
+        )
+    }
+
+    /// Candidates backed or included in a relay block.
+    pub fn get_para_inclusions_in(&self, relay_block: u32) -> Result<Vec<ParaInclusion>, rusqlite::Error> {
+        self.query_para_inclusions("WHERE relay_block = ?1 ORDER BY para_id, status DESC", params![relay_block])
+    }
+
+    /// Where a parachain block was backed and included.
+    pub fn get_para_inclusions_of(&self, para_id: u32, para_block_hash: &str) -> Result<Vec<ParaInclusion>, rusqlite::Error> {
+        self.query_para_inclusions(
+            "WHERE para_block_hash = ?1 AND para_id = ?2 ORDER BY relay_block",
+            params![para_block_hash, para_id],
+        )
+    }
+
+    fn query_para_inclusions(&self, clause: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<ParaInclusion>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT relay_block, para_id, status, para_block_hash, para_block_number, relay_parent
+             FROM para_inclusions {}",
+            clause
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok(ParaInclusion {
+                relay_block: row.get(0)?,
+                para_id: row.get(1)?,
+                status: row.get(2)?,
+                para_block_hash: row.get(3)?,
+                para_block_number: row.get(4)?,
+                relay_parent: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn record_xcm_message(&self, event_index: u32, message: &XcmMessage) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO xcm_messages
+             (block_number, event_index, extrinsic_index, message_id, direction, counterparty, success)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                message.block_number,
+                event_index,
+                message.extrinsic_index,
+                message.message_id,
+                message.direction,
+                message.counterparty,
+                message.success,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Every time this chain sent or processed the message.
+    pub fn get_xcm_messages(&self, message_id: &str) -> Result<Vec<XcmMessage>, rusqlite::Error> {
+        self.query_xcm_messages("WHERE message_id = ?1 ORDER BY block_number, event_index", params![message_id])
+    }
+
+    /// The latest messages this chain sent, newest first.
+    pub fn get_sent_xcm_messages(&self, limit: u32) -> Result<Vec<XcmMessage>, rusqlite::Error> {
+        self.query_xcm_messages(
+            "WHERE direction = 'sent' ORDER BY block_number DESC, event_index DESC LIMIT ?1",
+            params![limit],
+        )
+    }
+
+    fn query_xcm_messages(&self, clause: &str, params: &[&dyn rusqlite::ToSql]) -> Result<Vec<XcmMessage>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT block_number, extrinsic_index, message_id, direction, counterparty, success
+             FROM xcm_messages {}",
+            clause
+        ))?;
+        let rows = stmt.query_map(params, |row| {
+            Ok(XcmMessage {
+                block_number: row.get(0)?,
+                extrinsic_index: row.get(1)?,
+                message_id: row.get(2)?,
+                direction: row.get(3)?,
+                counterparty: row.get(4)?,
+                success: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn record_offence(&self, event_index: u32, offence: &Offence) -> Result<(), rusqlite::Error> {
+        let offenders = serde_json::to_string(&offence.offenders).unwrap();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO offences (block_number, event_index, kind, time_slot, offenders)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![offence.block_number, event_index, offence.kind, offence.time_slot, offenders],
+        )?;
+        Ok(())
+    }
+
+    /// The latest offences, newest first.
+    pub fn get_offences(&self, limit: u32) -> Result<Vec<Offence>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT block_number, kind, time_slot, offenders FROM offences
+             ORDER BY block_number DESC, event_index DESC
+             LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit], |row| {
+            let offenders: String = row.get(3)?;
+            Ok(Offence {
+                block_number: row.get(0)?,
+                kind: row.get(1)?,
+                time_slot: row.get(2)?,
+                offenders: serde_json::from_str(&offenders).unwrap_or_default(),
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn store_justification(&self, block_number: u32, block_hash: &str, justification: &[u8]) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO justifications (block_number, block_hash, justification) VALUES (?1, ?2, ?3)",
+            params![block_number, block_hash, justification],
+        )?;
+        Ok(())
+    }
+
+    /// The block's hash and its encoded GRANDPA justification.
+    pub fn get_justification(&self, block_number: u32) -> Result<Option<(String, Vec<u8>)>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT block_hash, justification FROM justifications WHERE block_number = ?1",
+            params![block_number],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+    }
+
+    /// `kind` is the BABE pre-digest kind, or "aura".
+    pub fn record_block_slot(
+        &self,
+        block_number: u32,
+        slot: u64,
+        authority_index: Option<u32>,
+        kind: &str,
+        author: Option<&str>,
+    ) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO block_slots (block_number, slot, authority_index, kind, author)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![block_number, slot, authority_index, kind, author],
+        )?;
+        Ok(())
+    }
+
+    /// Stores an epoch's validators (as hex), in authority index order.
+    pub fn record_epoch_validators(&self, index: u64, validators: &[String]) -> Result<(), rusqlite::Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM epoch_validators WHERE epoch_index = ?1", params![index])?;
+        for (authority_index, validator) in validators.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO epoch_validators (epoch_index, authority_index, validator) VALUES (?1, ?2, ?3)",
+                params![index, authority_index as u32, validator],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// The validator behind an authority index, in the epoch the block is in.
+    pub fn epoch_validator(&self, block_number: u32, authority_index: u32) -> Result<Option<String>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT validator FROM epoch_validators
+             WHERE authority_index = ?2 AND epoch_index =
+                (SELECT epoch_index FROM epochs WHERE start_block <= ?1 ORDER BY start_block DESC LIMIT 1)",
+            params![block_number, authority_index],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    pub fn record_epoch_start(&self, index: u64, block_number: u32, slot: Option<u64>) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO epochs (epoch_index, start_block, start_slot) VALUES (?1, ?2, ?3)
+             ON CONFLICT (epoch_index) DO UPDATE SET start_block = ?2, start_slot = ?3",
+            params![index, block_number, slot],
+        )?;
+        Ok(())
+    }
+
+    pub fn record_epoch_data(&self, index: u64, authorities: u32, randomness: &str) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO epochs (epoch_index, authorities, randomness) VALUES (?1, ?2, ?3)
+             ON CONFLICT (epoch_index) DO UPDATE SET authorities = ?2, randomness = ?3",
+            params![index, authorities, randomness],
+        )?;
+        Ok(())
+    }
+
+    /// The latest `limit` epochs, newest first, with the kinds of slots their blocks were in.
+    pub fn get_epochs(&self, limit: u32) -> Result<Vec<Epoch>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT epoch_index, start_block, start_slot, authorities, randomness FROM epochs
+             ORDER BY epoch_index DESC
+             LIMIT ?1",
+        )?;
+        let mut epochs = stmt
+            .query_map(params![limit], |row| {
+                Ok(Epoch {
+                    index: row.get(0)?,
+                    start_block: row.get(1)?,
+                    start_slot: row.get(2)?,
+                    authorities: row.get(3)?,
+                    randomness: row.get(4)?,
+                    slots: BTreeMap::new(),
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // An epoch runs until the next one starts
+        let mut end = None;
+        let mut stmt = conn.prepare_cached(
+            "SELECT kind, COUNT(*) FROM block_slots
+             WHERE block_number >= ?1 AND (?2 IS NULL OR block_number < ?2)
+             GROUP BY kind",
+        )?;
+        for epoch in &mut epochs {
+            let Some(start) = epoch.start_block else {
+                continue;
+            };
+            let counts = stmt.query_map(params![start, end], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            epoch.slots = counts.collect::<Result<_, _>>()?;
+            end = Some(start);
+        }
+        Ok(epochs)
+    }
+
+    pub fn record_election_event(&self, event_index: u32, event: &ElectionEvent) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO election_events (block_number, event_index, round, kind, detail)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![event.block_number, event_index, event.round, event.kind, event.detail],
+        )?;
+        Ok(())
+    }
+
+    /// The latest election events, newest first, of one kind or all.
+    pub fn get_election_events(&self, kind: Option<&str>, limit: u32) -> Result<Vec<ElectionEvent>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT block_number, round, kind, detail FROM election_events
+             WHERE ?1 IS NULL OR kind = ?1
+             ORDER BY block_number DESC, event_index DESC
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![kind, limit], |row| {
+            Ok(ElectionEvent {
+                block_number: row.get(0)?,
+                round: row.get(1)?,
+                kind: row.get(2)?,
+                detail: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Stores an era's points, `points` keyed by validator hex.
+    pub fn record_era_points(
+        &self,
+        era: u32,
+        block_number: u32,
+        total: u32,
+        points: &[(String, u32)],
+    ) -> Result<(), rusqlite::Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO eras (era, paid_in, total_points, validators) VALUES (?1, ?2, ?3, ?4)",
+            params![era, block_number, total, points.len() as u32],
+        )?;
+        for (validator, points) in points {
+            tx.execute(
+                "INSERT OR REPLACE INTO era_points (era, validator, points) VALUES (?1, ?2, ?3)",
+                params![era, validator, points],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// A validator's points in `era`, or in its latest `limit` eras, newest first.
+    pub fn get_era_points(
+        &self,
+        validator: &str,
+        era: Option<u32>,
+        limit: u32,
+    ) -> Result<Vec<EraPoints>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT p.era, p.points, e.total_points, e.validators,
+                (SELECT COUNT(*) FROM era_points o WHERE o.era = p.era AND o.points > p.points) + 1
+             FROM era_points p JOIN eras e ON e.era = p.era
+             WHERE p.validator = ?1 AND (?2 IS NULL OR p.era = ?2)
+             ORDER BY p.era DESC
+             LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![validator, era, limit], |row| {
+            Ok(EraPoints {
+                era: row.get(0)?,
+                points: row.get(1)?,
+                total_points: row.get(2)?,
+                validators: row.get(3)?,
+                rank: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Stores an `ImOnline` session end, with the validators it reported offline as
+    /// (hex, SS58 address).
+    pub fn record_heartbeat_session(&self, block_number: u32, offline: &[(String, String)]) -> Result<(), rusqlite::Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO heartbeat_sessions (block_number, offline) VALUES (?1, ?2)",
+            params![block_number, offline.len() as u32],
+        )?;
+        for (validator, address) in offline {
+            tx.execute(
+                "INSERT OR REPLACE INTO offline_validators (block_number, validator, address) VALUES (?1, ?2, ?3)",
+                params![block_number, validator, address],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// `validator` is the hex account the `authority_id` key belongs to, if it could be looked up.
+    pub fn record_heartbeat(&self, block_number: u32, authority_id: &str, validator: Option<&str>) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO heartbeats (block_number, authority_id, validator) VALUES (?1, ?2, ?3)",
+            params![block_number, authority_id, validator],
+        )?;
+        Ok(())
+    }
+
+    /// The latest session ends that reported someone offline, newest first.
+    pub fn get_offline_reports(&self, cursor: Cursor, limit: u32) -> Result<Vec<OfflineReport>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(if cursor.ascending() {
+            "SELECT block_number FROM heartbeat_sessions
+             WHERE offline > 0 AND block_number < ?1 AND block_number > ?2
+             ORDER BY block_number ASC LIMIT ?3"
+        } else {
+            "SELECT block_number FROM heartbeat_sessions
+             WHERE offline > 0 AND block_number < ?1 AND block_number > ?2
+             ORDER BY block_number DESC LIMIT ?3"
+        })?;
+        let (before, after) = cursor.bounds();
+        let mut blocks = stmt
+            .query_map(params![before, after, limit], |row| row.get(0))?
+            .collect::<Result<Vec<u32>, _>>()?;
+        if cursor.ascending() {
+            blocks.reverse();
+        }
+
+        let mut stmt = conn.prepare_cached(
+            "SELECT COALESCE(address, validator) FROM offline_validators WHERE block_number = ?1 ORDER BY address",
+        )?;
+        blocks
+            .into_iter()
+            .map(|block_number| {
+                let validators = stmt.query_map(params![block_number], |row| row.get(0))?.collect::<Result<_, _>>()?;
+                Ok(OfflineReport { block_number, validators })
+            })
+            .collect()
+    }
+
+    /// A validator's authorship, uptime and points over its latest `eras` eras.
+    pub fn get_validator_performance(&self, validator: &str, eras: u32) -> Result<ValidatorPerformance, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let count = |sql: &str| conn.query_row(sql, params![validator], |row| row.get::<_, u32>(0));
+
+        let blocks_authored = count("SELECT COUNT(*) FROM block_slots WHERE author = ?1")?;
+        let epochs_active = count("SELECT COUNT(*) FROM epoch_validators WHERE validator = ?1")?;
+        // A session's offline report comes in the first block of the next one
+        let heartbeat_sessions = count(
+            "SELECT COUNT(*) FROM heartbeat_sessions h WHERE EXISTS (
+                SELECT 1 FROM epoch_validators v WHERE v.validator = ?1 AND v.epoch_index =
+                    (SELECT epoch_index FROM epochs WHERE start_block < h.block_number ORDER BY start_block DESC LIMIT 1)
+             )",
+        )?;
+        let offline = count("SELECT COUNT(*) FROM offline_validators WHERE validator = ?1")?;
+        let heartbeats = count("SELECT COUNT(*) FROM heartbeats WHERE validator = ?1")?;
+        let last = |sql: &str| conn.query_row(sql, params![validator], |row| row.get::<_, Option<u32>>(0));
+        let last_heartbeat = last("SELECT MAX(block_number) FROM heartbeats WHERE validator = ?1")?;
+        let last_offline = last("SELECT MAX(block_number) FROM offline_validators WHERE validator = ?1")?;
+
+        let (era_count, points, average_points): (u32, u32, f64) = conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(points), 0), COALESCE(SUM(CAST(total_points AS REAL) / validators), 0)
+             FROM (
+                SELECT p.points, e.total_points, e.validators FROM era_points p JOIN eras e ON e.era = p.era
+                WHERE p.validator = ?1 AND e.validators > 0
+                ORDER BY p.era DESC
+                LIMIT ?2
+             )",
+            params![validator, eras],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        Ok(ValidatorPerformance {
+            blocks_authored,
+            epochs_active,
+            heartbeat_sessions,
+            offline,
+            uptime: (heartbeat_sessions > 0)
+                .then(|| heartbeat_sessions.saturating_sub(offline) as f64 / heartbeat_sessions as f64),
+            heartbeats,
+            last_heartbeat,
+            last_offline,
+            eras: era_count,
+            points,
+            average_points,
+            points_vs_average: (average_points > 0.0).then(|| points as f64 / average_points),
+        })
+    }
+
+    pub fn get_block_by_hash(&self, block_hash: &str) -> Result<Option<StoredBlock>, rusqlite::Error> {
+        let _timer = metrics::time(DbOperation::Get);
+        let conn = self.conn.lock().unwrap();
+        let block_data_json: Option<String> = conn
+            .query_row(
+                "SELECT block_data FROM blocks WHERE block_hash = ?1",
+                params![block_hash],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        block_data_json
+            .map(|json| serde_json::from_str(&json))
+            .transpose()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+                0,
+                rusqlite::types::Type::Text,
+                Box::new(e)
+            ))
+    }
+
+    pub fn get_latest_block_number(&self) -> Result<Option<u32>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT MAX(block_number) FROM blocks")?;
+        let mut rows = stmt.query([])?;
+
+        if let Some(row) = rows.next()? {
+            Ok(row.get(0)?)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The lowest and highest stored block numbers, None when nothing's stored.
+    pub fn get_block_range(&self) -> Result<Option<(u32, u32)>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT MIN(block_number), MAX(block_number) FROM blocks")?;
+        let (first, last): (Option<u32>, Option<u32>) = stmt.query_row([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(first.zip(last))
+    }
+
+    /// The numbers of the stored blocks between `start` and `end`, inclusive.
+    pub fn stored_block_numbers(&self, start: u32, end: u32) -> Result<Vec<u32>, rusqlite::Error> {
+        let _timer = metrics::time(DbOperation::Range);
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT block_number FROM blocks WHERE block_number BETWEEN ?1 AND ?2")?;
+        let numbers = stmt.query_map(params![start, end], |row| row.get(0))?.collect();
+        numbers
+    }
+
+    /// Up to `limit` blocks in the cursor's page, newest first.
+    pub fn get_blocks_page(&self, cursor: Cursor, limit: u32) -> Result<Vec<StoredBlock>, rusqlite::Error> {
+        let _timer = metrics::time(DbOperation::Range);
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(if cursor.ascending() {
+            "SELECT block_data FROM blocks
+             WHERE block_number < ?1 AND block_number > ?2
+             ORDER BY block_number ASC
+             LIMIT ?3"
+        } else {
+            "SELECT block_data FROM blocks
+             WHERE block_number < ?1 AND block_number > ?2
+             ORDER BY block_number DESC
+             LIMIT ?3"
+        })?;
+
+        let (before, after) = cursor.bounds();
+        let rows = stmt.query_map(params![before, after, limit], |row| {
+            let block_data_json: String = row.get(0)?;
+            Ok(block_data_json)
+        })?;
+
+        let mut blocks = Vec::new();
+        for row in rows {
+            let block_data_json = row?;
+            if let Ok(block) = serde_json::from_str(&block_data_json) {
+                blocks.push(block);
+            }
+        }
+        if cursor.ascending() {
+            blocks.reverse();
+        }
+
+        Ok(blocks)
+    }
+
+    /// Up to `limit` blocks from `start` upwards, oldest first.
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub fn get_blocks_from(&self, start: u32, limit: u32) -> Result<Vec<StoredBlock>, rusqlite::Error> {
+        let _timer = metrics::time(DbOperation::Range);
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT block_data FROM blocks
+             WHERE block_number >= ?1
+             ORDER BY block_number ASC
+             LIMIT ?2"
+        )?;
+
+        let rows = stmt.query_map(params![start, limit], |row| row.get::<_, String>(0))?;
+        let mut blocks = Vec::new();
+        for row in rows {
+            if let Ok(block) = serde_json::from_str(&row?) {
+                blocks.push(block);
+            }
+        }
+
+        Ok(blocks)
+    }
+
+    // Pages through blocks `from..=to` newest first, until `visit` has collected `limit` items
+    fn scan_blocks<T>(
+        &self,
+        from: u32,
+        to: u32,
+        limit: usize,
+        mut visit: impl FnMut(&StoredBlock, &mut Vec<T>),
+    ) -> Result<Vec<T>, rusqlite::Error> {
+        const PAGE: u32 = 100;
+
+        let mut found = Vec::new();
+        let mut to = to;
+        while found.len() < limit && to >= from {
+            let cursor = Cursor { before: to.checked_add(1), after: from.checked_sub(1) };
+            let blocks = self.get_blocks_page(cursor, PAGE)?;
+            let Some(oldest) = blocks.last().map(|b| b.number) else {
+                break;
+            };
+            blocks.iter().for_each(|block| visit(block, &mut found));
+
+            match oldest.checked_sub(1) {
+                Some(before) => to = before,
+                None => break,
+            }
+        }
+        found.truncate(limit);
+
+        Ok(found)
+    }
+
+    /// Events of `pallet` (of any variant if `variant` is None) in blocks `from..=to`, newest
+    /// first. This scans the stored JSON, so keep the range reasonable.
+    pub fn find_events(
+        &self,
+        pallet: &str,
+        variant: Option<&str>,
+        from: u32,
+        to: u32,
+        limit: usize,
+    ) -> Result<Vec<StoredEvent>, rusqlite::Error> {
+        self.scan_blocks(from, to, limit, |block, found| {
+            for extrinsic in &block.extrinsics {
+                let events = extrinsic["events"].as_array().into_iter().flatten();
+                for (event_index, event) in events.enumerate() {
+                    if event["pallet"] != pallet
+                        || variant.is_some_and(|variant| event["variant"] != variant)
+                    {
+                        continue;
+                    }
+                    found.push(StoredEvent {
+                        block_number: block.number,
+                        block_hash: block.hash.clone(),
+                        timestamp: block.timestamp,
+                        extrinsic_index: extrinsic["index"].as_u64().unwrap_or_default() as u32,
+                        event_index,
+                        pallet: pallet.to_string(),
+                        variant: event["variant"].as_str().unwrap_or_default().to_string(),
+                        data: event["data"].as_str().unwrap_or_default().to_string(),
+                    });
+                }
+            }
+        })
+    }
+
+    /// Calls to `pallet` (and `call`, if given) in blocks `from..=to`, newest first, whether
+    /// they're the extrinsic itself or nested in it by sudo, proxy, batch and the like.
+    pub fn find_calls(
+        &self,
+        pallet: &str,
+        call: Option<&str>,
+        from: u32,
+        to: u32,
+        limit: usize,
+    ) -> Result<Vec<StoredCall>, rusqlite::Error> {
+        // The extrinsic's own call is its `action`, the nested ones are a tree under `calls`
+        fn visit(
+            call: &serde_json::Value,
+            depth: u32,
+            matches: &mut dyn FnMut(&str, &str, &serde_json::Value, u32),
+        ) {
+            let (pallet, name) = match call["action"].as_str() {
+                Some(action) => action.split_once('/').unwrap_or((action, "")),
+                None => (
+                    call["pallet"].as_str().unwrap_or_default(),
+                    call["call"].as_str().unwrap_or_default(),
+                ),
+            };
+            matches(pallet, name, &call["params"], depth);
+            for nested in call["calls"].as_array().into_iter().flatten() {
+                visit(nested, depth + 1, matches);
+            }
+        }
+
+        self.scan_blocks(from, to, limit, |block, found| {
+            for extrinsic in &block.extrinsics {
+                visit(extrinsic, 0, &mut |call_pallet, call_name, params, depth| {
+                    if call_pallet == pallet && call.is_none_or(|call| call == call_name) {
+                        found.push(StoredCall {
+                            block_number: block.number,
+                            block_hash: block.hash.clone(),
+                            extrinsic_index: extrinsic["index"].as_u64().unwrap_or_default() as u32,
+                            depth,
+                            pallet: call_pallet.to_string(),
+                            call: call_name.to_string(),
+                            params: params.clone(),
+                        });
+                    }
+                });
+            }
+        })
+    }
+
+    /// The running totals kept by the `counters` triggers: "blocks", "extrinsics", "events",
+    /// and "extrinsics:<Pallet>"/"events:<Pallet>" for each pallet seen in what's stored.
+    pub fn counters(&self) -> Result<BTreeMap<String, u64>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT name, value FROM counters WHERE value > 0")?;
+        let counters = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+        counters
+    }
+
+    pub fn integrity_check(&self) -> Result<Vec<String>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        rows.collect()
+    }
+
+    /// Walks every stored block in ascending order, handing the raw columns to `f`.
+    /// Used by `smolcar check`, so it deliberately doesn't hide rows that fail to parse.
+    pub fn for_each_raw_block<F>(&self, mut f: F) -> Result<(), rusqlite::Error>
+    where
+        F: FnMut(u32, &str, &str),
+    {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT block_number, block_hash, block_data FROM blocks ORDER BY block_number ASC"
+        )?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let number: u32 = row.get(0)?;
+            let hash: String = row.get(1)?;
+            let data: String = row.get(2)?;
+            f(number, &hash, &data);
+        }
+
+        Ok(())
+    }
+
+    /// Bytes actually used by data. Deleted rows leave free pages behind that SQLite
+    /// reuses but doesn't hand back to the OS, so this is what pruning brings down.
+    pub fn used_bytes(&self) -> Result<u64, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        let page_count: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        let freelist: u64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+        Ok((page_count - freelist) * page_size)
+    }
+
+    /// Switches the database to incremental vacuum if it isn't yet, with a full VACUUM that
+    /// rewrites the whole file. Returns whether it had to.
+    pub fn enable_incremental_vacuum(&self) -> Result<bool, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        // 2 is INCREMENTAL
+        let auto_vacuum: u8 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+        if auto_vacuum == 2 {
+            return Ok(false);
+        }
+        conn.pragma_update(None, "auto_vacuum", "incremental")?;
+        conn.execute_batch("VACUUM")?;
+        Ok(true)
+    }
+
+    /// Hands up to `pages` free pages back to the OS and returns how many are left.
+    pub fn incremental_vacuum(&self, pages: u32) -> Result<u64, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        // Frees a page per row it steps through, and 0 would mean all of them
+        let mut stmt = conn.prepare(&format!("PRAGMA incremental_vacuum({})", pages.max(1)))?;
+        let mut rows = stmt.query([])?;
+        while rows.next()?.is_some() {}
+        drop(rows);
+        drop(stmt);
+        conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))
+    }
+
+    pub fn file_bytes(&self) -> Result<u64, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        let page_count: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        Ok(page_count * page_size)
+    }
+
+    pub fn storage_stats(&self) -> Result<StorageStats, rusqlite::Error> {
+        // How many recent blocks the averages and rate are taken over
+        const SAMPLE: u32 = 1000;
+
+        let used_bytes = self.used_bytes()?;
+        let blocks = self.counters()?.get("blocks").copied().unwrap_or_default();
+        let conn = self.conn.lock().unwrap();
+        let page_size: u64 = conn.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+        let page_count: u64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+
+        // Blocks are counted by the `counters` triggers, the other tables are small enough to count
+        let mut rows = BTreeMap::new();
+        let tables = conn
+            .prepare_cached("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for table in tables {
+            let count = if table == "blocks" {
+                blocks
+            } else {
+                conn.query_row(&format!("SELECT COUNT(*) FROM \"{}\"", table), [], |row| row.get(0))?
+            };
+            rows.insert(table, count);
+        }
+
+        let (avg_block_bytes, blocks_per_day): (Option<f64>, Option<f64>) = conn
+            .prepare_cached(
+                "SELECT AVG(LENGTH(block_data)),
+                    (MAX(block_number) - MIN(block_number)) * 86400.0 / NULLIF(MAX(timestamp) - MIN(timestamp), 0)
+                 FROM (SELECT block_data, block_number, timestamp FROM blocks ORDER BY block_number DESC LIMIT ?1)",
+            )?
+            .query_row(params![SAMPLE], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let bytes_per_block = (blocks > 0).then(|| used_bytes / blocks);
+
+        Ok(StorageStats {
+            file_bytes: page_count * page_size,
+            used_bytes,
+            rows,
+            avg_block_bytes: avg_block_bytes.map(|avg| avg as u64),
+            bytes_per_block,
+            blocks_per_day,
+            growth_per_day_bytes: bytes_per_block.zip(blocks_per_day).map(|(size, rate)| (size as f64 * rate) as u64),
+        })
+    }
+
+    /// Deletes up to `batch` of the oldest blocks and everything recorded from them, leaving
+    /// the newest `keep_recent` alone. Returns how many blocks were removed.
+    pub fn prune_oldest(&self, batch: u32, keep_recent: u32) -> Result<usize, rusqlite::Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let last: Option<u32> = tx.query_row(
+            "SELECT MAX(block_number) FROM (
+                SELECT block_number FROM blocks
+                WHERE block_number <= (SELECT MAX(block_number) FROM blocks) - ?2
+                ORDER BY block_number ASC
+                LIMIT ?1
+            )",
+            params![batch, keep_recent],
+            |row| row.get(0),
+        )?;
+        let Some(last) = last else {
+            return Ok(0);
+        };
+        mark_pruned(&tx, last)?;
+        delete_recorded(&tx, 0, last)?;
+        let deleted = tx.execute("DELETE FROM blocks WHERE block_number <= ?1", params![last])?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    pub fn count_blocks_before(&self, before: u32) -> Result<u64, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT COUNT(*) FROM blocks WHERE block_number < ?1")?;
+        stmt.query_row(params![before], |row| row.get(0))
+    }
+
+    /// Deletes the oldest `batch` blocks below `before` and everything recorded from them, in
+    /// one transaction, and returns how many blocks went. Once no blocks are left below
+    /// `before`, the call returning 0 clears what's left of the side tables below it.
+    pub fn delete_blocks_before(&self, before: u32, batch: u32) -> Result<usize, rusqlite::Error> {
+        if before == 0 {
+            return Ok(0);
+        }
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let last: u32 = tx.query_row(
+            "SELECT COALESCE(MAX(block_number), ?1 - 1) FROM (
+                SELECT block_number FROM blocks WHERE block_number < ?1 ORDER BY block_number ASC LIMIT ?2
+            )",
+            params![before, batch],
+            |row| row.get(0),
+        )?;
+        mark_pruned(&tx, last)?;
+        delete_recorded(&tx, 0, last)?;
+        let deleted = tx.execute("DELETE FROM blocks WHERE block_number <= ?1", params![last])?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Deletes one block and everything recorded from it. Returns whether it was stored.
+    pub fn delete_block(&self, number: u32) -> Result<bool, rusqlite::Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        delete_recorded(&tx, number, number)?;
+        let deleted = tx.execute("DELETE FROM blocks WHERE block_number = ?1", params![number])?;
+        tx.commit()?;
+        Ok(deleted > 0)
+    }
+
+    /// The oldest blocks `prune_oldest` would delete, as raw (number, JSON) pairs.
+    #[cfg_attr(not(feature = "s3"), allow(dead_code))]
+    pub fn oldest_raw_blocks(&self, limit: u32, keep_recent: u32) -> Result<Vec<(u32, String)>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT block_number, block_data FROM blocks
+             WHERE block_number <= (SELECT MAX(block_number) FROM blocks) - ?2
+             ORDER BY block_number ASC
+             LIMIT ?1"
+        )?;
+        let rows = stmt.query_map(params![limit, keep_recent], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Remembers that blocks `first..=last` were uploaded to `object_key`, then deletes them and
+    /// everything recorded from them.
+    #[cfg_attr(not(feature = "s3"), allow(dead_code))]
+    pub fn archive_and_delete(&self, first: u32, last: u32, object_key: &str) -> Result<usize, rusqlite::Error> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO archived_chunks (first_block, last_block, object_key) VALUES (?1, ?2, ?3)",
+            params![first, last, object_key],
+        )?;
+        mark_pruned(&tx, last)?;
+        delete_recorded(&tx, first, last)?;
+        let deleted = tx.execute(
+            "DELETE FROM blocks WHERE block_number BETWEEN ?1 AND ?2",
+            params![first, last],
+        )?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Key of the archived chunk holding `block_number`, if it was archived.
+    #[cfg_attr(not(feature = "s3"), allow(dead_code))]
+    pub fn archived_chunk(&self, block_number: u32) -> Result<Option<String>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT object_key FROM archived_chunks
+             WHERE first_block <= ?1 AND last_block >= ?1
+             ORDER BY first_block DESC LIMIT 1",
+            params![block_number],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(locked: &str) -> VestingSchedule {
+        VestingSchedule { locked: locked.to_string(), per_block: "1".to_string(), starting_block: 0 }
+    }
+
+    // Backfilled blocks write after the follower's newer ones, so block N lands after N + 1
+    #[test]
+    fn older_blocks_keep_newer_state() {
+        let path = std::env::temp_dir().join(format!("smolcar-test-{}.db", std::process::id()));
+        let db = Database::new(&path, &SqliteConfig::default()).unwrap();
+        let (n, next) = (100, 101);
+
+        db.record_nft_item("Nfts", 1, 1, "burned", None, next).unwrap();
+        db.record_nft_item("Nfts", 1, 1, "transferred", Some("bob"), n).unwrap();
+        let item = db.get_nft_items("Nfts", 1, None, 10).unwrap().remove(0);
+        assert_eq!((item.owner, item.burned_in), (None, Some(next)));
+
+        db.set_vesting("alice", &[schedule("2")], None, next).unwrap();
+        db.set_vesting("alice", &[schedule("1")], Some("1"), n).unwrap();
+        let vesting = db.get_vesting("alice").unwrap().unwrap();
+        assert_eq!((vesting.schedules[0].locked.as_str(), vesting.updated_in), ("2", next));
+
+        db.set_scheduled_task_status(500, 0, "dispatched", next).unwrap();
+        db.record_scheduled_task(500, 0, n, None).unwrap();
+        assert!(db.get_upcoming_tasks(0, 10).unwrap().is_empty());
+
+        db.set_asset_destroyed(7, next).unwrap();
+        db.record_asset_created(7, n, Some("alice")).unwrap();
+        db.set_asset_metadata(7, Some("New"), None, None, next).unwrap();
+        db.set_asset_metadata(7, Some("Old"), None, None, n).unwrap();
+        let asset = db.get_assets(None, 10).unwrap().remove(0);
+        assert_eq!((asset.destroyed_in, asset.name.as_deref()), (Some(next), Some("New")));
+
+        db.record_referendum(3, 0, n, Some("0xab"), Some(2), None).unwrap();
+        db.set_referendum_proposal(3, "{\"call\":1}").unwrap();
+        db.record_referendum(3, 0, n, Some("0xab"), Some(2), None).unwrap();
+        assert!(db.get_referendum(3).unwrap().unwrap().proposal.is_some());
+
+        drop(db);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
     }
 }
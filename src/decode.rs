@@ -0,0 +1,329 @@
+//! Turning decoded SCALE values into the text and JSON smolcar stores. Accounts are written as
+//! SS58 addresses in the chain's format instead of the 32 raw bytes scale-value would print.
+
+use scale_info::{TypeDef, TypeDefPrimitive};
+use serde::Serialize;
+use serde_json::Value as Json;
+use std::sync::Arc;
+use subxt::ext::scale_value::{self, Composite, Primitive, Value, ValueDef};
+use subxt::ext::sp_core::crypto::{AccountId32, Ss58AddressFormat, Ss58Codec};
+use subxt::Metadata;
+
+use crate::decoders::Decoders;
+
+/// What extrinsics and events need to render their fields.
+#[derive(Clone)]
+pub struct Context {
+    pub metadata: Metadata,
+    /// From the chain spec's `ss58Format`
+    pub ss58_prefix: u16,
+    /// Take over decoding their pallets, see `decoders.rs`
+    pub decoders: Arc<Decoders>,
+}
+
+/// A call nested in an extrinsic's params (by sudo, proxy, multisig, batch, scheduler...).
+#[derive(Clone, Serialize)]
+pub struct Call {
+    pub pallet: String,
+    pub call: String,
+    pub params: Json,
+    /// Calls nested in this one, e.g. each call of a batch inside a proxy call
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<Call>,
+}
+
+/// An account, both ways round.
+#[derive(Clone, Serialize)]
+pub struct Address {
+    /// Missing when the address isn't a 32 byte account (e.g. an index or a 20 byte address)
+    pub ss58: Option<String>,
+    pub hex: String,
+}
+
+impl Context {
+    fn ss58(&self, bytes: [u8; 32]) -> String {
+        AccountId32::new(bytes).to_ss58check_with_version(Ss58AddressFormat::custom(self.ss58_prefix))
+    }
+
+    /// The signer of an extrinsic from its encoded address, usually a `MultiAddress`.
+    pub fn address(&self, encoded: &[u8]) -> Address {
+        Address {
+            ss58: signer_account(encoded).map(|account| self.ss58(account)),
+            hex: format!("0x{}", hex::encode(encoded)),
+        }
+    }
+
+    /// A decoded `AccountId32` as an SS58 address.
+    pub fn account(&self, value: &Value<u32>) -> Option<String> {
+        account_bytes(value).map(|account| self.ss58(account))
+    }
+
+    /// Fields as text, like scale-value's `Display` but with accounts as SS58 addresses.
+    pub fn fields_to_string(&self, mut fields: Composite<u32>) -> String {
+        self.rewrite_composite(&mut fields);
+        fields.to_string()
+    }
+
+    /// Fields as a JSON tree keyed by field name. Integers up to 32 bits are numbers and wider
+    /// ones strings, so a field is always one or the other, byte strings are hex, accounts SS58
+    /// addresses, and enums `{"Variant": fields}` (just `"Variant"` without fields, `Option`s
+    /// the value or null).
+    pub fn fields_to_json(&self, mut fields: Composite<u32>) -> Json {
+        self.rewrite_composite(&mut fields);
+        match &fields {
+            Composite::Named(fields) => self.named_to_json(fields),
+            Composite::Unnamed(values) => Json::Array(values.iter().map(|value| self.to_json(value)).collect()),
+        }
+    }
+
+    /// A call's params: from the pallet's custom decoder if it has one that takes the call,
+    /// the JSON tree otherwise.
+    pub fn call_params(&self, pallet: &str, call: &str, fields: Composite<u32>) -> Json {
+        let custom = self.decoders.get(pallet).and_then(|decoder| decoder.call(self, call, &fields));
+        custom.unwrap_or_else(|| self.fields_to_json(fields))
+    }
+
+    fn named_to_json(&self, fields: &[(String, Value<u32>)]) -> Json {
+        Json::Object(fields.iter().map(|(name, value)| (name.clone(), self.to_json(value))).collect())
+    }
+
+    // A single unnamed field stands for itself, as in `Id(account)` or a newtype
+    fn unnamed_to_json(&self, values: &[Value<u32>]) -> Json {
+        match values {
+            [value] => self.to_json(value),
+            values => Json::Array(values.iter().map(|value| self.to_json(value)).collect()),
+        }
+    }
+
+    fn to_json(&self, value: &Value<u32>) -> Json {
+        let type_def = self.metadata.types().resolve(value.context).map(|ty| &ty.type_def);
+        if let Some(TypeDef::Sequence(_) | TypeDef::Array(_)) = type_def {
+            if self.is_byte_string(value.context) {
+                if let Some(bytes) = bytes(value) {
+                    return Json::String(format!("0x{}", hex::encode(bytes)));
+                }
+            }
+        }
+
+        match &value.value {
+            ValueDef::Composite(Composite::Named(fields)) => self.named_to_json(fields),
+            ValueDef::Composite(Composite::Unnamed(values)) => match type_def {
+                Some(TypeDef::Composite(_) | TypeDef::Tuple(_)) => self.unnamed_to_json(values),
+                // A sequence of one is still a sequence
+                _ => Json::Array(values.iter().map(|value| self.to_json(value)).collect()),
+            },
+            ValueDef::Variant(variant) => {
+                let fields = match &variant.values {
+                    Composite::Named(fields) => self.named_to_json(fields),
+                    Composite::Unnamed(values) => self.unnamed_to_json(values),
+                };
+                match (self.type_name(value.context), variant.name.as_str()) {
+                    (Some("Option"), "None") => Json::Null,
+                    (Some("Option"), "Some") => fields,
+                    _ if variant.values.is_empty() => Json::String(variant.name.clone()),
+                    _ => serde_json::json!({ variant.name.clone(): fields }),
+                }
+            }
+            ValueDef::Primitive(primitive) => match primitive {
+                Primitive::Bool(b) => Json::Bool(*b),
+                Primitive::Char(c) => Json::String(c.to_string()),
+                Primitive::String(s) => Json::String(s.clone()),
+                Primitive::U128(n) if self.is_small_integer(value.context) => Json::from(*n as u64),
+                Primitive::I128(n) if self.is_small_integer(value.context) => Json::from(*n as i64),
+                Primitive::U128(n) => Json::String(n.to_string()),
+                Primitive::I128(n) => Json::String(n.to_string()),
+                Primitive::U256(bytes) | Primitive::I256(bytes) => Json::String(format!("0x{}", hex::encode(bytes))),
+            },
+            ValueDef::BitSequence(bits) => Json::String(bits.iter().map(|bit| if bit { '1' } else { '0' }).collect()),
+        }
+    }
+
+    // Integers that always fit a JSON number, by their type rather than their value: u64 and
+    // wider can go past 2^53, where JavaScript clients would silently round them
+    fn is_small_integer(&self, type_id: u32) -> bool {
+        match self.metadata.types().resolve(type_id).map(|ty| &ty.type_def) {
+            Some(TypeDef::Primitive(primitive)) => matches!(
+                primitive,
+                TypeDefPrimitive::U8
+                    | TypeDefPrimitive::U16
+                    | TypeDefPrimitive::U32
+                    | TypeDefPrimitive::I8
+                    | TypeDefPrimitive::I16
+                    | TypeDefPrimitive::I32
+            ),
+            Some(TypeDef::Compact(compact)) => self.is_small_integer(compact.type_param.id),
+            // Newtypes like `Perbill(u32)`, when compact
+            Some(TypeDef::Composite(composite)) if composite.fields.len() == 1 => {
+                self.is_small_integer(composite.fields[0].ty.id)
+            }
+            _ => false,
+        }
+    }
+
+    fn is_byte_string(&self, type_id: u32) -> bool {
+        let types = self.metadata.types();
+        let element = match types.resolve(type_id).map(|ty| &ty.type_def) {
+            Some(TypeDef::Sequence(sequence)) => sequence.type_param.id,
+            Some(TypeDef::Array(array)) => array.type_param.id,
+            _ => return false,
+        };
+        matches!(types.resolve(element).map(|ty| &ty.type_def), Some(TypeDef::Primitive(TypeDefPrimitive::U8)))
+    }
+
+    /// Every call found in `fields`, each with the calls nested in it. Any argument typed as
+    /// the runtime's call enum counts, so this needs no knowledge of the wrapping pallets.
+    pub fn nested_calls(&self, fields: &Composite<u32>) -> Vec<Call> {
+        let mut calls = Vec::new();
+        fields.values().for_each(|value| self.find_calls(value, &mut calls));
+        calls
+    }
+
+    /// Decodes a SCALE-encoded runtime call, e.g. a governance proposal.
+    pub fn call(&self, encoded: &[u8]) -> Result<Call, String> {
+        let call_type = self.metadata.outer_enums().call_enum_ty();
+        let value = scale_value::scale::decode_as_type(&mut &*encoded, call_type, self.metadata.types())
+            .map_err(|e| e.to_string())?;
+        self.nested_calls(&Composite::Unnamed(vec![value]))
+            .pop()
+            .ok_or_else(|| "not a runtime call".to_string())
+    }
+
+    fn find_calls(&self, value: &Value<u32>, calls: &mut Vec<Call>) {
+        match &value.value {
+            ValueDef::Variant(pallet) => {
+                // RuntimeCall (just Call on older runtimes) is Pallet(call_name { fields })
+                let is_runtime_call = matches!(self.type_name(value.context), Some("RuntimeCall" | "Call"));
+                let call = match &pallet.values {
+                    Composite::Unnamed(values) if is_runtime_call && values.len() == 1 => match &values[0].value {
+                        ValueDef::Variant(call) => Some(call),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match call {
+                    Some(call) => calls.push(Call {
+                        pallet: pallet.name.clone(),
+                        call: call.name.clone(),
+                        params: self.call_params(&pallet.name, &call.name, call.values.clone()),
+                        calls: self.nested_calls(&call.values),
+                    }),
+                    None => pallet.values.values().for_each(|value| self.find_calls(value, calls)),
+                }
+            }
+            ValueDef::Composite(composite) => composite.values().for_each(|value| self.find_calls(value, calls)),
+            _ => {}
+        }
+    }
+
+    /// Every account in `fields`, however deeply nested, e.g. both sides of a transfer.
+    pub fn accounts(&self, fields: &Composite<u32>) -> Vec<[u8; 32]> {
+        let mut accounts = Vec::new();
+        fields.values().for_each(|value| self.find_accounts(value, &mut accounts));
+        accounts
+    }
+
+    fn find_accounts(&self, value: &Value<u32>, accounts: &mut Vec<[u8; 32]>) {
+        if self.type_name(value.context) == Some("AccountId32") {
+            if let Some(account) = account_bytes(value) {
+                accounts.push(account);
+                return;
+            }
+        }
+        match &value.value {
+            ValueDef::Composite(composite) => composite.values().for_each(|value| self.find_accounts(value, accounts)),
+            ValueDef::Variant(variant) => variant.values.values().for_each(|value| self.find_accounts(value, accounts)),
+            _ => {}
+        }
+    }
+
+    fn type_name(&self, type_id: u32) -> Option<&str> {
+        let ty = self.metadata.types().resolve(type_id)?;
+        ty.path.segments.last().map(|name| name.as_str())
+    }
+
+    fn rewrite_composite(&self, composite: &mut Composite<u32>) {
+        match composite {
+            Composite::Named(fields) => fields.iter_mut().for_each(|(_, value)| self.rewrite(value)),
+            Composite::Unnamed(values) => values.iter_mut().for_each(|value| self.rewrite(value)),
+        }
+    }
+
+    fn rewrite(&self, value: &mut Value<u32>) {
+        let type_name = self.type_name(value.context);
+        if type_name == Some("AccountId32") {
+            if let Some(account) = account_bytes(value) {
+                value.value = ValueDef::Primitive(Primitive::String(self.ss58(account)));
+                return;
+            }
+        }
+
+        match &mut value.value {
+            ValueDef::Composite(composite) => self.rewrite_composite(composite),
+            ValueDef::Variant(variant) => {
+                // The account is a plain [u8; 32] here, so the type name doesn't give it away
+                if type_name == Some("MultiAddress") && variant.name == "Address32" {
+                    let account = match &variant.values {
+                        Composite::Unnamed(values) => values.first().and_then(account_bytes),
+                        Composite::Named(_) => None,
+                    };
+                    if let Some(account) = account {
+                        variant.values = Composite::Unnamed(vec![Value {
+                            value: ValueDef::Primitive(Primitive::String(self.ss58(account))),
+                            context: value.context,
+                        }]);
+                        return;
+                    }
+                }
+                self.rewrite_composite(&mut variant.values);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Stored params as text: the JSON tree, or the text blocks stored before params were a tree.
+#[cfg_attr(
+    not(any(feature = "clickhouse", feature = "duckdb", feature = "graphql", feature = "grpc")),
+    allow(dead_code)
+)]
+pub fn params_text(params: &Json) -> String {
+    match params {
+        Json::String(text) => text.clone(),
+        Json::Null => String::new(),
+        params => params.to_string(),
+    }
+}
+
+/// The account behind an extrinsic's encoded address, if it's a 32 byte one.
+pub fn signer_account(encoded: &[u8]) -> Option<[u8; 32]> {
+    match encoded {
+        // MultiAddress::Id and MultiAddress::Address32
+        [0 | 3, account @ ..] if account.len() == 32 => account.try_into().ok(),
+        // Chains whose address is the plain AccountId32
+        account if account.len() == 32 => account.try_into().ok(),
+        _ => None,
+    }
+}
+
+/// The bytes of a decoded byte array or vector (hashes, accounts, `BoundedVec<u8>`...),
+/// however deeply they're wrapped.
+pub fn bytes(value: &Value<u32>) -> Option<Vec<u8>> {
+    fn collect(value: &Value<u32>, bytes: &mut Vec<u8>) -> Option<()> {
+        match &value.value {
+            ValueDef::Composite(composite) => composite.values().try_for_each(|v| collect(v, bytes)),
+            ValueDef::Primitive(Primitive::U128(byte)) => {
+                bytes.push(u8::try_from(*byte).ok()?);
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    let mut bytes = Vec::new();
+    collect(value, &mut bytes)?;
+    Some(bytes)
+}
+
+fn account_bytes(value: &Value<u32>) -> Option<[u8; 32]> {
+    bytes(value)?.try_into().ok()
+}
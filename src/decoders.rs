@@ -0,0 +1,123 @@
+//! Custom decoders for pallets smolcar knows nothing about, e.g. a parachain's own. A decoder
+//! gets the pallet's decoded calls and events and returns the JSON to store for them: a
+//! call's `params`, an event's `typed`. Whatever it declines (returns None for) keeps the
+//! generic decoding.
+//!
+//! To add one, put it in a module under `src/decoders/`, declare it below and list it in
+//! `REGISTERED`, for every chain or only the ones that have the pallet.
+
+use serde_json::Value as Json;
+use std::collections::HashMap;
+use subxt::ext::scale_value::Composite;
+
+use crate::decode::Context;
+
+pub trait PalletDecoder: Send + Sync {
+    /// The pallet's name in the metadata, e.g. "Marketplace"
+    fn pallet(&self) -> &'static str;
+
+    /// What's stored as the call's `params`, in extrinsics and wherever the call is nested.
+    fn call(&self, _decode: &Context, _call: &str, _fields: &Composite<u32>) -> Option<Json> {
+        None
+    }
+
+    /// What's stored as the event's `typed`, next to the generic `data`.
+    fn event(&self, _decode: &Context, _variant: &str, _fields: &Composite<u32>) -> Option<Json> {
+        None
+    }
+}
+
+type NewDecoder = fn() -> Box<dyn PalletDecoder>;
+
+// The decoders and the chain each is for, by its chain spec name or "*" for every chain, e.g.
+// `("mythos", || Box::new(marketplace::Decoder))`
+const REGISTERED: &[(&str, NewDecoder)] = &[];
+
+/// The custom decoders of one chain, by pallet.
+#[derive(Default)]
+pub struct Decoders {
+    by_pallet: HashMap<&'static str, Box<dyn PalletDecoder>>,
+}
+
+impl Decoders {
+    /// Adds the decoder, in place of any registered for the same pallet.
+    pub fn register(&mut self, decoder: Box<dyn PalletDecoder>) {
+        if self.by_pallet.insert(decoder.pallet(), decoder).is_some() {
+            tracing::warn!("More than one custom decoder for a pallet, the last one registered is used");
+        }
+    }
+
+    pub fn get(&self, pallet: &str) -> Option<&dyn PalletDecoder> {
+        self.by_pallet.get(pallet).map(|decoder| &**decoder)
+    }
+
+    pub fn pallets(&self) -> Vec<&'static str> {
+        self.by_pallet.keys().copied().collect()
+    }
+}
+
+/// The custom decoders for `chain`, as named in its chain spec.
+pub fn for_chain(chain: &str) -> Decoders {
+    let mut decoders = Decoders::default();
+    for (name, decoder) in REGISTERED {
+        if *name == "*" || *name == chain {
+            decoders.register(decoder());
+        }
+    }
+    if !decoders.pallets().is_empty() {
+        tracing::info!(chain, pallets = ?decoders.pallets(), "Custom decoders registered");
+    }
+    decoders
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::typed::{self, TypedEvent};
+    use std::sync::Arc;
+    use subxt::ext::codec::Decode;
+    use subxt::Metadata;
+
+    struct Balances;
+
+    impl PalletDecoder for Balances {
+        fn pallet(&self) -> &'static str {
+            "Balances"
+        }
+
+        fn call(&self, _decode: &Context, call: &str, _fields: &Composite<u32>) -> Option<Json> {
+            (call == "transfer_keep_alive").then(|| serde_json::json!({ "custom": call }))
+        }
+
+        fn event(&self, _decode: &Context, variant: &str, _fields: &Composite<u32>) -> Option<Json> {
+            Some(serde_json::json!({ "custom": variant }))
+        }
+    }
+
+    fn context(decoders: Decoders) -> Context {
+        let metadata = include_bytes!("../configs/polkadot_metadata_small.scale");
+        Context {
+            metadata: Metadata::decode(&mut &metadata[..]).unwrap(),
+            ss58_prefix: 0,
+            decoders: Arc::new(decoders),
+        }
+    }
+
+    #[test]
+    fn registered_decoders_take_over_their_pallet() {
+        let mut decoders = Decoders::default();
+        decoders.register(Box::new(Balances));
+        let decode = context(decoders);
+        let fields = || Composite::Named(vec![]);
+
+        let params = decode.call_params("Balances", "transfer_keep_alive", fields());
+        assert_eq!(params, serde_json::json!({ "custom": "transfer_keep_alive" }));
+        // Declined calls and other pallets keep the generic decoding
+        assert_eq!(decode.call_params("Balances", "force_transfer", fields()), serde_json::json!({}));
+        assert_eq!(decode.call_params("System", "remark", fields()), serde_json::json!({}));
+
+        let typed = typed::decode(&decode, "Balances", "Transfer", &fields());
+        assert!(matches!(typed, Some(TypedEvent::Custom(custom)) if custom == serde_json::json!({ "custom": "Transfer" })));
+        assert!(typed::decode(&decode, "System", "Remarked", &fields()).is_none());
+    }
+}
@@ -0,0 +1,95 @@
+//! Staking election tracking: `ElectionProviderMultiPhase` phase changes and solutions in the
+//! `election_events` table, served on `/staking/election`, so nominators can tell when the
+//! next validator set is being computed.
+
+use axum::{
+    extract::State,
+    response::Json,
+};
+use serde_json::json;
+use subxt::events::Events;
+use subxt::ext::scale_value::{At, Value, ValueDef};
+use subxt::PolkadotConfig;
+
+use crate::api::AppState;
+use crate::db::ElectionEvent;
+use crate::decode;
+use crate::error::Error;
+use crate::indexer::TrackerUpdates;
+
+const RECENT_EVENTS: u32 = 20;
+
+// Phases and ElectionCompute are enums, e.g. `Unsigned((true, 123))` -> "Unsigned"
+fn variant_name(value: Option<&Value<u32>>) -> Option<String> {
+    match &value?.value {
+        ValueDef::Variant(variant) => Some(variant.name.clone()),
+        _ => None,
+    }
+}
+
+// `origin` is an `Option<AccountId>`, None for unsigned solutions
+fn submitter(decode: &decode::Context, value: Option<&Value<u32>>) -> Option<String> {
+    match &value?.value {
+        ValueDef::Variant(option) if option.name == "Some" => decode.account(option.values.values().next()?),
+        _ => None,
+    }
+}
+
+/// Records the block's election phase changes and solutions.
+pub fn record(updates: &mut TrackerUpdates, decode: &decode::Context, block_number: u32, events: &Events<PolkadotConfig>) {
+    for event in events.iter().flatten() {
+        if event.pallet_name() != "ElectionProviderMultiPhase" {
+            continue;
+        }
+        let Ok(fields) = event.field_values() else {
+            continue;
+        };
+
+        let (kind, round, detail) = match event.variant_name() {
+            "PhaseTransitioned" => (
+                "phase",
+                fields.at("round").and_then(|v| v.as_u128()).map(|n| n as u32),
+                variant_name(fields.at("to")),
+            ),
+            "SolutionStored" => {
+                let compute = variant_name(fields.at("compute")).unwrap_or_default();
+                let detail = match submitter(decode, fields.at("origin")) {
+                    Some(origin) => format!("{} by {}", compute, origin),
+                    None => compute,
+                };
+                ("solution_stored", None, Some(detail))
+            }
+            "ElectionFinalized" => ("finalized", None, variant_name(fields.at("compute"))),
+            "ElectionFailed" => ("failed", None, None),
+            _ => continue,
+        };
+
+        let event_index = event.index();
+        let election_event = ElectionEvent {
+            block_number,
+            round,
+            kind: kind.to_string(),
+            detail,
+        };
+        updates.push("Failed to record election event", move |db| db.record_election_event(event_index, &election_event));
+    }
+}
+
+/// The current election phase and round, and what happened lately.
+pub async fn get_election(State(state): State<AppState>) -> Result<Json<serde_json::Value>, Error> {
+    let (phase, recent) = state
+        .db
+        .blocking(|db| {
+            let phase = db.get_election_events(Some("phase"), 1)?;
+            Ok::<_, rusqlite::Error>((phase, db.get_election_events(None, RECENT_EVENTS)?))
+        })
+        .await?;
+
+    let phase = phase.first();
+    Ok(Json(json!({
+        "phase": phase.and_then(|p| p.detail.clone()),
+        "round": phase.and_then(|p| p.round),
+        "since_block": phase.map(|p| p.block_number),
+        "recent": recent,
+    })))
+}
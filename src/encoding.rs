@@ -0,0 +1,86 @@
+//! CBOR and MessagePack for clients that ask for them with `Accept`, re-encoding the JSON the
+//! routes return. Meant for consumers where bandwidth matters more than a little CPU here.
+
+use axum::{
+    body::{self, Body},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+use crate::error::Error;
+
+// As in the envelope, more than any route returns
+const MAX_BODY: usize = 256 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Cbor,
+    MessagePack,
+}
+
+impl Format {
+    // The first supported type the client lists, JSON when it lists none
+    fn accepted(headers: &HeaderMap) -> Format {
+        let accept = headers.get(header::ACCEPT).and_then(|value| value.to_str().ok()).unwrap_or_default();
+        accept
+            .split(',')
+            .filter_map(|media| match media.split(';').next().unwrap_or_default().trim() {
+                "application/json" | "*/*" => Some(Format::Json),
+                "application/cbor" => Some(Format::Cbor),
+                "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                    Some(Format::MessagePack)
+                }
+                _ => None,
+            })
+            .next()
+            .unwrap_or(Format::Json)
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Cbor => "application/cbor",
+            Format::MessagePack => "application/msgpack",
+        }
+    }
+
+    fn encode(self, value: &Value) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Format::Cbor => cbor4ii::serde::to_vec(Vec::new(), value).map_err(|e| e.to_string()),
+            Format::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+pub async fn negotiate(request: Request, next: Next) -> Response {
+    let format = Format::accepted(request.headers());
+    let mut response = next.run(request).await;
+    response.headers_mut().append(header::VARY, HeaderValue::from_static("accept"));
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if format == Format::Json || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let encoded = async {
+        let bytes = body::to_bytes(body, MAX_BODY).await.map_err(|e| e.to_string())?;
+        let value: Value = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+        format.encode(&value)
+    };
+    let encoded = match encoded.await {
+        Ok(encoded) => encoded,
+        Err(e) => return Error::Other(format!("Failed to encode the response: {}", e)).into_response(),
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(format.content_type()));
+    Response::from_parts(parts, Body::from(encoded))
+}
@@ -0,0 +1,53 @@
+//! The `/v1` routes: the same resources as the unversioned ones, with each JSON body wrapped as
+//! `{"data": ..., "meta": {"chain", "api_version", "finalized"}}`, so fields can be added
+//! around the data later without breaking clients. Errors keep their `{"error", "code"}` body.
+
+use axum::{
+    body::{self, Body},
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+
+use crate::api::AppState;
+use crate::error::Error;
+
+pub const API_VERSION: u32 = 1;
+
+// Larger bodies than any route returns, so nothing is ever cut short
+const MAX_BODY: usize = 256 * 1024 * 1024;
+
+pub async fn wrap(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if !response.status().is_success() || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match body::to_bytes(body, MAX_BODY).await {
+        Ok(bytes) => bytes,
+        Err(e) => return Error::Other(format!("Failed to read the response: {}", e)).into_response(),
+    };
+    let data: Value = match serde_json::from_slice(&bytes) {
+        Ok(data) => data,
+        Err(e) => return Error::Other(format!("Failed to wrap the response: {}", e)).into_response(),
+    };
+    let wrapped = json!({
+        "data": data,
+        "meta": {
+            "chain": state.chain,
+            "api_version": API_VERSION,
+            "finalized": state.block_info.load().number,
+        },
+    });
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Response::from_parts(parts, Body::from(wrapped.to_string()))
+}
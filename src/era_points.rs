@@ -0,0 +1,109 @@
+//! Validator era reward points. When `Staking::EraPaid` closes an era, its
+//! `ErasRewardPoints` are read from storage into `era_points`, served on
+//! `/validators/:address/points`, so operators can compare their validator with the rest.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use subxt::dynamic;
+use subxt::events::Events;
+use subxt::ext::scale_value::{At, ValueDef};
+use subxt::utils::{AccountId32, H256};
+use subxt::{OnlineClient, PolkadotConfig};
+
+use crate::api::AppState;
+use crate::db;
+use crate::error::Error;
+use crate::decode;
+use crate::indexer::TrackerUpdates;
+use crate::reporting;
+
+const MAX_ERAS: u32 = 200;
+
+// (total, [(validator hex, points)])
+async fn fetch_points(
+    api: &OnlineClient<PolkadotConfig>,
+    at: H256,
+    era: u32,
+) -> Result<Option<(u32, Vec<(String, u32)>)>, subxt::Error> {
+    let key = vec![dynamic::Value::u128(era.into())];
+    let Some(points) = api.storage().at(at).fetch(&dynamic::storage("Staking", "ErasRewardPoints", key)).await? else {
+        return Ok(None);
+    };
+    let points = points.to_value()?;
+
+    let total = points.at("total").and_then(|v| v.as_u128()).unwrap_or_default() as u32;
+    // `individual` is a BTreeMap, which decodes as a list of (account, points)
+    let individual = match points.at("individual").map(|v| &v.value) {
+        Some(ValueDef::Composite(entries)) => entries
+            .values()
+            .filter_map(|entry| {
+                let validator = decode::bytes(entry.at(0)?)?;
+                let points = entry.at(1)?.as_u128()? as u32;
+                Some((format!("0x{}", hex::encode(validator)), points))
+            })
+            .collect(),
+        _ => vec![],
+    };
+    Ok(Some((total, individual)))
+}
+
+/// Records the points of eras paid out in this block.
+pub async fn record(
+    updates: &mut TrackerUpdates,
+    chain: &str,
+    api: &OnlineClient<PolkadotConfig>,
+    block_number: u32,
+    block_hash: H256,
+    events: &Events<PolkadotConfig>,
+) {
+    for event in events.iter().flatten() {
+        if event.pallet_name() != "Staking" || event.variant_name() != "EraPaid" {
+            continue;
+        }
+        let Some(era) = event
+            .field_values()
+            .ok()
+            .and_then(|fields| fields.at("era_index").and_then(|v| v.as_u128()))
+            .map(|era| era as u32)
+        else {
+            continue;
+        };
+
+        match fetch_points(api, block_hash, era).await {
+            Ok(Some((total, points))) => {
+                updates.push(format!("Failed to record era {} points", era), move |db| {
+                    db.record_era_points(era, block_number, total, &points)
+                });
+            }
+            Ok(None) => {}
+            Err(e) => reporting::block_error(chain, block_number, &format!("Failed to fetch era {} points: {}", era, e)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PointsQuery {
+    era: Option<u32>,
+    limit: Option<u32>,
+}
+
+/// A validator's points (and rank among all validators) in `?era=`, or its latest eras.
+pub async fn get_points(
+    State(state): State<AppState>,
+    Path(address): Path<String>,
+    Query(query): Query<PointsQuery>,
+) -> Result<Json<Vec<db::EraPoints>>, Error> {
+    let validator: AccountId32 =
+        address.parse().map_err(|_| Error::BadRequest(format!("Invalid address {:?}", address)))?;
+
+    let limit = query.limit.unwrap_or(30).min(MAX_ERAS);
+    let (key, era) = (format!("0x{}", hex::encode(validator.0)), query.era);
+    let points = state.db.blocking(move |db| db.get_era_points(&key, era, limit)).await?;
+    if let (true, Some(era)) = (points.is_empty(), query.era) {
+        return Err(Error::NotFound(format!("No points stored for {} in era {}", address, era)));
+    }
+    Ok(Json(points))
+}
@@ -0,0 +1,108 @@
+//! The crate's error type. Handlers return it and its variant picks the status code, with a
+//! body of `{"error": "<message>", "code": "<kind>"}` either way.
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+use std::fmt;
+
+use crate::db::SchemaError;
+
+#[derive(thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("Database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+    /// The light client, a node behind it, or the archive failed
+    #[error("{0}")]
+    Upstream(String),
+    /// The request took longer than `--request-timeout`
+    #[error("{0}")]
+    Timeout(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Other(String),
+}
+
+// `main` returning an error prints it with Debug, which should read like the message
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Other(message.to_string())
+    }
+}
+
+impl From<subxt::Error> for Error {
+    fn from(e: subxt::Error) -> Self {
+        Error::Upstream(e.to_string())
+    }
+}
+
+impl From<subxt::lightclient::LightClientError> for Error {
+    fn from(e: subxt::lightclient::LightClientError) -> Self {
+        Error::Upstream(e.to_string())
+    }
+}
+
+// Seconds a client is asked to wait after the database was too busy to answer
+const BUSY_RETRY_AFTER: &str = "1";
+
+impl Error {
+    // SQLite gave up waiting for another connection's lock, see `[sqlite] busy_timeout_ms`
+    fn is_busy(&self) -> bool {
+        let Error::Database(rusqlite::Error::SqliteFailure(e, _)) = self else {
+            return false;
+        };
+        matches!(e.code, rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+    }
+
+    fn status(&self) -> (StatusCode, &'static str) {
+        if self.is_busy() {
+            return (StatusCode::SERVICE_UNAVAILABLE, "busy");
+        }
+        match self {
+            Error::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
+            Error::Unauthorized(_) => (StatusCode::UNAUTHORIZED, "unauthorized"),
+            Error::Forbidden(_) => (StatusCode::FORBIDDEN, "forbidden"),
+            Error::NotFound(_) => (StatusCode::NOT_FOUND, "not_found"),
+            Error::Database(_) | Error::Schema(_) => (StatusCode::INTERNAL_SERVER_ERROR, "database"),
+            Error::Upstream(_) => (StatusCode::BAD_GATEWAY, "upstream"),
+            Error::Timeout(_) => (StatusCode::SERVICE_UNAVAILABLE, "timeout"),
+            Error::Io(_) | Error::Other(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+        }
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let (status, code) = self.status();
+        if self.is_busy() {
+            let body = Json(json!({ "error": "The database is busy, try again shortly", "code": code }));
+            return (status, [(header::RETRY_AFTER, BUSY_RETRY_AFTER)], body).into_response();
+        }
+        (status, Json(json!({ "error": self.to_string(), "code": code }))).into_response()
+    }
+}
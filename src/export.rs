@@ -0,0 +1,155 @@
+//! `smolcar export`: copies stored blocks into an analytics-ready database, with one typed
+//! row per block, extrinsic and event instead of a JSON blob per block.
+
+use clap::ValueEnum;
+use std::path::Path;
+
+use crate::db::Database;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Format {
+    /// A DuckDB database with `blocks`, `extrinsics` and `events` tables
+    Duckdb,
+}
+
+/// Appends every block of `chain` to the database at `output`, creating it if needed.
+/// Returns the number of blocks exported.
+pub fn run(format: Format, chain: &str, db: &Database, output: &Path) -> Result<u64, String> {
+    match format {
+        #[cfg(feature = "duckdb")]
+        Format::Duckdb => to_duckdb::export(chain, db, output),
+        #[cfg(not(feature = "duckdb"))]
+        Format::Duckdb => {
+            let _ = (chain, db, output);
+            Err("smolcar was built without the `duckdb` feature".to_string())
+        }
+    }
+}
+
+#[cfg(feature = "duckdb")]
+mod to_duckdb {
+    use duckdb::types::{TimeUnit, Value};
+    use duckdb::{params, Appender, Connection};
+    use std::path::Path;
+
+    use crate::db::{Database, StoredBlock};
+    use crate::decode;
+
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS blocks (
+            chain VARCHAR NOT NULL,
+            number UINTEGER NOT NULL,
+            hash VARCHAR NOT NULL,
+            timestamp TIMESTAMP NOT NULL,
+            extrinsics_count UINTEGER NOT NULL,
+            events_count UINTEGER NOT NULL,
+            PRIMARY KEY (chain, number)
+        );
+        CREATE TABLE IF NOT EXISTS extrinsics (
+            chain VARCHAR NOT NULL,
+            block_number UINTEGER NOT NULL,
+            extrinsic_index UINTEGER NOT NULL,
+            hash VARCHAR NOT NULL,
+            pallet VARCHAR NOT NULL,
+            call VARCHAR NOT NULL,
+            params VARCHAR NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS events (
+            chain VARCHAR NOT NULL,
+            block_number UINTEGER NOT NULL,
+            extrinsic_index UINTEGER NOT NULL,
+            pallet VARCHAR NOT NULL,
+            variant VARCHAR NOT NULL,
+            data VARCHAR NOT NULL
+        );
+    ";
+
+    pub fn export(chain: &str, db: &Database, output: &Path) -> Result<u64, String> {
+        let conn = Connection::open(output).map_err(|e| e.to_string())?;
+        conn.execute_batch(SCHEMA).map_err(|e| e.to_string())?;
+
+        // Re-exporting replaces the chain's rows instead of duplicating them
+        for table in ["blocks", "extrinsics", "events"] {
+            conn.execute(&format!("DELETE FROM {} WHERE chain = ?", table), params![chain])
+                .map_err(|e| e.to_string())?;
+        }
+
+        let mut appenders = Appenders {
+            blocks: conn.appender("blocks").map_err(|e| e.to_string())?,
+            extrinsics: conn.appender("extrinsics").map_err(|e| e.to_string())?,
+            events: conn.appender("events").map_err(|e| e.to_string())?,
+        };
+        let mut exported = 0;
+        let mut result = Ok(());
+
+        db.for_each_raw_block(|number, _hash, data| {
+            if result.is_err() {
+                return;
+            }
+            // `smolcar check` reports these, no reason to fail the whole export
+            let block: StoredBlock = match serde_json::from_str(data) {
+                Ok(block) => block,
+                Err(e) => {
+                    tracing::warn!(chain, block = number, "Skipping unreadable block: {}", e);
+                    return;
+                }
+            };
+            result = appenders.append(chain, &block);
+            exported += 1;
+        })
+        .map_err(|e| e.to_string())?;
+        result.map_err(|e| e.to_string())?;
+
+        appenders.blocks.flush().map_err(|e| e.to_string())?;
+        appenders.extrinsics.flush().map_err(|e| e.to_string())?;
+        appenders.events.flush().map_err(|e| e.to_string())?;
+        Ok(exported)
+    }
+
+    struct Appenders<'conn> {
+        blocks: Appender<'conn>,
+        extrinsics: Appender<'conn>,
+        events: Appender<'conn>,
+    }
+
+    impl Appenders<'_> {
+        fn append(&mut self, chain: &str, block: &StoredBlock) -> Result<(), duckdb::Error> {
+            let mut events_count = 0u32;
+            for extrinsic in &block.extrinsics {
+                let index = extrinsic["index"].as_u64().unwrap_or_default() as u32;
+                let action = extrinsic["action"].as_str().unwrap_or_default();
+                let (pallet, call) = action.split_once('/').unwrap_or((action, ""));
+                self.extrinsics.append_row(params![
+                    chain,
+                    block.number,
+                    index,
+                    extrinsic["hash"].as_str().unwrap_or_default(),
+                    pallet,
+                    call,
+                    decode::params_text(&extrinsic["params"]),
+                ])?;
+
+                for event in extrinsic["events"].as_array().into_iter().flatten() {
+                    self.events.append_row(params![
+                        chain,
+                        block.number,
+                        index,
+                        event["pallet"].as_str().unwrap_or_default(),
+                        event["variant"].as_str().unwrap_or_default(),
+                        event["data"].as_str().unwrap_or_default(),
+                    ])?;
+                    events_count += 1;
+                }
+            }
+
+            self.blocks.append_row(params![
+                chain,
+                block.number,
+                block.hash,
+                Value::Timestamp(TimeUnit::Second, block.timestamp),
+                block.extrinsics.len() as u32,
+                events_count,
+            ])
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! `GET /feed.atom?pallet=Referenda[&variant=Submitted]`: matching events as an Atom feed,
+//! so governance (or any other pallet) can be followed from a plain feed reader.
+
+use axum::{
+    extract::{OriginalUri, Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::fmt::Write;
+
+use crate::api::AppState;
+use crate::db::StoredEvent;
+use crate::error::Error;
+
+const DEFAULT_ENTRIES: usize = 50;
+const MAX_ENTRIES: usize = 200;
+// How far back from the latest block to look for matching events
+const SCAN_BLOCKS: u32 = 10_000;
+
+#[derive(Deserialize)]
+pub struct FeedQuery {
+    pallet: String,
+    variant: Option<String>,
+    limit: Option<usize>,
+}
+
+pub async fn get_feed(
+    State(state): State<AppState>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    Query(query): Query<FeedQuery>,
+) -> Result<Response, Error> {
+    let limit = query.limit.unwrap_or(DEFAULT_ENTRIES).min(MAX_ENTRIES);
+    let (pallet, variant) = (query.pallet.clone(), query.variant.clone());
+    let events = state
+        .db
+        .blocking(move |db| {
+            db.get_latest_block_number().and_then(|latest| match latest {
+                Some(latest) => db.find_events(
+                    &pallet,
+                    variant.as_deref(),
+                    latest.saturating_sub(SCAN_BLOCKS),
+                    latest,
+                    limit,
+                ),
+                None => Ok(vec![]),
+            })
+        })
+        .await?;
+
+    // Feed readers need absolute links, built from the address the feed was requested on
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok())
+        .unwrap_or("localhost");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|proto| proto.to_str().ok())
+        .unwrap_or("http");
+    let feed_url = format!("{}://{}{}", scheme, host, uri);
+    let chain_base = format!(
+        "{}://{}{}",
+        scheme,
+        host,
+        uri.path().trim_end_matches("/feed.atom")
+    );
+
+    let title = match &query.variant {
+        Some(variant) => format!("{}.{} events", query.pallet, variant),
+        None => format!("{} events", query.pallet),
+    };
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+        atom(&title, &feed_url, &chain_base, &events),
+    )
+        .into_response())
+}
+
+fn rfc3339(timestamp: i64) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .to_rfc3339()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn atom(title: &str, feed_url: &str, chain_base: &str, events: &[StoredEvent]) -> String {
+    let updated = events
+        .first()
+        .map(|event| event.timestamp)
+        .unwrap_or_else(|| Utc::now().timestamp());
+
+    let mut xml = String::new();
+    let _ = write!(
+        xml,
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>{title}</title>
+<id>{feed_url}</id>
+<link rel="self" href="{feed_url}"/>
+<updated>{updated}</updated>
+<author><name>smolcar</name></author>
+"#,
+        title = escape(title),
+        feed_url = escape(feed_url),
+        updated = rfc3339(updated),
+    );
+
+    for event in events {
+        let block_url = format!("{}/block/{}", chain_base, event.block_number);
+        let _ = write!(
+            xml,
+            r#"<entry>
+<title>{pallet}.{variant} in block #{number}</title>
+<id>urn:smolcar:{hash}:{extrinsic}:{index}</id>
+<link href="{block_url}"/>
+<updated>{updated}</updated>
+<content type="text">{data}</content>
+</entry>
+"#,
+            pallet = escape(&event.pallet),
+            variant = escape(&event.variant),
+            number = event.block_number,
+            hash = escape(&event.block_hash),
+            extrinsic = event.extrinsic_index,
+            index = event.event_index,
+            block_url = escape(&block_url),
+            updated = rfc3339(event.timestamp),
+            data = escape(&event.data),
+        );
+    }
+    xml.push_str("</feed>\n");
+
+    xml
+}
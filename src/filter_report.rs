@@ -0,0 +1,110 @@
+//! `smolcar filters --dry-run`: runs the configured filters over the latest stored blocks and
+//! reports what each one would drop, and roughly how much space that saves.
+//!
+//! Only stored blocks are looked at, so whatever earlier filters already dropped can't show up.
+
+use std::collections::BTreeMap;
+
+use crate::config::{ByteSize, Filters};
+use crate::db::{Cursor, Database, EventFilter};
+
+#[derive(Default)]
+struct Dropped {
+    extrinsics: u64,
+    events: u64,
+    bytes: u64,
+}
+
+// Which filter drops the extrinsic or event, if any. The `only_*` lists count as one filter.
+fn dropped_by(excluded: &[EventFilter], only: &[EventFilter], pallet: &str, method: &str) -> Option<String> {
+    if !only.is_empty() && !only.iter().any(|filter| filter.matches(pallet, method)) {
+        return Some("not in only_*".to_string());
+    }
+    excluded.iter().find(|filter| filter.matches(pallet, method)).map(|filter| match &filter.method {
+        Some(method) => format!("{}/{}", filter.pallet, method),
+        None => filter.pallet.clone(),
+    })
+}
+
+fn size(value: &serde_json::Value) -> u64 {
+    serde_json::to_string(value).map_or(0, |json| json.len() as u64)
+}
+
+/// Prints the report for the latest `blocks` blocks of one chain.
+pub fn run(db: &Database, filters: &Filters, blocks: u32) -> Result<(), rusqlite::Error> {
+    let excluded_events = filters.event_filters();
+    let excluded_extrinsics = Filters::parse(&filters.exclude_extrinsics);
+    let only_events = Filters::parse(&filters.only_events);
+    let only_extrinsics = Filters::parse(&filters.only_extrinsics);
+
+    let stored = db.get_blocks_page(Cursor::default(), blocks)?;
+    let (mut extrinsics, mut events, mut total_bytes) = (0u64, 0u64, 0u64);
+    let mut dropped: BTreeMap<String, Dropped> = BTreeMap::new();
+
+    for block in &stored {
+        for extrinsic in &block.extrinsics {
+            let extrinsic_bytes = size(extrinsic);
+            let extrinsic_events = extrinsic["events"].as_array().map(Vec::as_slice).unwrap_or_default();
+            extrinsics += 1;
+            events += extrinsic_events.len() as u64;
+            total_bytes += extrinsic_bytes;
+
+            // A dropped extrinsic takes its events with it
+            let action = extrinsic["action"].as_str().unwrap_or_default();
+            let (pallet, method) = action.split_once('/').unwrap_or((action, ""));
+            if let Some(filter) = dropped_by(&excluded_extrinsics, &only_extrinsics, pallet, method) {
+                let entry = dropped.entry(format!("{} (extrinsics)", filter)).or_default();
+                entry.extrinsics += 1;
+                entry.events += extrinsic_events.len() as u64;
+                entry.bytes += extrinsic_bytes;
+                continue;
+            }
+
+            for event in extrinsic_events {
+                let pallet = event["pallet"].as_str().unwrap_or_default();
+                let variant = event["variant"].as_str().unwrap_or_default();
+                if let Some(filter) = dropped_by(&excluded_events, &only_events, pallet, variant) {
+                    let entry = dropped.entry(format!("{} (events)", filter)).or_default();
+                    entry.events += 1;
+                    entry.bytes += size(event);
+                }
+            }
+        }
+    }
+
+    println!("Looked at the latest {} stored blocks\n", stored.len());
+    if dropped.is_empty() {
+        println!("The filters drop nothing in them");
+        return Ok(());
+    }
+
+    let dropped_extrinsics: u64 = dropped.values().map(|d| d.extrinsics).sum();
+    let dropped_events: u64 = dropped.values().map(|d| d.events).sum();
+    let dropped_bytes: u64 = dropped.values().map(|d| d.bytes).sum();
+    println!("Extrinsics: {}, would drop {}", extrinsics, dropped_extrinsics);
+    println!("Events: {}, would drop {}\n", events, dropped_events);
+
+    let mut by_size: Vec<_> = dropped.into_iter().collect();
+    by_size.sort_by_key(|(_, d)| std::cmp::Reverse(d.bytes));
+    for (filter, d) in &by_size {
+        println!(
+            "  {:<50} {:>8} extrinsics {:>8} events {:>10}",
+            filter,
+            d.extrinsics,
+            d.events,
+            ByteSize(d.bytes).to_string()
+        );
+    }
+
+    let share = dropped_bytes as f64 / total_bytes.max(1) as f64;
+    let used = db.used_bytes()?;
+    println!(
+        "\nSaves {} of {} in these blocks ({:.1}%), around {} of the {} database",
+        ByteSize(dropped_bytes),
+        ByteSize(total_bytes),
+        share * 100.0,
+        ByteSize((used as f64 * share) as u64),
+        ByteSize(used)
+    );
+    Ok(())
+}
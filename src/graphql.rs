@@ -0,0 +1,314 @@
+//! GraphQL API on `/graphql`: queries over HTTP POST, and `newBlock` / `event` subscriptions
+//! over WebSocket (both graphql-transport-ws and the older graphql-ws protocol).
+//!
+//! Like the gRPC API, every field takes an optional `chain`, defaulting to the first one.
+
+use async_graphql::futures_util::{self, SinkExt};
+use async_graphql::http::{WebSocket, WebSocketProtocols, WsMessage, ALL_WEBSOCKET_PROTOCOLS};
+use async_graphql::{EmptyMutation, Object, Schema, SimpleObject, Subscription};
+use axum::{
+    extract::ws::{self, CloseFrame, WebSocketUpgrade},
+    extract::State,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use std::sync::Arc;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::api::AppState;
+use crate::db::{Database, StoredBlock};
+use crate::decode;
+use crate::stream::{EventStream, Message};
+
+const DEFAULT_EVENTS_LIMIT: usize = 100;
+const MAX_EVENTS_LIMIT: usize = 1000;
+
+type SmolcarSchema = Schema<Query, EmptyMutation, Subscription>;
+
+struct Chains {
+    chains: Vec<(String, Arc<Database>)>,
+    stream: EventStream,
+}
+
+impl Chains {
+    fn chain(&self, name: Option<&str>) -> async_graphql::Result<(String, Arc<Database>)> {
+        let found = match name {
+            Some(name) => self.chains.iter().find(|(chain, _)| chain == name),
+            None => self.chains.first(),
+        };
+        found
+            .cloned()
+            .ok_or_else(|| format!("unknown chain {:?}", name.unwrap_or_default()).into())
+    }
+}
+
+fn db_error(e: rusqlite::Error) -> async_graphql::Error {
+    format!("Database error: {}", e).into()
+}
+
+#[derive(SimpleObject)]
+struct Block {
+    chain: String,
+    number: u32,
+    hash: String,
+    timestamp: i64,
+    extrinsics: Vec<Extrinsic>,
+}
+
+#[derive(SimpleObject)]
+struct Extrinsic {
+    index: u32,
+    hash: String,
+    pallet: String,
+    call: String,
+    /// Decoded call arguments as JSON text
+    params: String,
+    events: Vec<Event>,
+}
+
+#[derive(SimpleObject)]
+struct Event {
+    pallet: String,
+    variant: String,
+    data: String,
+}
+
+#[derive(SimpleObject)]
+struct EventRecord {
+    chain: String,
+    block_number: u32,
+    extrinsic_index: u32,
+    event: Event,
+}
+
+impl Block {
+    fn new(chain: &str, block: StoredBlock) -> Self {
+        let extrinsics = block
+            .extrinsics
+            .iter()
+            .map(|extrinsic| {
+                let action = extrinsic["action"].as_str().unwrap_or_default();
+                let (pallet, call) = action.split_once('/').unwrap_or((action, ""));
+                Extrinsic {
+                    index: extrinsic["index"].as_u64().unwrap_or_default() as u32,
+                    hash: extrinsic["hash"].as_str().unwrap_or_default().to_string(),
+                    pallet: pallet.to_string(),
+                    call: call.to_string(),
+                    params: decode::params_text(&extrinsic["params"]),
+                    events: extrinsic["events"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .map(|event| Event {
+                            pallet: event["pallet"].as_str().unwrap_or_default().to_string(),
+                            variant: event["variant"].as_str().unwrap_or_default().to_string(),
+                            data: event["data"].as_str().unwrap_or_default().to_string(),
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Block {
+            chain: chain.to_string(),
+            number: block.number,
+            hash: block.hash,
+            timestamp: block.timestamp,
+            extrinsics,
+        }
+    }
+}
+
+struct Query(Arc<Chains>);
+
+#[Object]
+impl Query {
+    /// A stored block by number or hash, or the latest one if neither is given.
+    async fn block(
+        &self,
+        chain: Option<String>,
+        number: Option<u32>,
+        hash: Option<String>,
+    ) -> async_graphql::Result<Option<Block>> {
+        let (chain, db) = self.0.chain(chain.as_deref())?;
+        let block = db
+            .blocking(move |db| match (number, hash) {
+                (Some(number), _) => db.get_block(number),
+                (None, Some(hash)) => db.get_block_by_hash(&hash.to_ascii_lowercase()),
+                (None, None) => db.get_latest_block_number().and_then(|latest| match latest {
+                    Some(number) => db.get_block(number),
+                    None => Ok(None),
+                }),
+            })
+            .await
+            .map_err(db_error)?;
+
+        Ok(block.map(|block| Block::new(&chain, block)))
+    }
+
+    /// Stored events of a pallet (and variant), newest first.
+    // Every GraphQL argument is a parameter
+    #[allow(clippy::too_many_arguments)]
+    async fn events(
+        &self,
+        chain: Option<String>,
+        pallet: String,
+        variant: Option<String>,
+        from_block: Option<u32>,
+        to_block: Option<u32>,
+        limit: Option<usize>,
+    ) -> async_graphql::Result<Vec<EventRecord>> {
+        let (chain, db) = self.0.chain(chain.as_deref())?;
+        let to = match to_block {
+            Some(to) => to,
+            None => match db.blocking(|db| db.get_latest_block_number()).await.map_err(db_error)? {
+                Some(latest) => latest,
+                None => return Ok(vec![]),
+            },
+        };
+        let limit = limit.unwrap_or(DEFAULT_EVENTS_LIMIT).min(MAX_EVENTS_LIMIT);
+
+        let events = db
+            .blocking(move |db| db.find_events(&pallet, variant.as_deref(), from_block.unwrap_or(0), to, limit))
+            .await
+            .map_err(db_error)?;
+        Ok(events
+            .into_iter()
+            .map(|event| EventRecord {
+                chain: chain.clone(),
+                block_number: event.block_number,
+                extrinsic_index: event.extrinsic_index,
+                event: Event {
+                    pallet: event.pallet,
+                    variant: event.variant,
+                    data: event.data,
+                },
+            })
+            .collect())
+    }
+}
+
+struct Subscription(Arc<Chains>);
+
+// Subscribers that fall behind skip what they missed, like on `/stream`
+#[Subscription]
+impl Subscription {
+    /// Every block stored from now on.
+    async fn new_block(&self, chain: Option<String>) -> async_graphql::Result<impl Stream<Item = Block>> {
+        let (chain, db) = self.0.chain(chain.as_deref())?;
+        let messages = BroadcastStream::new(self.0.stream.receiver());
+        Ok(futures_util::StreamExt::filter_map(messages, move |message| {
+            let (chain, db) = (chain.clone(), db.clone());
+            async move {
+                match message {
+                    Ok(Message::Block(block)) if block.chain == chain => {
+                        match db.blocking(move |db| db.get_block(block.number)).await {
+                            Ok(block) => block.map(|block| Block::new(&chain, block)),
+                            Err(e) => {
+                                tracing::warn!(chain, "GraphQL newBlock subscription failed to read a block: {}", e);
+                                None
+                            }
+                        }
+                    }
+                    _ => None,
+                }
+            }
+        }))
+    }
+
+    /// Every event of `pallet` (and `variant`, if given) stored from now on.
+    async fn event(
+        &self,
+        chain: Option<String>,
+        pallet: String,
+        variant: Option<String>,
+    ) -> async_graphql::Result<impl Stream<Item = EventRecord>> {
+        let (chain, _) = self.0.chain(chain.as_deref())?;
+        Ok(BroadcastStream::new(self.0.stream.receiver()).filter_map(move |message| {
+            match message {
+                Ok(Message::Event {
+                    chain: event_chain,
+                    block_number,
+                    extrinsic_index,
+                    pallet: event_pallet,
+                    variant: event_variant,
+                    data,
+                }) if event_chain == chain
+                    && event_pallet == pallet
+                    && variant.as_ref().is_none_or(|v| *v == event_variant) =>
+                {
+                    Some(EventRecord {
+                        chain: event_chain,
+                        block_number,
+                        extrinsic_index,
+                        event: Event {
+                            pallet: event_pallet,
+                            variant: event_variant,
+                            data,
+                        },
+                    })
+                }
+                _ => None,
+            }
+        }))
+    }
+}
+
+pub fn router(chains: &[(String, AppState)], stream: EventStream) -> Router {
+    let chains = Arc::new(Chains {
+        chains: chains
+            .iter()
+            .map(|(name, state)| (name.clone(), state.db.clone()))
+            .collect(),
+        stream,
+    });
+    let schema = Schema::build(Query(chains.clone()), EmptyMutation, Subscription(chains)).finish();
+
+    Router::new()
+        .route("/graphql", get(subscribe).post(execute))
+        .with_state(schema)
+}
+
+async fn execute(
+    State(schema): State<SmolcarSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(request).await)
+}
+
+async fn subscribe(State(schema): State<SmolcarSchema>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |socket| async move {
+            // Clients that don't ask for a protocol get the older graphql-ws one
+            let protocol = socket
+                .protocol()
+                .and_then(|protocol| protocol.to_str().ok()?.parse().ok())
+                .unwrap_or(WebSocketProtocols::SubscriptionsTransportWS);
+            let (mut sink, incoming) = futures_util::StreamExt::split(socket);
+            let incoming = incoming
+                .take_while(|message| message.is_ok())
+                .filter_map(|message| match message {
+                    Ok(ws::Message::Text(text)) => Some(text.into_bytes()),
+                    Ok(ws::Message::Binary(bytes)) => Some(bytes),
+                    _ => None,
+                });
+
+            let mut outgoing = WebSocket::new(schema, incoming, protocol);
+            while let Some(message) = outgoing.next().await {
+                let message = match message {
+                    WsMessage::Text(text) => ws::Message::Text(text),
+                    WsMessage::Close(code, reason) => ws::Message::Close(Some(CloseFrame {
+                        code,
+                        reason: reason.into(),
+                    })),
+                };
+                if sink.send(message).await.is_err() {
+                    break;
+                }
+            }
+        })
+        .into_response()
+}
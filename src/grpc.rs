@@ -0,0 +1,243 @@
+//! gRPC API (see `proto/smolcar.proto`), served on its own address next to the HTTP API
+//! for backend consumers that want typed messages and streaming instead of JSON.
+
+// tonic::Status is large, but it's what every handler has to return anyway
+#![allow(clippy::result_large_err)]
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+use crate::db::{Database, StoredBlock};
+use crate::decode;
+use crate::stream::{EventStream, Message};
+
+mod proto {
+    tonic::include_proto!("smolcar.v1");
+}
+
+use proto::smolcar_server::{Smolcar, SmolcarServer};
+use proto::{
+    get_block_request, Block, Event, EventRecord, Extrinsic, GetBlockRequest, QueryEventsRequest,
+    QueryEventsResponse, StreamBlocksRequest,
+};
+
+const DEFAULT_EVENTS_LIMIT: u32 = 100;
+const MAX_EVENTS_LIMIT: u32 = 1000;
+// Blocks read from the database at a time when replaying
+const PAGE: u32 = 100;
+
+struct Service {
+    chains: Vec<(String, Arc<Database>)>,
+    stream: EventStream,
+}
+
+impl Service {
+    fn chain(&self, name: &str) -> Result<(&str, &Arc<Database>), Status> {
+        let found = if name.is_empty() {
+            self.chains.first()
+        } else {
+            self.chains.iter().find(|(chain, _)| chain == name)
+        };
+        found
+            .map(|(chain, db)| (chain.as_str(), db))
+            .ok_or_else(|| Status::not_found(format!("unknown chain {:?}", name)))
+    }
+}
+
+fn db_error(e: rusqlite::Error) -> Status {
+    Status::internal(format!("Database error: {}", e))
+}
+
+fn to_proto(chain: &str, block: StoredBlock) -> Block {
+    let extrinsics = block
+        .extrinsics
+        .iter()
+        .map(|extrinsic| {
+            let action = extrinsic["action"].as_str().unwrap_or_default();
+            let (pallet, call) = action.split_once('/').unwrap_or((action, ""));
+            Extrinsic {
+                index: extrinsic["index"].as_u64().unwrap_or_default() as u32,
+                hash: extrinsic["hash"].as_str().unwrap_or_default().to_string(),
+                pallet: pallet.to_string(),
+                call: call.to_string(),
+                params: decode::params_text(&extrinsic["params"]),
+                events: extrinsic["events"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(|event| Event {
+                        pallet: event["pallet"].as_str().unwrap_or_default().to_string(),
+                        variant: event["variant"].as_str().unwrap_or_default().to_string(),
+                        data: event["data"].as_str().unwrap_or_default().to_string(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Block {
+        chain: chain.to_string(),
+        number: block.number,
+        hash: block.hash,
+        timestamp: block.timestamp,
+        extrinsics,
+    }
+}
+
+type BlockStream = Pin<Box<dyn Stream<Item = Result<Block, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Smolcar for Service {
+    async fn get_block(&self, request: Request<GetBlockRequest>) -> Result<Response<Block>, Status> {
+        let request = request.into_inner();
+        let (chain, db) = self.chain(&request.chain)?;
+
+        let block = db
+            .blocking(move |db| match &request.id {
+                Some(get_block_request::Id::Number(number)) => db.get_block(*number),
+                Some(get_block_request::Id::Hash(hash)) => db.get_block_by_hash(&hash.to_ascii_lowercase()),
+                None => db.get_latest_block_number().and_then(|latest| match latest {
+                    Some(number) => db.get_block(number),
+                    None => Ok(None),
+                }),
+            })
+            .await
+            .map_err(db_error)?;
+
+        match block {
+            Some(block) => Ok(Response::new(to_proto(chain, block))),
+            None => Err(Status::not_found("block not found")),
+        }
+    }
+
+    type StreamBlocksStream = BlockStream;
+
+    async fn stream_blocks(
+        &self,
+        request: Request<StreamBlocksRequest>,
+    ) -> Result<Response<Self::StreamBlocksStream>, Status> {
+        let request = request.into_inner();
+        let (chain, db) = self.chain(&request.chain)?;
+        let (chain, db) = (chain.to_string(), db.clone());
+        // Subscribe before replaying so nothing stored in between is missed
+        let mut live = self.stream.receiver();
+        let (tx, rx) = mpsc::channel(16);
+
+        tokio::spawn(async move {
+            let mut next = request.from;
+            let mut replayed = None;
+            while let Some(from) = next {
+                let blocks = match db.blocking(move |db| db.get_blocks_from(from, PAGE)).await {
+                    Ok(blocks) => blocks,
+                    Err(e) => {
+                        let _ = tx.send(Err(db_error(e))).await;
+                        return;
+                    }
+                };
+                next = match blocks.last() {
+                    Some(last) if blocks.len() as u32 == PAGE => Some(last.number + 1),
+                    _ => None,
+                };
+                for block in blocks {
+                    replayed = Some(block.number);
+                    if tx.send(Ok(to_proto(&chain, block))).await.is_err() {
+                        return;
+                    }
+                }
+            }
+
+            loop {
+                let number = match live.recv().await {
+                    // Skip what the replay already covered
+                    Ok(Message::Block(block))
+                        if block.chain == chain && replayed.is_none_or(|r| block.number > r) =>
+                    {
+                        block.number
+                    }
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(chain, skipped, "gRPC block stream fell behind");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                let block = match db.blocking(move |db| db.get_block(number)).await {
+                    Ok(Some(block)) => block,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        let _ = tx.send(Err(db_error(e))).await;
+                        return;
+                    }
+                };
+                if tx.send(Ok(to_proto(&chain, block))).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn query_events(
+        &self,
+        request: Request<QueryEventsRequest>,
+    ) -> Result<Response<QueryEventsResponse>, Status> {
+        let request = request.into_inner();
+        let (_, db) = self.chain(&request.chain)?;
+        if request.pallet.is_empty() {
+            return Err(Status::invalid_argument("pallet is required"));
+        }
+        let limit = match request.limit {
+            0 => DEFAULT_EVENTS_LIMIT,
+            limit => limit.min(MAX_EVENTS_LIMIT),
+        } as usize;
+        let from = request.from_block.unwrap_or(0);
+        let to = match request.to_block {
+            Some(to) => to,
+            None => match db.blocking(|db| db.get_latest_block_number()).await.map_err(db_error)? {
+                Some(latest) => latest,
+                None => return Ok(Response::new(QueryEventsResponse::default())),
+            },
+        };
+
+        let events = db
+            .blocking(move |db| {
+                let variant = Some(request.variant.as_str()).filter(|v| !v.is_empty());
+                db.find_events(&request.pallet, variant, from, to, limit)
+            })
+            .await
+            .map_err(db_error)?
+            .into_iter()
+            .map(|event| EventRecord {
+                block_number: event.block_number,
+                extrinsic_index: event.extrinsic_index,
+                event: Some(Event {
+                    pallet: event.pallet,
+                    variant: event.variant,
+                    data: event.data,
+                }),
+            })
+            .collect();
+
+        Ok(Response::new(QueryEventsResponse { events }))
+    }
+}
+
+/// Serves the gRPC API on `addr` until the process exits.
+pub async fn serve(
+    addr: SocketAddr,
+    chains: Vec<(String, Arc<Database>)>,
+    stream: EventStream,
+) -> Result<(), String> {
+    tracing::info!(%addr, "gRPC API running");
+    tonic::transport::Server::builder()
+        .add_service(SmolcarServer::new(Service { chains, stream }))
+        .serve(addr)
+        .await
+        .map_err(|e| format!("gRPC server on {} failed: {}", addr, e))
+}
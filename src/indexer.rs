@@ -0,0 +1,898 @@
+use arc_swap::ArcSwap;
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use subxt::config::Header;
+use subxt::ext::codec::Encode;
+use subxt::ext::sp_runtime::traits::{BlakeTwo256, Hash};
+use subxt::ext::sp_runtime::StateVersion;
+use subxt::config::signed_extensions::CheckMortality;
+use subxt::error::DispatchError;
+use subxt::ext::scale_value::{self, At, ValueDef};
+use subxt::utils::{Era, H256};
+use subxt::Metadata;
+use subxt::backend::legacy::LegacyRpcMethods;
+use subxt::backend::StreamOfResults;
+use subxt::blocks::Block;
+use subxt::{client::OnlineClient, PolkadotConfig};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::alerts::Alerts;
+use crate::assets;
+use crate::babe;
+use crate::backfill;
+use crate::db;
+use crate::decode;
+use crate::decoders::Decoders;
+use crate::election;
+use crate::era_points;
+use crate::justifications;
+use crate::locks;
+use crate::nfts;
+use crate::offences;
+use crate::parachains;
+use crate::referenda;
+use crate::reporting;
+use crate::rules::EventContext;
+use crate::scheduler;
+use crate::sinks::{BlockSummary, Sinks};
+use crate::typed::{self, TypedEvent};
+use crate::validators;
+use crate::vesting;
+use crate::webhooks;
+use crate::xcm;
+
+/// Finalized blocks waiting to be processed. They stay pinned on the light client meanwhile.
+const BLOCK_QUEUE: usize = 64;
+/// Processed blocks waiting to be written
+const STORE_QUEUE: usize = 8;
+
+/// Why fields couldn't be decoded, next to their undecoded bytes so they can be re-decoded later
+#[derive(Clone, Serialize)]
+pub struct DecodeError {
+    pub message: String,
+    /// SCALE-encoded fields as hex
+    pub raw: String,
+}
+
+impl DecodeError {
+    fn new(error: impl std::fmt::Display, bytes: &[u8]) -> Self {
+        DecodeError {
+            message: error.to_string(),
+            raw: format!("0x{}", hex::encode(bytes)),
+        }
+    }
+}
+
+// Decoded fields as text, or empty with the reason they couldn't be decoded
+fn decode_fields<T: Default, E: std::fmt::Display>(
+    values: Result<subxt::ext::scale_value::Composite<u32>, E>,
+    bytes: &[u8],
+    render: impl FnOnce(subxt::ext::scale_value::Composite<u32>) -> T,
+) -> (T, Option<DecodeError>) {
+    match values {
+        Ok(values) => (render(values), None),
+        Err(e) => (T::default(), Some(DecodeError::new(e, bytes))),
+    }
+}
+
+/// Weight and fee details of an extrinsic, from its `System/ExtrinsicSuccess` or
+/// `System/ExtrinsicFailed` event.
+#[derive(Clone, Serialize)]
+pub struct DispatchInfo {
+    pub ref_time: u64,
+    /// Missing on runtimes from before weights had a proof size
+    pub proof_size: Option<u64>,
+    /// "Normal", "Operational" or "Mandatory"
+    pub class: String,
+    pub pays_fee: bool,
+}
+
+impl DispatchInfo {
+    fn from_event(fields: &scale_value::Composite<u32>) -> Option<Self> {
+        let info = fields.at("dispatch_info")?;
+        let weight = info.at("weight")?;
+        let variant_name = |value: &scale_value::Value<u32>| match &value.value {
+            ValueDef::Variant(variant) => Some(variant.name.clone()),
+            _ => None,
+        };
+        let (ref_time, proof_size) = match weight.at("ref_time") {
+            Some(ref_time) => (ref_time.as_u128()?, weight.at("proof_size").and_then(|p| p.as_u128())),
+            // A bare number before weights v2
+            None => (weight.as_u128()?, None),
+        };
+
+        Some(DispatchInfo {
+            ref_time: ref_time as u64,
+            proof_size: proof_size.map(|p| p as u64),
+            class: variant_name(info.at("class")?)?,
+            pays_fee: variant_name(info.at("pays_fee")?)? == "Yes",
+        })
+    }
+}
+
+/// How long a signed extrinsic was valid for, from its `CheckMortality` extension.
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mortality {
+    Immortal,
+    Mortal { period: u64, phase: u64 },
+}
+
+impl From<Era> for Mortality {
+    fn from(era: Era) -> Self {
+        match era {
+            Era::Immortal => Mortality::Immortal,
+            Era::Mortal { period, phase } => Mortality::Mortal { period, phase },
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct EventInfo {
+    pub pallet: String,
+    pub variant: String,
+    pub data: String,
+    /// For well-known events, see `typed.rs`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub typed: Option<TypedEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decode_error: Option<DecodeError>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ExtrinsicInfo {
+    pub index: u32,
+    pub hash: String,
+    pub action: String,
+    /// Arguments by name, see `decode::Context::fields_to_json`. Null when they couldn't be
+    /// decoded, and text in blocks stored before they were a tree
+    pub params: serde_json::Value,
+    /// Calls wrapped by this one (sudo, proxy, multisig, batch...), recursively
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub calls: Vec<decode::Call>,
+    pub events: Vec<EventInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<decode::Address>,
+    // From the signed extensions, so only on signed extrinsics
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    /// In the chain's smallest unit, as a string since it may not fit in a JSON number
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub era: Option<Mortality>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dispatch_info: Option<DispatchInfo>,
+    /// Why the extrinsic failed, e.g. "Balances::InsufficientBalance"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Set when `params` couldn't be decoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decode_error: Option<DecodeError>,
+    /// The SCALE-encoded extrinsic as hex, only returned by the API on `?include=raw`
+    #[serde(skip_serializing_if = "raw_skipped")]
+    pub raw: String,
+}
+
+thread_local! {
+    // Set while serializing through `skipping_raw`
+    static SKIP_RAW: Cell<bool> = const { Cell::new(false) };
+}
+
+fn raw_skipped<T>(_: &T) -> bool {
+    SKIP_RAW.get()
+}
+
+/// Runs `serialize` with the extrinsics' `raw` left out, so the shared head can be served as
+/// it is instead of copied to remove it.
+pub fn skipping_raw<T>(serialize: impl FnOnce() -> T) -> T {
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            SKIP_RAW.set(false);
+        }
+    }
+    SKIP_RAW.set(true);
+    let _reset = Reset;
+    serialize()
+}
+
+#[derive(Clone, Serialize)]
+pub struct BlockInfo {
+    pub number: u32,
+    pub hash: String,
+    pub extrinsics_count: usize,
+    pub events_count: usize,
+    pub extrinsics: Vec<ExtrinsicInfo>,
+}
+
+impl Default for BlockInfo {
+    fn default() -> Self {
+        BlockInfo {
+            number: 0,
+            hash: String::from("0x0"),
+            extrinsics_count: 0,
+            events_count: 0,
+            extrinsics: vec![],
+        }
+    }
+}
+
+type TrackerWrite = Box<dyn FnOnce(&db::Database) -> Result<(), rusqlite::Error> + Send>;
+
+/// What the trackers (scheduler, assets, referenda...) write for a block. Blocks are processed
+/// several at once, so this is held back and written along with the block, in order.
+#[derive(Default)]
+pub struct TrackerUpdates(Vec<(String, TrackerWrite)>);
+
+impl TrackerUpdates {
+    /// `failure` is what's reported if the write fails, e.g. "Failed to record offence".
+    pub fn push(
+        &mut self,
+        failure: impl Into<String>,
+        write: impl FnOnce(&db::Database) -> Result<(), rusqlite::Error> + Send + 'static,
+    ) {
+        self.0.push((failure.into(), Box::new(write)));
+    }
+
+    fn apply(self, db: &db::Database, chain: &str, block_number: u32) {
+        for (failure, write) in self.0 {
+            if let Err(e) = write(db) {
+                reporting::block_error(chain, block_number, &format!("{}: {}", failure, e));
+            }
+        }
+    }
+}
+
+/// The latest stored block, swapped in whole so readers never wait on (or copy) it
+pub type SharedBlockInfo = Arc<ArcSwap<BlockInfo>>;
+
+/// With `--read-only` another smolcar stores the blocks, so the head is polled from the
+/// database instead. Like in low-memory mode, it comes without its extrinsics.
+pub async fn follow_stored(db: Arc<db::Database>, block_info: SharedBlockInfo) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(2));
+    loop {
+        interval.tick().await;
+        let latest = db.blocking(|db| db.get_blocks_page(db::Cursor::default(), 1)).await;
+        let Ok(Some(latest)) = latest.map(|mut blocks| blocks.pop()) else {
+            continue;
+        };
+        if latest.number == block_info.load().number {
+            continue;
+        }
+        let events_count = latest
+            .extrinsics
+            .iter()
+            .map(|extrinsic| extrinsic["events"].as_array().map_or(0, Vec::len))
+            .sum();
+        block_info.store(Arc::new(BlockInfo {
+            number: latest.number,
+            hash: latest.hash,
+            extrinsics_count: latest.extrinsics.len(),
+            events_count,
+            extrinsics: vec![],
+        }));
+    }
+}
+
+/// Where decoded extrinsics go while a block is processed. In low-memory mode each one is
+/// serialized straight into the block's JSON and dropped, instead of collecting the whole block.
+enum ExtrinsicSink {
+    Collect(Vec<ExtrinsicInfo>),
+    Stream { json: String, count: usize },
+}
+
+impl ExtrinsicSink {
+    fn new(low_memory: bool) -> Self {
+        if low_memory {
+            ExtrinsicSink::Stream { json: String::from("["), count: 0 }
+        } else {
+            ExtrinsicSink::Collect(Vec::new())
+        }
+    }
+
+    fn push(&mut self, extrinsic: ExtrinsicInfo) {
+        match self {
+            ExtrinsicSink::Collect(extrinsics) => extrinsics.push(extrinsic),
+            ExtrinsicSink::Stream { json, count } => {
+                if *count > 0 {
+                    json.push(',');
+                }
+                json.push_str(&serde_json::to_string(&extrinsic).unwrap());
+                *count += 1;
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            ExtrinsicSink::Collect(extrinsics) => extrinsics.len(),
+            ExtrinsicSink::Stream { count, .. } => *count,
+        }
+    }
+}
+
+// Runtimes pick the trie layout of the extrinsics root, older ones V0 and newer ones V1
+fn extrinsics_root_matches(extrinsics: Vec<Vec<u8>>, root: H256) -> bool {
+    [StateVersion::V0, StateVersion::V1]
+        .into_iter()
+        .any(|version| BlakeTwo256::ordered_trie_root(extrinsics.clone(), version).as_bytes() == root.as_bytes())
+}
+
+// `System::ExtrinsicFailed` starts with the DispatchError. Module errors are resolved to
+// "Pallet::Error" through the metadata, the other kinds are named by their variant
+fn dispatch_error_name(event_fields: &[u8], metadata: Metadata) -> String {
+    match DispatchError::decode_from(event_fields, metadata) {
+        Ok(DispatchError::Module(module)) => module.details_string(),
+        Ok(other) => format!("{:?}", other),
+        Err(e) => format!("Undecodable dispatch error: {}", e),
+    }
+}
+
+/// Follows finalized blocks of one chain and stores them, this could use some cleaning up (not too much though!)
+pub struct Indexer {
+    pub chain: String,
+    pub api: OnlineClient<PolkadotConfig>,
+    pub db: Arc<db::Database>,
+    pub block_info: SharedBlockInfo,
+    pub low_memory: bool,
+    pub track_inclusions: bool,
+    /// Set when justifications should be stored
+    pub justifications: Option<LegacyRpcMethods<PolkadotConfig>>,
+    /// How many blocks are processed at once
+    pub parallelism: usize,
+    pub ss58_prefix: u16,
+    /// Custom decoders for the chain's own pallets
+    pub decoders: Arc<Decoders>,
+    pub alerts: Arc<Alerts>,
+    pub sinks: Arc<Sinks>,
+    /// Ranges to fetch from before following started (`[backfill]`, catching up), see `backfill.rs`
+    pub backfills: Vec<backfill::Backfill>,
+}
+
+impl Indexer {
+    pub async fn run(self) {
+        let Indexer {
+            chain,
+            api,
+            db,
+            block_info,
+            low_memory,
+            track_inclusions,
+            justifications: justification_rpc,
+            parallelism,
+            ss58_prefix,
+            decoders,
+            alerts,
+            sinks,
+            backfills,
+        } = self;
+
+        // The `finalized` events of `chainHead_v1_follow`, in order. Forks it prunes are never
+        // handed out, so nothing unfinalized gets stored.
+        let blocks_sub = match api.blocks().subscribe_finalized().await {
+            Ok(sub) => sub,
+            Err(e) => {
+                reporting::error(&chain, &format!("Failed to subscribe to finalized blocks: {}", e));
+                return;
+            }
+        };
+
+        // Following, processing and storing each run on their own, with bounded queues between
+        // them, so a slow stage is noticed instead of silently stalling the subscription
+        let (blocks_tx, mut blocks_rx) = mpsc::channel(BLOCK_QUEUE);
+        let (store_tx, store_rx) = mpsc::channel(STORE_QUEUE);
+        tokio::spawn(follow(chain.clone(), blocks_sub, blocks_tx));
+        tokio::spawn(store(chain.clone(), db.clone(), block_info, sinks.clone(), store_rx));
+
+        let processor = Arc::new(Processor {
+            chain: chain.clone(),
+            api: api.clone(),
+            db: db.clone(),
+            low_memory,
+            track_inclusions,
+            justification_rpc,
+            ss58_prefix,
+            decoders,
+            alerts,
+            sinks: sinks.clone(),
+            webhook_http: reqwest::Client::new(),
+            live: true,
+        });
+        for backfill in backfills {
+            let historical = Arc::new(processor.historical(backfill.api.clone()));
+            tokio::spawn(backfill.run(chain.clone(), api.clone(), db.clone(), historical));
+        }
+
+        // Up to `parallelism` blocks are processed at once, but handed on in the order they came
+        let parallelism = parallelism.max(1);
+        let mut in_flight = VecDeque::with_capacity(parallelism);
+        while let Some(block) = blocks_rx.recv().await {
+            in_flight.push_back(tokio::spawn(processor.clone().process(block)));
+            while in_flight.len() < parallelism {
+                match blocks_rx.try_recv() {
+                    Ok(block) => in_flight.push_back(tokio::spawn(processor.clone().process(block))),
+                    Err(_) => break,
+                }
+            }
+
+            // Wait for the oldest while the window is full, or for all of them once nothing is queued
+            while in_flight.len() >= parallelism || (blocks_rx.is_empty() && !in_flight.is_empty()) {
+                let Some(task) = in_flight.pop_front() else {
+                    break;
+                };
+                let processed = match task.await {
+                    Ok(Some(processed)) => processed,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        reporting::error(&chain, &format!("Block processing task failed: {}", e));
+                        continue;
+                    }
+                };
+                let block_number = processed.number;
+                let processed = match store_tx.try_send(processed) {
+                    Ok(()) => continue,
+                    Err(TrySendError::Full(processed)) => processed,
+                    Err(TrySendError::Closed(_)) => return,
+                };
+                tracing::warn!(chain = %chain, number = block_number, "Storing blocks is falling behind, waiting for it");
+                if store_tx.send(processed).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// What processing a block needs, shared by the blocks processed at once
+#[derive(Clone)]
+pub struct Processor {
+    chain: String,
+    api: OnlineClient<PolkadotConfig>,
+    db: Arc<db::Database>,
+    low_memory: bool,
+    track_inclusions: bool,
+    justification_rpc: Option<LegacyRpcMethods<PolkadotConfig>>,
+    ss58_prefix: u16,
+    decoders: Arc<Decoders>,
+    alerts: Arc<Alerts>,
+    sinks: Arc<Sinks>,
+    webhook_http: reqwest::Client,
+    // Off for old blocks, which shouldn't fire alerts, webhooks or sinks
+    live: bool,
+}
+
+impl Processor {
+    /// The same processing for blocks fetched through `api` after the fact.
+    pub fn historical(&self, api: OnlineClient<PolkadotConfig>) -> Processor {
+        Processor { api, live: false, ..self.clone() }
+    }
+
+    /// Decodes the block and records what the trackers want from it, None if it's skipped.
+    pub async fn process(
+        self: Arc<Self>,
+        block: Block<PolkadotConfig, OnlineClient<PolkadotConfig>>,
+    ) -> Option<ProcessedBlock> {
+        let Processor {
+            chain,
+            api,
+            db,
+            low_memory,
+            track_inclusions,
+            justification_rpc,
+            ss58_prefix,
+            decoders,
+            alerts,
+            sinks,
+            webhook_http,
+            live,
+        } = (*self).clone();
+
+        let block_number = block.number();
+
+        // Skip blocks we already have, but a different hash under the same number means
+        // the stored one is corrupted (or was never final), so it gets replaced and logged
+        let block_hash = format!("{:?}", block.hash());
+        match db.blocking(move |db| db.get_block_hash(block_number)).await {
+            Ok(Some(stored)) if stored == block_hash => return None,
+            Ok(Some(stored)) => {
+                tracing::warn!(
+                    chain = %chain,
+                    number = block_number,
+                    stored = %stored,
+                    incoming = %block_hash,
+                    "Stored block has a different hash, replacing it"
+                );
+                let (old_hash, new_hash) = (stored.clone(), block_hash.clone());
+                let recorded = db.blocking(move |db| db.record_conflict(block_number, &old_hash, &new_hash)).await;
+                if let Err(e) = recorded {
+                    reporting::block_error(&chain, block_number, &format!("Failed to record the block conflict: {}", e));
+                }
+            }
+            Ok(None) => {}
+            // Index it anyway, storing it is what matters
+            Err(e) => {
+                reporting::block_error(&chain, block_number, &format!("Failed to look up stored block: {}", e));
+            }
+        }
+
+        let extrinsics = match block.extrinsics().await {
+            Ok(extrinsics) => extrinsics,
+            Err(e) => {
+                reporting::block_error(&chain, block_number, &format!("Failed to fetch extrinsics: {}", e));
+                return None;
+            }
+        };
+
+        // Recompute what the header commits to from the data we got, before any filtering,
+        // so blocks that arrived corrupted are flagged instead of served as if they were fine
+        let header = block.header();
+        let raw_extrinsics = extrinsics.iter().map(|e| e.bytes().to_vec()).collect();
+        let verified = header.hash() == block.hash()
+            && extrinsics_root_matches(raw_extrinsics, header.extrinsics_root);
+        if !verified {
+            reporting::block_error(
+                &chain,
+                block_number,
+                "Block failed verification: its hash or extrinsics root doesn't match the data",
+            );
+        }
+
+        let decode = decode::Context { metadata: api.metadata(), ss58_prefix, decoders };
+        let mut total_events = 0;
+
+        let mut extrinsics_info = ExtrinsicSink::new(low_memory);
+        let mut scheduled_calls = HashMap::new();
+
+        for extrinsic_details in extrinsics.iter() {
+            let idx = extrinsic_details.index();
+            let hash = format!("{:?}", extrinsic_details.hash());
+            let meta = extrinsic_details.extrinsic_metadata().ok();
+            let action = meta
+                .as_ref()
+                .map(|m| format!("{}/{}", m.pallet.name(), m.variant.name))
+                .unwrap_or_else(|| "unknown".to_string());
+
+            // Apply extrinsic filtering, which watched accounts and accounts with webhooks are
+            // exempt from. Whether one is mentioned is only known from the events, so those
+            // are checked first.
+            let signer_account = extrinsic_details.address_bytes().and_then(decode::signer_account);
+            let signer_watch = signer_account.and_then(|account| db.watched(&account));
+            let signer_hooked = signer_account.filter(|account| !db.webhooks_for(account).is_empty());
+            let filtered_out =
+                !db.should_include_extrinsic(&action) && signer_watch.is_none() && signer_hooked.is_none();
+            if filtered_out && !db.has_watchlist() && !db.has_webhooks() {
+                continue;
+            }
+            // Watched accounts this extrinsic touched, and how
+            let mut activity: HashMap<[u8; 32], (db::Watch, Vec<String>)> = HashMap::new();
+            if let (Some(account), Some(watch)) = (signer_account, signer_watch.clone()) {
+                activity.insert(account, (watch, vec![format!("signed {}", action)]));
+            }
+            // Accounts with webhooks that signed it or had a transfer in it
+            let mut hooked: Vec<[u8; 32]> = signer_hooked.into_iter().collect();
+
+            // Get extrinsic parameters
+            let field_values = extrinsic_details.field_values();
+            let calls = field_values.as_ref().map(|fields| decode.nested_calls(fields)).unwrap_or_default();
+            let (params, decode_error) = decode_fields(field_values, extrinsic_details.field_bytes(), |fields| match meta {
+                Some(meta) => decode.call_params(meta.pallet.name(), &meta.variant.name, fields),
+                None => decode.fields_to_json(fields),
+            });
+            let signer = extrinsic_details.address_bytes().map(|address| decode.address(address));
+            let scheduled = scheduler::scheduled_calls(&action, &calls);
+            if !scheduled.is_empty() {
+                scheduled_calls.insert(idx, scheduled);
+            }
+
+            let extensions = extrinsic_details.signed_extensions();
+            let nonce = extensions.as_ref().and_then(|e| e.nonce());
+            let tip = extensions.as_ref().and_then(|e| e.tip()).map(|tip| tip.to_string());
+            let era = extensions
+                .as_ref()
+                .and_then(|e| e.find::<CheckMortality<PolkadotConfig>>().ok().flatten())
+                .map(Mortality::from);
+
+            // Get events for this extrinsic
+            let events = match extrinsic_details.events().await {
+                Ok(events) => events,
+                Err(e) => {
+                    reporting::block_error(&chain, block_number, &format!("Failed to fetch events: {}", e));
+                    return None;
+                }
+            };
+            let mut events_info: Vec<EventInfo> = Vec::new();
+            let mut dispatch_info = None;
+            let mut error = None;
+
+            for evt in events.iter().flatten() {
+                let pallet = evt.pallet_name();
+                let variant = evt.variant_name();
+
+                // Even when the event itself is filtered out
+                if pallet == "System" && matches!(variant, "ExtrinsicSuccess" | "ExtrinsicFailed") {
+                    dispatch_info = evt.field_values().ok().as_ref().and_then(DispatchInfo::from_event);
+                    if variant == "ExtrinsicFailed" {
+                        error = Some(dispatch_error_name(evt.field_bytes(), decode.metadata.clone()));
+                    }
+                }
+
+                // Events of or about watched accounts (and their webhooks' transfers) are
+                // always stored
+                let mut touches_watched = signer_watch.is_some() || signer_hooked.is_some();
+                if db.has_watchlist() || db.has_webhooks() {
+                    let accounts = evt.field_values().map(|fields| decode.accounts(&fields)).unwrap_or_default();
+                    for account in accounts {
+                        if webhooks::is_transfer(variant) && !db.webhooks_for(&account).is_empty() {
+                            touches_watched = true;
+                            if !hooked.contains(&account) {
+                                hooked.push(account);
+                            }
+                        }
+                        let Some(watch) = db.watched(&account) else {
+                            continue;
+                        };
+                        touches_watched = true;
+                        let (_, what) = activity.entry(account).or_insert_with(|| (watch, vec![]));
+                        what.push(format!("{}::{}", pallet, variant));
+                    }
+                }
+
+                // Alerts see events regardless of what's filtered out of storage
+                let include = db.should_include_event(pallet, variant) || touches_watched;
+                let watched = live && alerts.is_watching(&chain, pallet, variant);
+                if !include && !watched {
+                    continue;
+                }
+
+                let ((typed, data), decode_error) = decode_fields(evt.field_values(), evt.field_bytes(), |fields| {
+                    (typed::decode(&decode, pallet, variant, &fields), decode.fields_to_string(fields))
+                });
+                let event_info = EventInfo {
+                    pallet: pallet.to_string(),
+                    variant: variant.to_string(),
+                    data,
+                    typed,
+                    decode_error,
+                };
+
+                if watched {
+                    alerts.on_event(&EventContext {
+                        chain: &chain,
+                        block_number,
+                        extrinsic_index: idx,
+                        event: &event_info,
+                    });
+                }
+                if include {
+                    events_info.push(event_info);
+                }
+            }
+
+            for (watch, what) in activity.values().filter(|_| live) {
+                alerts.on_watched(&chain, block_number, idx, watch, what);
+            }
+            if filtered_out && activity.is_empty() && hooked.is_empty() {
+                continue;
+            }
+
+            total_events += events_info.len();
+
+            let extrinsic = ExtrinsicInfo {
+                index: idx,
+                hash,
+                action,
+                params,
+                calls,
+                events: events_info,
+                signer,
+                nonce,
+                tip,
+                era,
+                dispatch_info,
+                error,
+                decode_error,
+                raw: format!("0x{}", hex::encode(extrinsic_details.bytes())),
+            };
+            if live {
+                for account in &hooked {
+                    let signed = signer_account.as_ref() == Some(account);
+                    webhooks::notify(&webhook_http, &chain, block_number, db.webhooks_for(account), signed, &extrinsic);
+                }
+                sinks.on_extrinsic(&chain, block_number, &extrinsic);
+            }
+            extrinsics_info.push(extrinsic);
+        }
+
+        // Scheduled tasks mostly run before any extrinsic, so these need all of the block's events
+        let mut updates = TrackerUpdates::default();
+        match block.events().await {
+            Ok(events) => {
+                let updates = &mut updates;
+                scheduler::record(updates, block_number, &events, scheduled_calls);
+                referenda::record(updates, &chain, &api, &decode, block_number, block.hash(), &events).await;
+                assets::record(&db, updates, &chain, &api, &decode, block_number, block.hash(), &events).await;
+                nfts::record(updates, &decode, block_number, &events);
+                vesting::record(updates, &chain, &api, block_number, block.hash(), &events).await;
+                locks::record(updates, block_number, &events);
+                xcm::record(updates, block_number, &events);
+                election::record(updates, &decode, block_number, &events);
+                era_points::record(updates, &chain, &api, block_number, block.hash(), &events).await;
+                let offline =
+                    validators::record(updates, &chain, &api, &decode, block_number, block.hash(), &events).await;
+                if live {
+                    alerts.on_offline(&chain, block_number, &offline);
+                }
+                let reported =
+                    offences::record(updates, &chain, &api, &decode, block_number, block.hash(), &events).await;
+                for offence in reported.into_iter().filter(|_| live) {
+                    alerts.on_offence(&chain, block_number, &offence.kind, &offence.offenders);
+                }
+                if track_inclusions {
+                    parachains::record(updates, block_number, &events);
+                }
+            }
+            Err(e) => reporting::block_error(&chain, block_number, &format!("Failed to fetch events: {}", e)),
+        }
+        babe::record(&mut updates, &chain, &api, block_number, block.hash(), &header.digest.logs).await;
+        if let Some(rpc) = &justification_rpc {
+            justifications::record(&mut updates, &chain, rpc, block_number, block.hash()).await;
+        }
+
+        let header = db::StoredHeader {
+            parent_hash: format!("{:?}", header.parent_hash),
+            state_root: format!("{:?}", header.state_root),
+            extrinsics_root: format!("{:?}", header.extrinsics_root),
+            digest: header
+                .digest
+                .logs
+                .iter()
+                .map(|item| format!("0x{}", hex::encode(item.encode())))
+                .collect(),
+        };
+        Some(ProcessedBlock {
+            number: block_number,
+            hash: block_hash,
+            extrinsics: extrinsics_info,
+            header,
+            verified,
+            events_count: total_events,
+            updates,
+        })
+    }
+}
+
+type FinalizedBlocks = StreamOfResults<Block<PolkadotConfig, OnlineClient<PolkadotConfig>>>;
+
+// Hands finalized blocks to processing as they come, warning when the queue fills up
+async fn follow(
+    chain: String,
+    mut blocks_sub: FinalizedBlocks,
+    blocks: mpsc::Sender<Block<PolkadotConfig, OnlineClient<PolkadotConfig>>>,
+) {
+    while let Some(block) = blocks_sub.next().await {
+        let block = match block {
+            Ok(block) => block,
+            Err(e) => {
+                reporting::error(&chain, &format!("Finalized block subscription error: {}", e));
+                continue;
+            }
+        };
+        let block = match blocks.try_send(block) {
+            Ok(()) => continue,
+            Err(TrySendError::Full(block)) => block,
+            Err(TrySendError::Closed(_)) => return,
+        };
+        tracing::warn!(
+            chain = %chain,
+            number = block.number(),
+            queued = BLOCK_QUEUE,
+            "Block processing is falling behind the chain, waiting for it to catch up"
+        );
+        if blocks.send(block).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// A block decoded and ready to be written.
+pub struct ProcessedBlock {
+    pub number: u32,
+    hash: String,
+    extrinsics: ExtrinsicSink,
+    header: db::StoredHeader,
+    verified: bool,
+    events_count: usize,
+    updates: TrackerUpdates,
+}
+
+impl ProcessedBlock {
+    /// Writes the block and what the trackers recorded from it, returning the extrinsics to keep
+    /// for `/blocks/head`. Tracker writes that fail are reported on their own.
+    pub fn write(
+        self,
+        db: &db::Database,
+        chain: &str,
+        timestamp: i64,
+    ) -> (Result<(), rusqlite::Error>, Vec<ExtrinsicInfo>) {
+        self.updates.apply(db, chain, self.number);
+        match self.extrinsics {
+            ExtrinsicSink::Collect(extrinsics) => {
+                let stored_block = db::StoredBlock {
+                    number: self.number,
+                    hash: self.hash,
+                    extrinsics: extrinsics.iter().map(|e| serde_json::to_value(e).unwrap()).collect(),
+                    timestamp,
+                    header: Some(self.header),
+                    verified: Some(self.verified),
+                };
+                (db.store_block(&stored_block), extrinsics)
+            }
+            ExtrinsicSink::Stream { mut json, .. } => {
+                json.push(']');
+                // Only a summary of the head is kept around in low-memory mode
+                let stored = db.store_block_json(
+                    self.number,
+                    &self.hash,
+                    &json,
+                    timestamp,
+                    Some(&self.header),
+                    Some(self.verified),
+                );
+                (stored, vec![])
+            }
+        }
+    }
+}
+
+async fn store(
+    chain: String,
+    db: Arc<db::Database>,
+    block_info: SharedBlockInfo,
+    sinks: Arc<Sinks>,
+    mut blocks: mpsc::Receiver<ProcessedBlock>,
+) {
+    while let Some(block) = blocks.recv().await {
+        let timestamp = chrono::Utc::now().timestamp();
+        let extrinsics_count = block.extrinsics.len();
+        let (number, hash, events_count) = (block.number, block.hash.clone(), block.events_count);
+
+        // Store in database, then move the cursor past it. A crash in between only means the
+        // block is fetched again on the next start, and skipped as already stored
+        let (cursor_hash, write_chain) = (hash.clone(), chain.clone());
+        let (stored, head_extrinsics) = db
+            .blocking(move |db| {
+                let (stored, head_extrinsics) = block.write(db, &write_chain, timestamp);
+                let stored = stored.and_then(|()| db.set_cursor(db::LAST_PROCESSED, number, &cursor_hash));
+                (stored, head_extrinsics)
+            })
+            .await;
+
+        match stored {
+            Ok(()) => sinks.on_block(&BlockSummary {
+                chain: chain.clone(),
+                number,
+                hash: hash.clone(),
+                extrinsics_count,
+                events_count,
+                timestamp,
+            }),
+            Err(e) => {
+                reporting::block_error(&chain, number, &format!("Failed to store block: {}", e));
+            }
+        }
+
+        tracing::info!(chain = %chain, number, extrinsics = extrinsics_count, events = events_count, "Block stored");
+
+        // Update in-memory state
+        block_info.store(Arc::new(BlockInfo {
+            number,
+            hash,
+            extrinsics_count,
+            events_count,
+            extrinsics: head_extrinsics,
+        }));
+    }
+}
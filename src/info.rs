@@ -0,0 +1,80 @@
+//! `/info`: what a client is talking to, in one call. The chain and its runtime, what's stored
+//! and how it's filtered, and which smolcar has been serving it for how long.
+
+use axum::{extract::State, response::Json};
+use serde::Serialize;
+use std::sync::LazyLock;
+use std::time::Instant;
+use subxt::backend::legacy::LegacyRpcMethods;
+use subxt::PolkadotConfig;
+
+use crate::api::AppState;
+use crate::config::Filters;
+use crate::error::Error;
+
+/// When smolcar started, forced first thing so uptime doesn't begin with the first `/info`.
+pub static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+#[derive(Serialize)]
+pub struct Runtime {
+    /// None when the light client couldn't say, the versions are from the last runtime subxt saw
+    spec_name: Option<String>,
+    spec_version: u32,
+    transaction_version: u32,
+}
+
+#[derive(Serialize)]
+pub struct IndexedRange {
+    first: u32,
+    last: u32,
+    stored_blocks: u64,
+}
+
+#[derive(Serialize)]
+pub struct Info {
+    chain: String,
+    genesis_hash: String,
+    runtime: Runtime,
+    /// None while nothing's stored
+    indexed: Option<IndexedRange>,
+    filters: Filters,
+    version: &'static str,
+    uptime_secs: u64,
+}
+
+pub async fn get_info(State(state): State<AppState>) -> Result<Json<Info>, Error> {
+    let version = state.api.runtime_version();
+    let rpc = LegacyRpcMethods::<PolkadotConfig>::new(state.rpc.clone());
+    let spec_name = match rpc.state_get_runtime_version(None).await {
+        Ok(runtime) => runtime.other.get("specName").and_then(|name| name.as_str()).map(str::to_string),
+        Err(e) => {
+            tracing::debug!(error = %e, "Couldn't get the runtime's spec name");
+            None
+        }
+    };
+
+    let indexed = state
+        .db
+        .blocking(|db| -> Result<_, rusqlite::Error> {
+            let Some((first, last)) = db.get_block_range()? else {
+                return Ok(None);
+            };
+            let stored_blocks = db.counters()?.get("blocks").copied().unwrap_or_default();
+            Ok(Some(IndexedRange { first, last, stored_blocks }))
+        })
+        .await?;
+
+    Ok(Json(Info {
+        chain: state.chain.clone(),
+        genesis_hash: format!("{:?}", state.api.genesis_hash()),
+        runtime: Runtime {
+            spec_name,
+            spec_version: version.spec_version,
+            transaction_version: version.transaction_version,
+        },
+        indexed,
+        filters: state.db.filters(),
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: STARTED.elapsed().as_secs(),
+    }))
+}
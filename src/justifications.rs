@@ -0,0 +1,66 @@
+//! GRANDPA justifications, so finality can be checked without trusting smolcar. Off unless
+//! `store_justifications` is set, since it fetches every block a second time through the
+//! legacy `chain_getBlock` RPC.
+//!
+//! GRANDPA only justifies some blocks (authority set changes and every few hundred blocks),
+//! and the light client may not have one to hand, so most blocks don't get one.
+
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use serde_json::json;
+use subxt::backend::legacy::LegacyRpcMethods;
+use subxt::utils::H256;
+use subxt::PolkadotConfig;
+
+use crate::api::AppState;
+use crate::error::Error;
+use crate::indexer::TrackerUpdates;
+use crate::reporting;
+
+const GRANDPA_ENGINE_ID: [u8; 4] = *b"FRNK";
+
+/// Stores the block's GRANDPA justification, if the node has one.
+pub async fn record(
+    updates: &mut TrackerUpdates,
+    chain: &str,
+    rpc: &LegacyRpcMethods<PolkadotConfig>,
+    block_number: u32,
+    block_hash: H256,
+) {
+    let justifications = match rpc.chain_get_block(Some(block_hash)).await {
+        Ok(block) => block.and_then(|block| block.justifications).unwrap_or_default(),
+        Err(e) => {
+            reporting::block_error(chain, block_number, &format!("Failed to fetch justifications: {}", e));
+            return;
+        }
+    };
+
+    for (engine, justification) in justifications {
+        if engine != GRANDPA_ENGINE_ID {
+            continue;
+        }
+        let hash = format!("{:?}", block_hash);
+        updates.push("Failed to store justification", move |db| {
+            db.store_justification(block_number, &hash, &justification)
+        });
+    }
+}
+
+/// The block's SCALE-encoded GRANDPA justification, as hex.
+pub async fn get_justification(
+    State(state): State<AppState>,
+    Path(number): Path<u32>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let (hash, justification) = state
+        .db
+        .blocking(move |db| db.get_justification(number))
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("No justification stored for block #{}", number)))?;
+    Ok(Json(json!({
+        "number": number,
+        "hash": hash,
+        "justification": format!("0x{}", hex::encode(justification)),
+    })))
+}
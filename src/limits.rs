@@ -0,0 +1,40 @@
+//! `--request-timeout` and `--max-requests`: at most that many requests are handled at once,
+//! across every chain, and a request that isn't answered in time (waiting for its turn
+//! included) gets a 503. Keeps one client asking for huge ranges from starving everyone else
+//! on a single-core board. Streams only count until their first bytes are sent.
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::error::Error;
+
+#[derive(Clone)]
+pub struct Limits {
+    timeout: Duration,
+    permits: Arc<Semaphore>,
+}
+
+impl Limits {
+    pub fn new(timeout: Duration, max_requests: usize) -> Self {
+        Limits { timeout, permits: Arc::new(Semaphore::new(max_requests.max(1))) }
+    }
+}
+
+pub async fn enforce(State(limits): State<Limits>, request: Request, next: Next) -> Response {
+    let handled = async {
+        // Never closed, so acquiring only ever waits
+        let _permit = limits.permits.acquire().await;
+        next.run(request).await
+    };
+    match tokio::time::timeout(limits.timeout, handled).await {
+        Ok(response) => response,
+        // A query already running on the blocking pool still finishes, its result is dropped
+        Err(_) => Error::Timeout(format!("No response within {}s", limits.timeout.as_secs())).into_response(),
+    }
+}
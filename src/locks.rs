@@ -0,0 +1,193 @@
+//! Balance locks, reserves and freezes. The Balances pallet's events for them are kept in the
+//! `balance_changes` table, and `/account/:address/locks` breaks an account's balance down by
+//! what's holding it, read live through the light client, along with those changes.
+//!
+//! The events don't say which pallet asked for them, so each one is put down to the next event
+//! of the same extrinsic from another pallet (`Proxy/ProxyAdded` after a reserve, and so on).
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use subxt::dynamic;
+use subxt::events::{Events, Phase};
+use subxt::ext::scale_value::{self, At, ValueDef};
+use subxt::utils::AccountId32;
+use subxt::PolkadotConfig;
+
+use crate::api::AppState;
+use crate::error::Error;
+use crate::decode;
+use crate::indexer::TrackerUpdates;
+
+const MAX_CHANGES: u32 = 500;
+
+// Pallets whose events never explain a balance change
+const BOOKKEEPING_PALLETS: [&str; 3] = ["Balances", "System", "TransactionPayment"];
+
+/// Records the block's balance lock, reserve and freeze events.
+pub fn record(updates: &mut TrackerUpdates, block_number: u32, events: &Events<PolkadotConfig>) {
+    let events: Vec<_> = events.iter().flatten().collect();
+    for (i, event) in events.iter().enumerate() {
+        if event.pallet_name() != "Balances" {
+            continue;
+        }
+        let kind = match event.variant_name() {
+            "Reserved" => "reserve",
+            "Unreserved" => "unreserve",
+            "Locked" => "lock",
+            "Unlocked" => "unlock",
+            "Frozen" => "freeze",
+            "Thawed" => "thaw",
+            _ => continue,
+        };
+        let Ok(fields) = event.field_values() else {
+            continue;
+        };
+        let (Some(account), Some(amount)) =
+            (fields.at("who").and_then(decode::bytes), fields.at("amount").and_then(|v| v.as_u128()))
+        else {
+            continue;
+        };
+
+        let phase = event.phase();
+        let source = events[i + 1..]
+            .iter()
+            .take_while(|next| next.phase() == phase)
+            .find(|next| !BOOKKEEPING_PALLETS.contains(&next.pallet_name()))
+            .map(|next| next.pallet_name().to_string());
+        let extrinsic_index = match phase {
+            Phase::ApplyExtrinsic(i) => Some(i),
+            _ => None,
+        };
+
+        let (event_index, account) = (event.index(), format!("0x{}", hex::encode(&account)));
+        updates.push("Failed to record balance change", move |db| {
+            db.record_balance_change(
+                block_number,
+                event_index,
+                extrinsic_index,
+                &account,
+                kind,
+                source.as_deref(),
+                amount,
+            )
+        });
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LocksQuery {
+    limit: Option<u32>,
+}
+
+fn amount(value: Option<&scale_value::Value<u32>>) -> Option<String> {
+    value?.as_u128().map(|n| n.to_string())
+}
+
+// Lock and reserve ids are 8 bytes of padded text, e.g. "staking " or "pyconvot"
+fn id(value: Option<&scale_value::Value<u32>>) -> Option<String> {
+    let bytes = decode::bytes(value?)?;
+    Some(String::from_utf8_lossy(&bytes).trim_end().to_string())
+}
+
+// Hold and freeze reasons are the runtime's enums, e.g. `Preimage(Preimage)` -> "Preimage/Preimage"
+fn reason(value: Option<&scale_value::Value<u32>>) -> Option<String> {
+    let ValueDef::Variant(pallet) = &value?.value else {
+        return None;
+    };
+    match pallet.values.values().next().map(|v| &v.value) {
+        Some(ValueDef::Variant(reason)) => Some(format!("{}/{}", pallet.name, reason.name)),
+        _ => Some(pallet.name.clone()),
+    }
+}
+
+// Reserves, holds and freezes are all lists of `IdAmount { id, amount }`
+fn entries(
+    value: Option<&scale_value::Value<u32>>,
+    key: &str,
+    name: fn(Option<&scale_value::Value<u32>>) -> Option<String>,
+) -> Vec<Value> {
+    match value.map(|v| &v.value) {
+        Some(ValueDef::Composite(entries)) => entries
+            .values()
+            .map(|entry| json!({ key: name(entry.at("id")), "amount": amount(entry.at("amount")) }))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+async fn breakdown(state: &AppState, account: &AccountId32) -> Result<Value, subxt::Error> {
+    let block = state.api.blocks().at_latest().await?;
+    let storage = block.storage();
+    let key = || vec![dynamic::Value::from_bytes(account.0)];
+    let fetch = |pallet, entry| {
+        let storage = storage.clone();
+        let address = dynamic::storage(pallet, entry, key());
+        async move {
+            match storage.fetch(&address).await? {
+                Some(value) => Ok::<_, subxt::Error>(Some(value.to_value()?)),
+                None => Ok(None),
+            }
+        }
+    };
+
+    let info = fetch("System", "Account").await?;
+    let locks = fetch("Balances", "Locks").await?;
+    let reserves = fetch("Balances", "Reserves").await?;
+    let holds = fetch("Balances", "Holds").await?;
+    let freezes = fetch("Balances", "Freezes").await?;
+
+    let data = info.as_ref().and_then(|i| i.at("data"));
+    let locks: Vec<Value> = match locks.as_ref().map(|l| &l.value) {
+        Some(ValueDef::Composite(locks)) => locks
+            .values()
+            .map(|lock| {
+                json!({
+                    "id": id(lock.at("id")),
+                    "amount": amount(lock.at("amount")),
+                    "reasons": match lock.at("reasons").map(|r| &r.value) {
+                        Some(ValueDef::Variant(reasons)) => json!(reasons.name),
+                        _ => Value::Null,
+                    },
+                })
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    Ok(json!({
+        "at": { "hash": format!("{:?}", block.hash()), "height": block.number() },
+        "free": amount(data.and_then(|d| d.at("free"))).unwrap_or_else(|| "0".into()),
+        "reserved": amount(data.and_then(|d| d.at("reserved"))).unwrap_or_else(|| "0".into()),
+        // Older runtimes only have `misc_frozen`
+        "frozen": amount(data.and_then(|d| d.at("frozen").or_else(|| d.at("misc_frozen")))).unwrap_or_else(|| "0".into()),
+        "locks": locks,
+        "reserves": entries(reserves.as_ref(), "id", id),
+        "holds": entries(holds.as_ref(), "reason", reason),
+        "freezes": entries(freezes.as_ref(), "reason", reason),
+    }))
+}
+
+/// What's holding an account's balance right now, and its lock and reserve events we stored.
+pub async fn get_locks(
+    State(state): State<AppState>,
+    Path(address): Path<String>,
+    Query(query): Query<LocksQuery>,
+) -> Result<Json<Value>, Error> {
+    let account: AccountId32 =
+        address.parse().map_err(|_| Error::BadRequest(format!("Invalid address {:?}", address)))?;
+
+    let limit = query.limit.unwrap_or(50).min(MAX_CHANGES);
+    let key = format!("0x{}", hex::encode(account.0));
+    let changes = state.db.blocking(move |db| db.get_balance_changes(&key, limit)).await?;
+
+    let mut breakdown = breakdown(&state, &account)
+        .await
+        .map_err(|e| Error::Upstream(format!("Failed to query chain state: {}", e)))?;
+    breakdown["address"] = json!(address);
+    breakdown["changes"] = json!(changes);
+    Ok(Json(breakdown))
+}
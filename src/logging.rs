@@ -0,0 +1,138 @@
+use clap::ValueEnum;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::ByteSize;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Rotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl Rotation {
+    // Identifies the current period, a change means it's time to rotate
+    fn period(&self) -> String {
+        let now = chrono::Utc::now();
+        match self {
+            Rotation::Never => String::new(),
+            Rotation::Hourly => now.format("%Y-%m-%d-%H").to_string(),
+            Rotation::Daily => now.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FileOptions {
+    pub path: PathBuf,
+    pub rotation: Rotation,
+    pub max_size: Option<ByteSize>,
+    /// How many rotated files (`smolcar.log.1`, `.2`, ...) to keep around
+    pub keep: usize,
+}
+
+/// Sets up tracing. Logs go to stdout, or to a rotating file when `file` is given.
+/// The level defaults to info and can be changed with RUST_LOG.
+pub fn init(format: LogFormat, file: Option<FileOptions>) -> io::Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match (format, file) {
+        (LogFormat::Text, None) => builder.init(),
+        (LogFormat::Json, None) => builder.json().init(),
+        (LogFormat::Text, Some(options)) => builder
+            .with_ansi(false)
+            .with_writer(Mutex::new(RotatingFile::open(options)?))
+            .init(),
+        (LogFormat::Json, Some(options)) => builder
+            .json()
+            .with_writer(Mutex::new(RotatingFile::open(options)?))
+            .init(),
+    }
+
+    Ok(())
+}
+
+/// A log file that moves itself to `<path>.1` (shifting older ones up) once it gets
+/// too big or the hour/day changes.
+struct RotatingFile {
+    options: FileOptions,
+    file: File,
+    size: u64,
+    period: String,
+}
+
+impl RotatingFile {
+    fn open(options: FileOptions) -> io::Result<Self> {
+        if let Some(dir) = options.path.parent() {
+            if !dir.as_os_str().is_empty() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&options.path)?;
+        let size = file.metadata()?.len();
+        let period = options.rotation.period();
+
+        Ok(RotatingFile { options, file, size, period })
+    }
+
+    fn rotated_path(&self, n: usize) -> PathBuf {
+        let mut path = self.options.path.clone().into_os_string();
+        path.push(format!(".{}", n));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.options.keep == 0 {
+            std::fs::remove_file(&self.options.path)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated_path(self.options.keep));
+            for n in (1..self.options.keep).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    std::fs::rename(from, self.rotated_path(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.options.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.options.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let period = self.options.rotation.period();
+        let too_big = self
+            .options
+            .max_size
+            .is_some_and(|max| self.size > 0 && self.size + buf.len() as u64 > max.0);
+
+        if period != self.period || too_big {
+            self.rotate()?;
+            self.period = period;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
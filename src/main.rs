@@ -1,23 +1,85 @@
 #![allow(missing_docs)]
-use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::{IntoResponse, Json},
-    routing::get,
-    Router,
-};
-use serde::Serialize;
+use arc_swap::ArcSwap;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 use std::sync::Arc;
+use subxt::backend::chain_head::ChainHeadBackend;
+use subxt::backend::legacy::{LegacyBackend, LegacyRpcMethods};
+use subxt::backend::rpc::RpcClient;
 use subxt::{client::OnlineClient, lightclient::LightClient, PolkadotConfig};
-use tokio::sync::RwLock;
-
+use tokio::sync::watch;
+use tracing::info;
+
+mod admin;
+mod alerts;
+mod api;
+mod archive;
+mod assets;
+mod babe;
+mod backfill;
+mod chain;
+mod check;
+#[cfg(feature = "clickhouse")]
+mod clickhouse;
+mod config;
+mod daemon;
 mod db;
+mod decode;
+mod decoders;
+mod election;
+mod encoding;
+mod envelope;
+mod era_points;
+mod error;
+mod export;
+mod filter_report;
+mod feed;
+#[cfg(feature = "graphql")]
+mod graphql;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod indexer;
+mod info;
+mod justifications;
+mod limits;
+mod locks;
+mod metrics;
+mod logging;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod nfts;
+mod offences;
+mod parachains;
+mod presets;
+mod proof;
+#[cfg(feature = "redis")]
+mod redis_sink;
+mod referenda;
+mod registry;
+mod reporting;
+mod retention;
+mod rpc;
+mod rules;
+mod scheduler;
+mod server;
+mod sidecar;
+mod sinks;
+mod standby;
+mod stream;
+mod subscriptions;
+mod sync;
+mod systemd;
+mod tui;
+mod typed;
+mod validators;
+mod vesting;
+mod watchlist;
+mod webhooks;
+mod xcm;
 
 #[subxt::subxt(runtime_metadata_path = "configs/polkadot_metadata_small.scale")]
 pub mod polkadot {}
 
-const POLKADOT_SPEC: &str = include_str!("../configs/polkadot.json");
-
 // Configuration: Events to exclude (add pallets/methods here to save space)
 const EXCLUDED_EVENTS: &[(&str, Option<&str>)] = &[
     // Example filters (uncomment to use):
@@ -33,227 +95,676 @@ const EXCLUDED_EXTRINSICS: &[&str] = &[
     "ParaInherent/enter",      // Exclude para inherent extrinsics
 ];
 
-#[derive(Clone, Serialize)]
-struct EventInfo {
-    pallet: String,
-    variant: String,
-    data: String,
+#[derive(Parser)]
+#[command(name = "smolcar", about = "Lightweight Substrate indexer on top of smoldot")]
+struct Cli {
+    /// Directory holding the chain registry and one database file per chain
+    #[arg(long, default_value = ".", global = true)]
+    data_dir: PathBuf,
+
+    /// Chain to index, either a bundled one ("polkadot") or name=path/to/spec.json.
+    /// Repeat for multiple chains, parachains need their relay chain listed too.
+    /// Polkadot if neither this nor --chain-spec-url is given.
+    #[arg(long = "chain")]
+    chains: Vec<chain::ChainArg>,
+
+    /// Chain to index with its spec downloaded at startup, as name=https://host/spec.json,
+    /// optionally pinned with #sha256=<hex>. Cached in <data-dir>/specs. Repeatable.
+    #[arg(long = "chain-spec-url")]
+    chain_spec_urls: Vec<chain::ChainSpecUrl>,
+
+    /// Where chains with nothing stored yet start: "head" follows new blocks only, a block
+    /// number or "genesis" also fetches everything from there up to the head
+    #[arg(long, default_value = "head")]
+    from_block: backfill::FromBlock,
+
+    /// Index every chain from genesis to the head, next to following new blocks. Progress is
+    /// checkpointed, so it picks up where it stopped after a restart
+    #[arg(long)]
+    full_archive: bool,
+
+    /// Address to serve the API on, e.g. 0.0.0.0:8080, [::]:8080, just a port,
+    /// or a Unix socket as unix:/run/smolcar.sock.
+    /// Repeat (or comma-separate) to listen on several addresses.
+    #[arg(long, env = "SMOLCAR_LISTEN", value_delimiter = ',', default_value = "0.0.0.0:8080")]
+    listen: Vec<server::ListenAddr>,
+
+    /// PEM certificate chain to serve HTTPS with (needs --tls-key)
+    #[arg(long, env = "SMOLCAR_TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, env = "SMOLCAR_TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// Also serve the gRPC API on this address, e.g. 0.0.0.0:50051 (needs the `grpc` feature)
+    #[arg(long, env = "SMOLCAR_GRPC_LISTEN")]
+    grpc_listen: Option<std::net::SocketAddr>,
+
+    /// Seconds a request may take, waiting for its turn included, before it's answered with a 503
+    #[arg(long, env = "SMOLCAR_REQUEST_TIMEOUT", default_value_t = 30)]
+    request_timeout: u64,
+
+    /// Requests handled at once across all chains, the rest wait for their turn
+    #[arg(long, env = "SMOLCAR_MAX_REQUESTS", default_value_t = 32)]
+    max_requests: usize,
+
+    /// Only serve the API, over databases another smolcar indexes into. They're opened
+    /// read-only and every route that changes anything is off.
+    #[arg(long, env = "SMOLCAR_READ_ONLY")]
+    read_only: bool,
+
+    /// Detach from the terminal and run in the background. Logs then need --log-file
+    #[arg(long, requires = "log_file")]
+    daemon: bool,
+
+    /// Where --daemon writes the PID (defaults to smolcar.pid in the data directory)
+    #[arg(long, requires = "daemon")]
+    pid_file: Option<PathBuf>,
+
+    /// Path to a smolcar.toml config file
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Store only what a named filter set keeps, instead of the config's [filters]
+    #[arg(long, value_enum, global = true)]
+    preset: Option<presets::Preset>,
+
+    /// Log output format
+    #[arg(long, value_enum, default_value = "text", global = true)]
+    log_format: logging::LogFormat,
+
+    /// Write logs to this file instead of stdout
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Start a new log file every hour or day
+    #[arg(long, value_enum, default_value = "daily", global = true)]
+    log_rotate: logging::Rotation,
+
+    /// Start a new log file once the current one reaches this size
+    #[arg(long, default_value = "100MB", global = true)]
+    log_max_size: config::ByteSize,
+
+    /// Number of rotated log files to keep
+    #[arg(long, default_value_t = 7, global = true)]
+    log_keep: usize,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Clone, Serialize)]
-struct ExtrinsicInfo {
-    index: u32,
-    hash: String,
-    action: String,
-    params: String,
-    events: Vec<EventInfo>,
+#[derive(Subcommand)]
+enum Command {
+    /// Check the database for corruption, bad hashes and missing blocks
+    Check {
+        /// Only check this chain (defaults to every registered chain)
+        chain: Option<String>,
+    },
+    /// Export stored blocks to an analytics database
+    Export {
+        /// Only export this chain (defaults to every registered chain)
+        chain: Option<String>,
+        #[arg(long, value_enum, default_value = "duckdb")]
+        format: export::Format,
+        /// File to write, existing rows of the exported chains are replaced
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+    /// Show the filters in effect, or with --dry-run what they'd drop from the latest stored blocks
+    Filters {
+        /// Only look at this chain (defaults to every registered chain)
+        chain: Option<String>,
+        #[arg(long)]
+        dry_run: bool,
+        /// How many of the latest stored blocks to look at
+        #[arg(long, default_value_t = 100)]
+        blocks: u32,
+    },
+    /// Delete every stored block below a block number, and what was recorded from them
+    Prune {
+        /// Only prune this chain (defaults to every registered chain)
+        chain: Option<String>,
+        /// First block to keep
+        #[arg(long)]
+        before: u32,
+    },
+    /// Watch a running smolcar from the terminal
+    Tui {
+        /// Base URL of its API
+        #[arg(long, default_value = "http://localhost:8080")]
+        url: String,
+        /// Chain to watch (defaults to the first one it indexes)
+        chain: Option<String>,
+    },
 }
 
-#[derive(Clone, Serialize)]
-struct BlockInfo {
-    number: u32,
-    hash: String,
-    extrinsics_count: usize,
-    events_count: usize,
-    extrinsics: Vec<ExtrinsicInfo>,
+fn main() -> Result<(), error::Error> {
+    let cli = Cli::parse();
+    if cli.daemon {
+        daemon::detach(&cli.data_dir, cli.pid_file.as_deref())?;
+    }
+    tokio::runtime::Builder::new_multi_thread().enable_all().build()?.block_on(run(cli))
 }
 
-type SharedBlockInfo = Arc<RwLock<BlockInfo>>;
+async fn run(cli: Cli) -> Result<(), error::Error> {
+    std::sync::LazyLock::force(&info::STARTED);
+    logging::init(
+        cli.log_format,
+        cli.log_file.clone().map(|path| logging::FileOptions {
+            path,
+            rotation: cli.log_rotate,
+            max_size: Some(cli.log_max_size),
+            keep: cli.log_keep,
+        }),
+    )?;
+    let config = match &cli.config {
+        Some(path) => config::Config::load(path)?,
+        None => config::Config::default(),
+    };
 
-#[derive(Clone)]
-struct AppState {
-    block_info: SharedBlockInfo,
-    db: Arc<db::Database>,
-}
+    let sentry_dsn = config.sentry_dsn.clone().or_else(|| std::env::var("SENTRY_DSN").ok());
+    let admin_token = config.admin_token.clone().or_else(|| std::env::var("SMOLCAR_ADMIN_TOKEN").ok());
+    let _reporting = reporting::init(sentry_dsn.as_deref());
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt::init();
+    if let Some(Command::Tui { url, chain }) = &cli.command {
+        tui::run(url, chain.as_deref()).await?;
+        return Ok(());
+    }
 
-    println!("Connecting to Polkadot via light client...\n");
+    // Two smolcars writing to the same databases would corrupt each other's writes
+    let _lock = match &cli.command {
+        None if !cli.read_only => Some(daemon::lock_data_dir(&cli.data_dir)?),
+        Some(Command::Prune { .. }) => Some(daemon::lock_data_dir(&cli.data_dir)?),
+        _ => None,
+    };
+    let registry = if cli.read_only && cli.command.is_none() {
+        registry::Registry::open_read_only(&cli.data_dir)?
+    } else {
+        std::fs::create_dir_all(&cli.data_dir)?;
+        registry::Registry::open(&cli.data_dir)?
+    };
 
-    // Initialize database
-    let event_filters: Vec<db::EventFilter> = EXCLUDED_EVENTS
-        .iter()
-        .map(|(pallet, method)| db::EventFilter {
-            pallet: pallet.to_string(),
-            method: method.map(|s| s.to_string()),
-        })
-        .collect();
-
-    let extrinsic_filters: Vec<String> = EXCLUDED_EXTRINSICS
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
-
-    let database = Arc::new(db::Database::new("./blocks.db", event_filters, extrinsic_filters)?);
-    println!("Database initialized at ./blocks.db");
-
-    if let Ok(Some(latest)) = database.get_latest_block_number() {
-        println!("Latest block in database: #{}\n", latest);
+    if let Some(Command::Check { chain }) = cli.command {
+        let mut all_ok = true;
+        for registered in registry.chains()? {
+            if chain.as_ref().is_some_and(|c| *c != registered.name) {
+                continue;
+            }
+            println!("=== {} ({}) ===\n", registered.name, registered.db_path.display());
+            let database = db::Database::new(&registered.db_path, &config.sqlite)?;
+            all_ok &= check::run(&database)?;
+            println!();
+        }
+        if !all_ok {
+            std::process::exit(1);
+        }
+        return Ok(());
     }
 
-    let (_lightclient, polkadot_rpc) = LightClient::relay_chain(POLKADOT_SPEC)?;
-    let polkadot_api = OnlineClient::<PolkadotConfig>::from_rpc_client(polkadot_rpc).await?;
-
-    let block_info = Arc::new(RwLock::new(BlockInfo {
-        number: 0,
-        hash: String::from("0x0"),
-        extrinsics_count: 0,
-        events_count: 0,
-        extrinsics: vec![],
-    }));
-
-    // Spawn block subscription task, this could use some cleaning up (not too much though!)
-    let block_info_clone = block_info.clone();
-    let db_clone = database.clone();
-    tokio::spawn(async move {
-        let mut blocks_sub = polkadot_api.blocks().subscribe_finalized().await.unwrap(); // double and triple check if this really gives the finalized stuff 
-        while let Some(block) = blocks_sub.next().await {
-            if let Ok(block) = block {
-                let block_number = block.number();
-
-                // Skip if block already exists in database (deduplication)
-                if let Ok(Some(_)) = db_clone.get_block(block_number) {
-                    continue;
-                }
+    if let Some(Command::Filters { chain, dry_run, blocks }) = cli.command {
+        let filters = filters(&config, cli.preset);
+        println!("{}", toml::to_string(&filters).map_err(|e| e.to_string())?);
+        if !dry_run {
+            return Ok(());
+        }
+        for registered in registry.chains()? {
+            if chain.as_ref().is_some_and(|c| *c != registered.name) {
+                continue;
+            }
+            println!("=== {} ({}) ===\n", registered.name, registered.db_path.display());
+            let database = db::Database::new(&registered.db_path, &config.sqlite)?;
+            filter_report::run(&database, &filters, blocks)?;
+            println!();
+        }
+        return Ok(());
+    }
 
-                let extrinsics = block.extrinsics().await.unwrap();
-                let mut total_events = 0;
+    if let Some(Command::Prune { chain, before }) = cli.command {
+        for registered in registry.chains()? {
+            if chain.as_ref().is_some_and(|c| *c != registered.name) {
+                continue;
+            }
+            let database = Arc::new(db::Database::new(&registered.db_path, &config.sqlite)?);
+            let deleted = retention::prune_before(&registered.name, database, before).await?;
+            println!("{}: deleted {} blocks below #{}", registered.name, deleted, before);
+        }
+        return Ok(());
+    }
 
-                let mut extrinsics_info: Vec<ExtrinsicInfo> = Vec::new();
+    if let Some(Command::Export { chain, format, output }) = cli.command {
+        for registered in registry.chains()? {
+            if chain.as_ref().is_some_and(|c| *c != registered.name) {
+                continue;
+            }
+            let database = db::Database::new(&registered.db_path, &config.sqlite)?;
+            let exported = export::run(format, &registered.name, &database, &output)?;
+            println!("{}: exported {} blocks to {}", registered.name, exported, output.display());
+        }
+        return Ok(());
+    }
 
-                for extrinsic_details in extrinsics.iter() {
-                    let idx = extrinsic_details.index();
-                    let hash = format!("{:?}", extrinsic_details.hash());
-                    let meta = extrinsic_details.extrinsic_metadata().ok();
-                    let action = meta
-                        .map(|m| format!("{}/{}", m.pallet.name(), m.variant.name))
-                        .unwrap_or_else(|| "unknown".to_string());
+    // Fail on bad certificates before spending time on the light client
+    let tls = match (&cli.tls_cert, &cli.tls_key) {
+        (Some(cert), Some(key)) => Some(server::load_tls(cert, key)?),
+        _ => None,
+    };
 
-                    // Apply extrinsic filtering
-                    if !db_clone.should_include_extrinsic(&action) {
-                        continue;
-                    }
+    let mut chain_args = cli.chains.clone();
+    for url in &cli.chain_spec_urls {
+        chain_args.push(url.fetch(&cli.data_dir.join("specs")).await?);
+    }
+    if chain_args.is_empty() {
+        chain_args.push("polkadot".parse()?);
+    }
+    let mut specs = chain_args.iter().map(chain::ChainSpec::load).collect::<Result<Vec<_>, _>>()?;
+    for spec in &mut specs {
+        if let Some(network) = config.network.get(&spec.name) {
+            spec.apply_network(network)?;
+        }
+    }
+    if let Some(name) = config.network.keys().find(|name| !specs.iter().any(|spec| spec.name == **name)) {
+        tracing::warn!(chain = %name, "[network] names a chain that isn't indexed, ignoring it");
+    }
 
-                    // Get extrinsic parameters
-                    let params = extrinsic_details
-                        .field_values()
-                        .ok()
-                        .map(|fv| format!("{}", fv))
-                        .unwrap_or_else(|| "".to_string());
-
-                    // Get events for this extrinsic
-                    let events = extrinsic_details.events().await.unwrap();
-                    let mut events_info: Vec<EventInfo> = Vec::new();
-
-                    for evt in events.iter() {
-                        if let Ok(evt) = evt {
-                            let pallet = evt.pallet_name();
-                            let variant = evt.variant_name();
-
-                            // Apply filtering
-                            if !db_clone.should_include_event(pallet, variant) {
-                                continue;
-                            }
+    for (i, spec) in specs.iter().enumerate() {
+        if specs[..i].iter().any(|s| s.name == spec.name) {
+            return Err(format!("chain {} is configured twice", spec.name).into());
+        }
+    }
 
-                            let field_values = evt.field_values().ok();
-                            events_info.push(EventInfo {
-                                pallet: pallet.to_string(),
-                                variant: variant.to_string(),
-                                data: field_values
-                                    .map(|fv| format!("{}", fv))
-                                    .unwrap_or_else(|| "".to_string()),
-                            });
-                        }
-                    }
+    // Initialize database
+    let filters = filters(&config, cli.preset);
+    if let Some(preset) = cli.preset {
+        info!(?preset, "Using a filter preset, [filters] is ignored");
+    }
 
-                    total_events += events_info.len();
+    // Relay chains get their own light client, parachains hang off their relay chain's one
+    let mut light_clients: Vec<(String, LightClient)> = Vec::new();
+    let mut chains: Vec<(chain::ChainSpec, subxt::lightclient::LightClientRpc)> = Vec::new();
+    for spec in specs.iter().filter(|s| s.relay_chain.is_none()) {
+        info!(chain = %spec.name, "Connecting via light client");
+        let (lightclient, rpc) = LightClient::relay_chain(spec.spec.as_str())?;
+        light_clients.push((spec.id.clone(), lightclient));
+        chains.push((spec.clone(), rpc));
+    }
+    for spec in specs.iter().filter(|s| s.relay_chain.is_some()) {
+        let relay_id = spec.relay_chain.as_deref().unwrap_or_default();
+        let (_, relay) = light_clients
+            .iter()
+            .find(|(id, _)| id == relay_id)
+            .ok_or_else(|| {
+                format!(
+                    "{} is a parachain of {:?}, add that relay chain with --chain as well",
+                    spec.name, relay_id
+                )
+            })?;
+        info!(chain = %spec.name, relay_chain = %relay_id, "Connecting via light client");
+        let rpc = relay.parachain(spec.spec.as_str())?;
+        chains.push((spec.clone(), rpc));
+    }
+    // Keep chains in the order they were given on the command line
+    chains.sort_by_key(|(spec, _)| specs.iter().position(|s| s.name == spec.name));
+
+    let (config_tx, config_rx) = watch::channel(config.clone());
+    let stream = stream::EventStream::default();
+    // Nothing gets stored with --read-only, so there's nothing to publish either
+    let mut sinks = if cli.read_only { sinks::Sinks::default() } else { build_sinks(&config).await? };
+    sinks.add(Box::new(stream.clone()));
+    let sinks = Arc::new(sinks);
+    let archive = build_archive(&config)?;
+    let alerts = Arc::new(alerts::Alerts::new(
+        config.alert_channels.clone(),
+        config.alerts.clone(),
+        config.offence_alerts.clone(),
+        config.offline_alerts.clone(),
+    )?);
+
+    systemd::status("Waiting for the light client");
+
+    let mut app_states = Vec::new();
+    let mut chain_ids = Vec::new();
+    let mut standby_sources = Vec::new();
+    for (spec, rpc) in chains {
+        let (registered, database) = if cli.read_only {
+            let registered = registry
+                .get(&spec.name)?
+                .ok_or_else(|| format!("{} hasn't been indexed in {} yet", spec.name, cli.data_dir.display()))?;
+            let database = db::Database::open_read_only(&registered.db_path, &config.sqlite)?;
+            (registered, database)
+        } else {
+            let registered = registry.register(&spec.name)?;
+            let database = db::Database::new(&registered.db_path, &config.sqlite)?;
+            (registered, database)
+        };
+        let database = Arc::new(database);
+        database.set_filters(&filters);
+        info!(chain = %spec.name, path = %registered.db_path.display(), "Database initialized");
+
+        if let Ok(Some(latest)) = database.get_latest_block_number() {
+            info!(chain = %spec.name, latest, "Latest block in database");
+        }
 
-                    extrinsics_info.push(ExtrinsicInfo {
-                        index: idx,
-                        hash,
-                        action,
-                        params,
-                        events: events_info,
-                    });
-                }
+        let rpc = RpcClient::new(rpc);
+        // chainHead_v1_follow instead of the legacy subscriptions: blocks stay pinned on the
+        // light client while we index them and are unpinned once dropped
+        let backend = ChainHeadBackend::<PolkadotConfig>::builder().build_with_background_driver(rpc.clone());
+        let api = OnlineClient::<PolkadotConfig>::from_backend(Arc::new(backend)).await?;
+
+        let genesis_hash = format!("{:?}", api.genesis_hash());
+        match &registered.genesis_hash {
+            Some(known) if *known != genesis_hash => {
+                return Err(format!(
+                    "{} belongs to a chain with genesis {}, but the {} spec has genesis {}",
+                    registered.db_path.display(), known, spec.name, genesis_hash
+                )
+                .into());
+            }
+            Some(_) => {}
+            None if cli.read_only => {}
+            None => registry.set_genesis_hash(&spec.name, &genesis_hash)?,
+        }
 
-                let block_number = block.number();
-                let block_hash = format!("{:?}", block.hash());
-
-                // Update in-memory state
-                let mut info = block_info_clone.write().await;
-                info.number = block_number;
-                info.hash = block_hash.clone();
-                info.extrinsics_count = extrinsics_info.len();
-                info.events_count = total_events;
-                info.extrinsics = extrinsics_info.clone();
-
-                // Store in database
-                let stored_block = db::StoredBlock {
-                    number: block_number,
-                    hash: block_hash.clone(),
-                    extrinsics: extrinsics_info.iter().map(|e| serde_json::to_value(e).unwrap()).collect(),
-                    timestamp: chrono::Utc::now().timestamp(),
-                };
-
-                if let Err(e) = db_clone.store_block(&stored_block) {
-                    eprintln!("Failed to store block #{}: {}", block_number, e);
+        let block_info = Arc::new(ArcSwap::from_pointee(indexer::BlockInfo::default()));
+        let sync = Arc::new(sync::SyncTracker::default());
+        tokio::spawn(sync.clone().run(spec.name.clone(), LegacyRpcMethods::new(rpc.clone()), block_info.clone()));
+        if cli.read_only {
+            tokio::spawn(indexer::follow_stored(database.clone(), block_info.clone()));
+        } else {
+            subscriptions::load(&spec.name, &database, &alerts)?;
+            standby_sources.push((spec.name.clone(), registered.db_path.clone()));
+            tokio::spawn(retention::run(
+                spec.name.clone(),
+                database.clone(),
+                archive.clone(),
+                config_rx.clone(),
+            ));
+
+            // With a cursor name, progress is kept there and picked up again after a restart
+            let mut backfills: Vec<(config::BackfillConfig, Option<&str>)> = Vec::new();
+            if let Some(backfill) = &config.backfill {
+                backfills.push((backfill.clone(), None));
+            }
+            if cli.full_archive {
+                let genesis = config::BackfillConfig::starting_at(0, config.backfill.as_ref());
+                backfills.push((genesis, Some(db::FULL_ARCHIVE)));
+            }
+            // Databases from before the cursor existed go by the newest stored block
+            let latest = match database.get_cursor(db::LAST_PROCESSED)? {
+                Some((number, _)) => Some(number),
+                None => database.get_latest_block_number()?,
+            };
+            match (latest, cli.from_block.first_block().filter(|_| !cli.full_archive)) {
+                (Some(latest), from) => {
+                    if from.is_some() {
+                        info!(chain = %spec.name, "The chain is indexed already, ignoring --from-block");
+                    }
+                    if config.catch_up {
+                        // The follower moves past the blocks still missing right away, so catching
+                        // up keeps its own cursor, and a restart picks an unfinished one up again
+                        let from = match database.get_cursor(db::CATCH_UP)? {
+                            Some((done, _)) => done + 1,
+                            None => {
+                                let hash = database.get_block_hash(latest)?.unwrap_or_default();
+                                database.set_cursor(db::CATCH_UP, latest, &hash)?;
+                                latest + 1
+                            }
+                        };
+                        info!(chain = %spec.name, from, "Catching up on the blocks finalized while stopped");
+                        backfills.push((
+                            config::BackfillConfig::starting_at(from, config.backfill.as_ref()),
+                            Some(db::CATCH_UP),
+                        ));
+                    }
                 }
-
-                println!("Block #{} - {} extrinsics, {} events (stored)",
-                    info.number, info.extrinsics_count, info.events_count);
+                (None, Some(from)) => {
+                    info!(chain = %spec.name, from, "Indexing from --from-block up to the head");
+                    backfills.push((config::BackfillConfig::starting_at(from, config.backfill.as_ref()), None));
+                }
+                (None, None) => {}
             }
+            // Old blocks can only be fetched through the legacy calls
+            let backfills = if backfills.is_empty() {
+                Vec::new()
+            } else {
+                let legacy_api = OnlineClient::from_backend(Arc::new(LegacyBackend::builder().build(rpc.clone()))).await?;
+                backfills
+                    .into_iter()
+                    .map(|(config, checkpoint)| backfill::Backfill {
+                        config,
+                        api: legacy_api.clone(),
+                        rpc: LegacyRpcMethods::new(rpc.clone()),
+                        checkpoint: checkpoint.map(str::to_string),
+                        // Catching up is done for good, unlike an archive that's only done up to now
+                        clear_checkpoint: checkpoint == Some(db::CATCH_UP),
+                        sync: sync.clone(),
+                    })
+                    .collect()
+            };
+
+            tokio::spawn(
+                indexer::Indexer {
+                    chain: spec.name.clone(),
+                    api: api.clone(),
+                    db: database.clone(),
+                    block_info: block_info.clone(),
+                    low_memory: config.low_memory,
+                    track_inclusions: config.track_inclusions,
+                    justifications: config.store_justifications.then(|| LegacyRpcMethods::new(rpc.clone())),
+                    parallelism: config.parallel_blocks,
+                    ss58_prefix: spec.ss58_format,
+                    decoders: Arc::new(decoders::for_chain(&spec.name)),
+                    alerts: alerts.clone(),
+                    sinks: sinks.clone(),
+                    backfills,
+                }
+                .run(),
+            );
+        }
+
+        chain_ids.push((spec.id.clone(), spec.relay_chain.clone(), spec.para_id));
+        app_states.push((
+            spec.name.clone(),
+            api::AppState {
+                chain: spec.name.clone(),
+                admin_token: admin_token.clone(),
+                read_only: cli.read_only,
+                block_info,
+                db: database,
+                archive: archive.clone(),
+                api,
+                rpc,
+                // It could submit extrinsics
+                rpc_passthrough: config.rpc_passthrough && !cli.read_only,
+                token_symbol: spec.token_symbol.clone(),
+                links: parachains::ChainLinks::default(),
+                alerts: alerts.clone(),
+                sync,
+            },
+        ));
+    }
+
+    if !cli.read_only {
+        let registry_path = cli.data_dir.join(registry::REGISTRY_FILE);
+        tokio::spawn(standby::run(registry_path, standby_sources, config_rx.clone()));
+    }
+
+    // Point parachains and their relay chain at each other
+    for i in 0..app_states.len() {
+        let (Some(relay_id), Some(para_id)) = (&chain_ids[i].1, chain_ids[i].2) else {
+            continue;
+        };
+        let Some(r) = chain_ids.iter().position(|(id, _, _)| id == relay_id) else {
+            continue;
+        };
+        let (relay_name, relay_db) = (app_states[r].0.clone(), app_states[r].1.db.clone());
+        let name = app_states[i].0.clone();
+        app_states[i].1.links.relay = Some(parachains::RelayLink { chain: relay_name, db: relay_db, para_id });
+        app_states[r].1.links.parachains.push((para_id, name));
+    }
+
+    if let Some(addr) = cli.grpc_listen {
+        #[cfg(feature = "grpc")]
+        {
+            let chains = app_states
+                .iter()
+                .map(|(name, state)| (name.clone(), state.db.clone()))
+                .collect();
+            let stream = stream.clone();
+            tokio::spawn(async move {
+                if let Err(e) = grpc::serve(addr, chains, stream).await {
+                    tracing::error!("{}", e);
+                }
+            });
         }
-    });
+        #[cfg(not(feature = "grpc"))]
+        tracing::warn!(%addr, "--grpc-listen is set but smolcar was built without the `grpc` feature, ignoring it");
+    }
+
+    // Keep the light clients alive for as long as we run
+    let _light_clients = light_clients;
 
     // Build API
-    let app_state = AppState {
-        block_info,
-        db: database,
-    };
+    let limits = limits::Limits::new(std::time::Duration::from_secs(cli.request_timeout), cli.max_requests);
+    let app = api::router(&app_states, stream, limits);
+    let listeners = server::bind_all(&cli.listen)?;
+
+    info!(
+        listen = %cli.listen.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "),
+        tls = tls.is_some(),
+        "Smolcar API running"
+    );
+    info!("Dashboard: /, live stream: /stream");
+    info!("Endpoints: /blocks/head, /block/{{number}}");
+    for (name, _) in &app_states {
+        info!("Endpoints: /{}/blocks/head, /{}/block/{{number}}", name, name);
+    }
 
-    let app = Router::new()
-        .route("/blocks/head", get(get_head_block))
-        .route("/block/:number", get(get_block_by_number))
-        .with_state(app_state);
+    systemd::ready(if cli.read_only { "Serving read-only" } else { "Indexing" });
+    systemd::spawn_watchdog();
 
-    println!("\nSmolcar API running on http://localhost:8080");
-    println!("Endpoints:");
-    println!("  - http://localhost:8080/blocks/head");
-    println!("  - http://localhost:8080/block/{{number}}\n");
+    if let Some(path) = cli.config.clone() {
+        let databases = app_states.iter().map(|(_, state)| state.db.clone()).collect();
+        tokio::spawn(reload_on_sighup(path, cli.preset, databases, alerts, config_tx));
+    }
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8080").await?;
-    axum::serve(listener, app).await?;
+    server::serve(app, listeners, tls).await?;
 
     Ok(())
 }
 
-async fn get_head_block(State(state): State<AppState>) -> Json<BlockInfo> {
-    let info = state.block_info.read().await;
-    Json(info.clone())
+async fn build_sinks(config: &config::Config) -> Result<sinks::Sinks, String> {
+    #[allow(unused_mut)]
+    let mut sinks = sinks::Sinks::default();
+
+    if let Some(mqtt) = &config.mqtt {
+        #[cfg(feature = "mqtt")]
+        sinks.add(Box::new(mqtt::MqttSink::connect(mqtt)?));
+        #[cfg(not(feature = "mqtt"))]
+        tracing::warn!(host = %mqtt.host, "MQTT is configured but smolcar was built without the `mqtt` feature, ignoring it");
+    }
+
+    if let Some(redis) = &config.redis {
+        #[cfg(feature = "redis")]
+        sinks.add(Box::new(redis_sink::RedisSink::connect(redis).await?));
+        #[cfg(not(feature = "redis"))]
+        {
+            let _ = redis;
+            tracing::warn!("Redis is configured but smolcar was built without the `redis` feature, ignoring it");
+        }
+    }
+
+    if let Some(clickhouse) = &config.clickhouse {
+        #[cfg(feature = "clickhouse")]
+        sinks.add(Box::new(clickhouse::ClickHouseSink::connect(clickhouse).await?));
+        #[cfg(not(feature = "clickhouse"))]
+        {
+            let _ = clickhouse;
+            tracing::warn!("ClickHouse is configured but smolcar was built without the `clickhouse` feature, ignoring it");
+        }
+    }
+
+    Ok(sinks)
+}
+
+fn build_archive(config: &config::Config) -> Result<Option<Arc<archive::Archive>>, String> {
+    let Some(archive) = &config.archive else {
+        return Ok(None);
+    };
+
+    #[cfg(feature = "s3")]
+    return Ok(Some(Arc::new(archive::Archive::connect(archive)?)));
+
+    #[cfg(not(feature = "s3"))]
+    {
+        tracing::warn!(bucket = %archive.bucket, "An archive is configured but smolcar was built without the `s3` feature, pruned blocks won't be archived");
+        Ok(None)
+    }
+}
+
+// The --preset's filters, else the config's, or the compiled-in ones above if it has no [filters] section
+fn filters(config: &config::Config, preset: Option<presets::Preset>) -> config::Filters {
+    match (preset, &config.filters) {
+        (Some(preset), _) => preset.filters(),
+        (None, Some(filters)) => filters.clone(),
+        (None, None) => config::Filters {
+            exclude_events: EXCLUDED_EVENTS
+                .iter()
+                .map(|(pallet, method)| match method {
+                    Some(method) => format!("{}/{}", pallet, method),
+                    None => pallet.to_string(),
+                })
+                .collect(),
+            exclude_extrinsics: EXCLUDED_EXTRINSICS.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        },
+    }
 }
 
-async fn get_block_by_number(
-    State(state): State<AppState>,
-    Path(block_number): Path<u32>,
-) -> impl IntoResponse {
-    match state.db.get_block(block_number) {
-        Ok(Some(block)) => (StatusCode::OK, Json(block)).into_response(),
-        Ok(None) => (
-            StatusCode::NOT_FOUND,
-            Json(serde_json::json!({
-                "error": format!("Block #{} not found", block_number)
-            })),
-        )
-            .into_response(),
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(serde_json::json!({
-                "error": format!("Database error: {}", e)
-            })),
-        )
-            .into_response(),
+/// Re-reads the config file on SIGHUP and applies filters, alerts and retention settings.
+/// Everything else (chains, listen addresses, low_memory...) still needs a restart.
+async fn reload_on_sighup(
+    path: PathBuf,
+    preset: Option<presets::Preset>,
+    databases: Vec<Arc<db::Database>>,
+    alerts: Arc<alerts::Alerts>,
+    config_tx: watch::Sender<config::Config>,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            tracing::warn!("Can't listen for SIGHUP, config reload disabled: {}", e);
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        systemd::reloading();
+        let loaded = config::Config::load(&path).and_then(|config| {
+            alerts.replace(
+                config.alert_channels.clone(),
+                config.alerts.clone(),
+                config.offence_alerts.clone(),
+                config.offline_alerts.clone(),
+            )?;
+            Ok(config)
+        });
+        match loaded {
+            Ok(config) => {
+                let filters = filters(&config, preset);
+                for db in &databases {
+                    db.set_filters(&filters);
+                }
+                config_tx.send_replace(config);
+                info!(path = %path.display(), "Config reloaded");
+                systemd::ready("Indexing");
+            }
+            Err(e) => {
+                tracing::error!("Config reload failed, keeping the current one: {}", e);
+                systemd::ready("Indexing (last config reload failed)");
+            }
+        }
     }
 }
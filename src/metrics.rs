@@ -0,0 +1,95 @@
+//! Prometheus metrics on `/metrics`: how long database operations take, as histograms over
+//! every chain. Kept by hand rather than with a metrics crate, there are only a few of them.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+// Upper bounds in seconds, from a cached lookup to a write stuck behind a checkpoint
+const BUCKETS: [f64; 14] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Clone, Copy)]
+pub enum DbOperation {
+    /// Writing a block
+    Store,
+    /// Reading one block
+    Get,
+    /// Reading a run of blocks
+    Range,
+}
+
+impl DbOperation {
+    const ALL: [DbOperation; 3] = [DbOperation::Store, DbOperation::Get, DbOperation::Range];
+
+    fn name(self) -> &'static str {
+        match self {
+            DbOperation::Store => "store",
+            DbOperation::Get => "get",
+            DbOperation::Range => "range",
+        }
+    }
+}
+
+struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Histogram {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS.len()],
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, seconds: f64) {
+        // Counted in the first bucket it fits, the exposition sums them up
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add((seconds * 1e6) as u64, Ordering::Relaxed);
+    }
+}
+
+static DB_OPERATIONS: [Histogram; 3] = [Histogram::new(), Histogram::new(), Histogram::new()];
+
+/// Records how long the operation took once dropped.
+pub struct Timer {
+    operation: DbOperation,
+    started: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        DB_OPERATIONS[self.operation as usize].observe(self.started.elapsed().as_secs_f64());
+    }
+}
+
+pub fn time(operation: DbOperation) -> Timer {
+    Timer { operation, started: Instant::now() }
+}
+
+pub async fn render() -> ([(&'static str, &'static str); 1], String) {
+    let mut out = String::new();
+    out.push_str("# HELP smolcar_db_operation_seconds Time spent in database operations, lock waits included\n");
+    out.push_str("# TYPE smolcar_db_operation_seconds histogram\n");
+    for operation in DbOperation::ALL {
+        let histogram = &DB_OPERATIONS[operation as usize];
+        let name = operation.name();
+        let mut cumulative = 0;
+        for (bound, bucket) in BUCKETS.iter().zip(&histogram.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "smolcar_db_operation_seconds_bucket{{operation=\"{}\",le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let count = histogram.count.load(Ordering::Relaxed);
+        let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "smolcar_db_operation_seconds_bucket{{operation=\"{}\",le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "smolcar_db_operation_seconds_sum{{operation=\"{}\"}} {}", name, sum);
+        let _ = writeln!(out, "smolcar_db_operation_seconds_count{{operation=\"{}\"}} {}", name, count);
+    }
+    ([("content-type", "text/plain; version=0.0.4")], out)
+}
@@ -0,0 +1,101 @@
+//! Publishes block headers and (filtered) events to an MQTT broker.
+//!
+//! Topics, with the default `smolcar` prefix:
+//! - `smolcar/<chain>/blocks` for every stored block (retained, so new subscribers get the head)
+//! - `smolcar/<chain>/events/<Pallet>/<Variant>` for every stored event
+
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use std::time::Duration;
+
+use crate::config::MqttConfig;
+use crate::indexer::ExtrinsicInfo;
+use crate::sinks::{BlockSummary, Sink};
+
+// Messages queued for the broker before we start dropping them
+const QUEUE_SIZE: usize = 1024;
+
+pub struct MqttSink {
+    client: AsyncClient,
+    prefix: String,
+    qos: QoS,
+}
+
+impl MqttSink {
+    /// Connects in the background, reconnecting whenever the broker goes away.
+    pub fn connect(config: &MqttConfig) -> Result<Self, String> {
+        let qos = match config.qos {
+            0 => QoS::AtMostOnce,
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            other => return Err(format!("invalid MQTT qos {}, use 0, 1 or 2", other)),
+        };
+
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.clone().unwrap_or_default());
+        }
+
+        let (client, mut eventloop) = AsyncClient::new(options, QUEUE_SIZE);
+        let broker = format!("{}:{}", config.host, config.port);
+        tokio::spawn(async move {
+            let mut connected = false;
+            loop {
+                match eventloop.poll().await {
+                    Ok(rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_))) => {
+                        tracing::info!(%broker, "Connected to MQTT broker");
+                        connected = true;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        if connected {
+                            tracing::warn!(%broker, "MQTT connection lost: {}", e);
+                            connected = false;
+                        }
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                }
+            }
+        });
+
+        Ok(MqttSink {
+            client,
+            prefix: config.topic_prefix.trim_end_matches('/').to_string(),
+            qos,
+        })
+    }
+
+    fn publish(&self, topic: String, retain: bool, payload: Vec<u8>) {
+        if let Err(e) = self.client.try_publish(topic, self.qos, retain, payload) {
+            tracing::warn!("Dropping MQTT message: {}", e);
+        }
+    }
+}
+
+impl Sink for MqttSink {
+    fn on_extrinsic(&self, chain: &str, block_number: u32, extrinsic: &ExtrinsicInfo) {
+        for event in &extrinsic.events {
+            let payload = serde_json::json!({
+                "chain": chain,
+                "block": block_number,
+                "extrinsic": extrinsic.index,
+                "pallet": event.pallet,
+                "variant": event.variant,
+                "data": event.data,
+            });
+            self.publish(
+                format!("{}/{}/events/{}/{}", self.prefix, chain, event.pallet, event.variant),
+                false,
+                payload.to_string().into_bytes(),
+            );
+        }
+    }
+
+    fn on_block(&self, block: &BlockSummary) {
+        self.publish(
+            format!("{}/{}/blocks", self.prefix, block.chain),
+            true,
+            serde_json::to_vec(block).unwrap(),
+        );
+    }
+}
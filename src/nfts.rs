@@ -0,0 +1,131 @@
+//! `Nfts` and `Uniques` pallet tracking: collections, items with their current owner, and item
+//! attributes, served on `/nfts/collections/:id/items`.
+//!
+//! Both pallets number their collections separately, so everything is keyed by pallet too.
+//! Collection-level attributes aren't kept.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use subxt::events::Events;
+use subxt::ext::scale_value::{At, Composite, Value, ValueDef};
+use subxt::PolkadotConfig;
+
+use crate::api::AppState;
+use crate::db;
+use crate::error::Error;
+use crate::decode;
+use crate::indexer::TrackerUpdates;
+
+const MAX_ITEMS: u32 = 1000;
+
+const FAILURE: &str = "Failed to record NFT event";
+
+fn number(value: Option<&Value<u32>>) -> Option<u32> {
+    value?.as_u128().and_then(|n| u32::try_from(n).ok())
+}
+
+// Keys and values are bytes, usually text
+fn text(value: Option<&Value<u32>>) -> Option<String> {
+    let bytes = decode::bytes(value?)?;
+    Some(match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => format!("0x{}", hex::encode(e.into_bytes())),
+    })
+}
+
+// `maybe_item` is an Option, which scale-value decodes as a Some/None variant
+fn maybe_item(value: Option<&Value<u32>>) -> Option<u32> {
+    match &value?.value {
+        ValueDef::Variant(option) if option.name == "Some" => number(option.values.at(0)),
+        _ => None,
+    }
+}
+
+fn record_event(
+    updates: &mut TrackerUpdates,
+    decode: &decode::Context,
+    block_number: u32,
+    pallet: &str,
+    variant: &str,
+    fields: &Composite<u32>,
+) {
+    let Some(collection) = number(fields.at("collection")) else {
+        return;
+    };
+    let pallet = pallet.to_string();
+    let account = |name| fields.at(name).and_then(|v| decode.account(v));
+    let item = number(fields.at("item"));
+
+    match variant {
+        "Created" | "ForceCreated" => {
+            let owner = account("owner");
+            updates.push(FAILURE, move |db| {
+                db.record_nft_collection(&pallet, collection, block_number, owner.as_deref())
+            });
+        }
+        "Destroyed" => {
+            updates.push(FAILURE, move |db| db.set_nft_collection_destroyed(&pallet, collection, block_number));
+        }
+        "Issued" | "Transferred" | "Burned" => {
+            let (event, owner) = match variant {
+                "Issued" => ("issued", account("owner")),
+                "Transferred" => ("transferred", account("to")),
+                _ => ("burned", None),
+            };
+            if let Some(item) = item {
+                updates.push(FAILURE, move |db| {
+                    db.record_nft_item(&pallet, collection, item, event, owner.as_deref(), block_number)
+                });
+            }
+        }
+        "AttributeSet" | "AttributeCleared" => {
+            let value = match variant {
+                "AttributeSet" => text(fields.at("value")),
+                _ => None,
+            };
+            if let (Some(item), Some(key)) = (maybe_item(fields.at("maybe_item")), text(fields.at("key"))) {
+                updates.push(FAILURE, move |db| {
+                    db.set_nft_attribute(&pallet, collection, item, &key, value.as_deref(), block_number)
+                });
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Records the block's `Nfts` and `Uniques` events.
+pub fn record(updates: &mut TrackerUpdates, decode: &decode::Context, block_number: u32, events: &Events<PolkadotConfig>) {
+    for event in events.iter().flatten() {
+        let pallet = event.pallet_name();
+        if pallet != "Nfts" && pallet != "Uniques" {
+            continue;
+        }
+        let Ok(fields) = event.field_values() else {
+            continue;
+        };
+        record_event(updates, decode, block_number, pallet, event.variant_name(), &fields);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ItemsQuery {
+    /// "Nfts" (the default) or "Uniques"
+    pallet: Option<String>,
+    after: Option<u32>,
+    limit: Option<u32>,
+}
+
+/// A collection's items by id, paged with `?after=<last id>`.
+pub async fn get_items(
+    State(state): State<AppState>,
+    Path(collection): Path<u32>,
+    Query(query): Query<ItemsQuery>,
+) -> Result<Json<Vec<db::NftItem>>, Error> {
+    let pallet = query.pallet.unwrap_or_else(|| "Nfts".to_string());
+    let limit = query.limit.unwrap_or(100).min(MAX_ITEMS);
+
+    Ok(Json(state.db.blocking(move |db| db.get_nft_items(&pallet, collection, query.after, limit)).await?))
+}
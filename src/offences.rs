@@ -0,0 +1,119 @@
+//! `Offences::Offence` reports (equivocations, unresponsiveness...) in the `offences` table,
+//! served on `/offences`. The event only has the offence kind and time slot, so the offenders
+//! are read from the pallet's reports in storage.
+
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use subxt::dynamic;
+use subxt::events::Events;
+use subxt::ext::scale_value::{At, Value, ValueDef};
+use subxt::utils::H256;
+use subxt::{OnlineClient, PolkadotConfig};
+
+use crate::api::AppState;
+use crate::db::Offence;
+use crate::error::Error;
+use crate::decode;
+use crate::indexer::TrackerUpdates;
+use crate::reporting;
+
+const MAX_OFFENCES: u32 = 500;
+
+async fn fetch(
+    api: &OnlineClient<PolkadotConfig>,
+    at: H256,
+    entry: &str,
+    keys: Vec<Value>,
+) -> Result<Option<Value<u32>>, subxt::Error> {
+    match api.storage().at(at).fetch(&dynamic::storage("Offences", entry, keys)).await? {
+        Some(value) => Ok(Some(value.to_value()?)),
+        None => Ok(None),
+    }
+}
+
+// Everyone reported for this kind of offence in this time slot
+async fn offenders(
+    api: &OnlineClient<PolkadotConfig>,
+    decode: &decode::Context,
+    at: H256,
+    kind: &[u8],
+    time_slot: &[u8],
+) -> Result<Vec<String>, subxt::Error> {
+    let keys = vec![Value::from_bytes(kind), Value::from_bytes(time_slot)];
+    let report_ids = match fetch(api, at, "ConcurrentReportsIndex", keys).await? {
+        Some(Value { value: ValueDef::Composite(ids), .. }) => ids.values().filter_map(decode::bytes).collect(),
+        _ => vec![],
+    };
+
+    let mut offenders = Vec::new();
+    for id in report_ids {
+        let Some(report) = fetch(api, at, "Reports", vec![Value::from_bytes(id)]).await? else {
+            continue;
+        };
+        // Usually `(AccountId, Exposure)`, just the account on some runtimes
+        let offender = report.at("offender");
+        let account = offender.and_then(|o| decode.account(o).or_else(|| decode.account(o.at(0)?)));
+        if let Some(account) = account.filter(|account| !offenders.contains(account)) {
+            offenders.push(account);
+        }
+    }
+    Ok(offenders)
+}
+
+/// Records the block's offence reports and returns them, for alerts.
+pub async fn record(
+    updates: &mut TrackerUpdates,
+    chain: &str,
+    api: &OnlineClient<PolkadotConfig>,
+    decode: &decode::Context,
+    block_number: u32,
+    block_hash: H256,
+    events: &Events<PolkadotConfig>,
+) -> Vec<Offence> {
+    let mut recorded = Vec::new();
+    for event in events.iter().flatten() {
+        if event.pallet_name() != "Offences" || event.variant_name() != "Offence" {
+            continue;
+        }
+        let Ok(fields) = event.field_values() else {
+            continue;
+        };
+        let (Some(kind), Some(time_slot)) =
+            (fields.at("kind").and_then(decode::bytes), fields.at("timeslot").and_then(decode::bytes))
+        else {
+            continue;
+        };
+
+        let offenders = offenders(api, decode, block_hash, &kind, &time_slot).await.unwrap_or_else(|e| {
+            reporting::block_error(chain, block_number, &format!("Failed to read offence reports: {}", e));
+            vec![]
+        });
+        let offence = Offence {
+            block_number,
+            kind: String::from_utf8_lossy(&kind).to_string(),
+            time_slot: format!("0x{}", hex::encode(&time_slot)),
+            offenders,
+        };
+        let (event_index, stored) = (event.index(), offence.clone());
+        updates.push("Failed to record offence", move |db| db.record_offence(event_index, &stored));
+        recorded.push(offence);
+    }
+    recorded
+}
+
+#[derive(Deserialize)]
+pub struct OffencesQuery {
+    limit: Option<u32>,
+}
+
+/// The latest offences, newest first.
+pub async fn get_offences(
+    State(state): State<AppState>,
+    Query(query): Query<OffencesQuery>,
+) -> Result<Json<Vec<Offence>>, Error> {
+    let limit = query.limit.unwrap_or(50).min(MAX_OFFENCES);
+    Ok(Json(state.db.blocking(move |db| db.get_offences(limit)).await?))
+}
@@ -0,0 +1,170 @@
+//! Relay chain → parachain block mapping, from the `ParaInclusion` candidate events, kept in
+//! `para_inclusions` and served on `/parachains/:id/inclusions`. Off unless `track_inclusions`
+//! is set, since a relay block carries a candidate for almost every parachain.
+//!
+//! When a parachain is indexed with its relay chain, `/block/:number/relay` on the parachain and
+//! `/block/:number/parachains` on the relay chain link their blocks both ways.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use subxt::events::Events;
+use subxt::ext::codec::{Compact, Decode};
+use subxt::ext::scale_value::{At, Composite, Value, ValueDef};
+use subxt::PolkadotConfig;
+
+use crate::api::AppState;
+use crate::db::{Database, ParaInclusion};
+use crate::decode;
+use crate::error::Error;
+use crate::indexer::TrackerUpdates;
+
+const MAX_INCLUSIONS: u32 = 1000;
+
+/// The relay chain or parachains indexed alongside a chain.
+#[derive(Clone, Default)]
+pub struct ChainLinks {
+    /// For a parachain whose spec has its para id
+    pub relay: Option<RelayLink>,
+    /// For a relay chain: names of its indexed parachains by para id
+    pub parachains: Vec<(u32, String)>,
+}
+
+#[derive(Clone)]
+pub struct RelayLink {
+    pub chain: String,
+    pub db: Arc<Database>,
+    pub para_id: u32,
+}
+
+// Through newtypes like `Id(u32)`
+fn number(value: Option<&Value<u32>>) -> Option<u32> {
+    let value = value?;
+    match &value.value {
+        ValueDef::Composite(Composite::Unnamed(values)) if values.len() == 1 => number(values.first()),
+        _ => value.as_u128().and_then(|n| u32::try_from(n).ok()),
+    }
+}
+
+fn hash(value: Option<&Value<u32>>) -> Option<String> {
+    decode::bytes(value?).map(|bytes| format!("0x{}", hex::encode(bytes)))
+}
+
+// Head data is the parachain's encoded header: parent hash, then the compact block number
+fn head_number(head_data: &[u8]) -> Option<u32> {
+    let mut number = head_data.get(32..)?;
+    Compact::<u32>::decode(&mut number).ok().map(|n| n.0)
+}
+
+/// Records the block's `CandidateBacked` and `CandidateIncluded` events.
+pub fn record(updates: &mut TrackerUpdates, block_number: u32, events: &Events<PolkadotConfig>) {
+    for event in events.iter().flatten() {
+        if event.pallet_name() != "ParaInclusion" {
+            continue;
+        }
+        let status = match event.variant_name() {
+            "CandidateBacked" => "backed",
+            "CandidateIncluded" => "included",
+            _ => continue,
+        };
+        let Ok(fields) = event.field_values() else {
+            continue;
+        };
+
+        // (CandidateReceipt, HeadData, CoreIndex, GroupIndex)
+        let descriptor = fields.at(0).and_then(|receipt| receipt.at("descriptor"));
+        let (Some(para_id), Some(para_block_hash), Some(relay_parent)) = (
+            number(descriptor.and_then(|d| d.at("para_id"))),
+            hash(descriptor.and_then(|d| d.at("para_head"))),
+            hash(descriptor.and_then(|d| d.at("relay_parent"))),
+        ) else {
+            continue;
+        };
+        let para_block_number = fields.at(1).and_then(decode::bytes).and_then(|head| head_number(&head));
+
+        let inclusion = ParaInclusion {
+            relay_block: block_number,
+            para_id,
+            status: status.to_string(),
+            para_block_hash,
+            para_block_number,
+            relay_parent,
+        };
+        updates.push("Failed to record candidate inclusion", move |db| db.record_para_inclusion(&inclusion));
+    }
+}
+
+#[derive(Deserialize)]
+pub struct InclusionsQuery {
+    limit: Option<u32>,
+}
+
+/// The parachain's latest backed and included blocks, newest relay block first.
+pub async fn get_inclusions(
+    State(state): State<AppState>,
+    Path(para_id): Path<u32>,
+    Query(query): Query<InclusionsQuery>,
+) -> Result<Json<Vec<ParaInclusion>>, Error> {
+    let limit = query.limit.unwrap_or(100).min(MAX_INCLUSIONS);
+    Ok(Json(state.db.blocking(move |db| db.get_para_inclusions(para_id, limit)).await?))
+}
+
+/// The relay chain blocks that backed and included a parachain block.
+pub async fn get_relay_block(
+    State(state): State<AppState>,
+    Path(number): Path<u32>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let Some(relay) = &state.links.relay else {
+        return Err(Error::NotFound(
+            "Not a parachain indexed with its relay chain (or its spec has no para_id)".to_string(),
+        ));
+    };
+    let hash = state
+        .db
+        .blocking(move |db| db.get_block_hash(number))
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("Block #{} not found", number)))?;
+    let (para_id, block_hash) = (relay.para_id, hash.clone());
+    let inclusions = relay.db.blocking(move |db| db.get_para_inclusions_of(para_id, &block_hash)).await?;
+    if inclusions.is_empty() {
+        return Err(Error::NotFound(format!(
+            "No inclusion of block #{} recorded on {} (is track_inclusions on?)",
+            number, relay.chain
+        )));
+    }
+
+    let relay_block = |status| inclusions.iter().find(|i| i.status == status).map(|i| i.relay_block);
+    Ok(Json(json!({
+        "number": number,
+        "hash": hash,
+        "para_id": relay.para_id,
+        "relay_chain": relay.chain,
+        "relay_parent": inclusions[0].relay_parent,
+        "backed_in": relay_block("backed"),
+        "included_in": relay_block("included"),
+    })))
+}
+
+/// The parachain blocks a relay chain block backed and included, with the name of their chain
+/// where it's indexed too.
+pub async fn get_parachain_blocks(
+    State(state): State<AppState>,
+    Path(number): Path<u32>,
+) -> Result<Json<Vec<serde_json::Value>>, Error> {
+    let inclusions = state.db.blocking(move |db| db.get_para_inclusions_in(number)).await?;
+    Ok(Json(
+        inclusions
+            .into_iter()
+            .map(|inclusion| {
+                let chain = state.links.parachains.iter().find(|(id, _)| *id == inclusion.para_id);
+                let mut inclusion = json!(inclusion);
+                inclusion["chain"] = json!(chain.map(|(_, name)| name));
+                inclusion
+            })
+            .collect(),
+    ))
+}
@@ -0,0 +1,59 @@
+//! Named filter sets for `--preset`, for a small database without learning pallet names.
+//! They're written for Polkadot-like relay chains, and replace any `[filters]` section.
+
+use clap::ValueEnum;
+
+use crate::config::Filters;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Preset {
+    /// Everything but the per-block relay chain bookkeeping (inherents, inclusions, fee events)
+    RelayMinimal,
+    /// Only balance transfers and failures, from direct `Balances` calls
+    TransfersOnly,
+    /// Only OpenGov: referenda, votes, the treasury and the fellowship
+    Governance,
+    /// No filters at all, not even the compiled-in defaults
+    Everything,
+}
+
+const GOVERNANCE_PALLETS: &[&str] = &[
+    "Referenda",
+    "ConvictionVoting",
+    "Whitelist",
+    "Preimage",
+    "Treasury",
+    "FellowshipReferenda",
+    "FellowshipCollective",
+];
+
+fn strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+impl Preset {
+    pub fn filters(self) -> Filters {
+        match self {
+            Preset::RelayMinimal => Filters {
+                exclude_events: strings(&[
+                    "ParaInclusion",
+                    "System/ExtrinsicSuccess",
+                    "TransactionPayment/TransactionFeePaid",
+                ]),
+                exclude_extrinsics: strings(&["ParaInherent/enter", "Timestamp/set"]),
+                ..Default::default()
+            },
+            Preset::TransfersOnly => Filters {
+                only_events: strings(&["Balances/Transfer", "Balances/Endowed", "System/ExtrinsicFailed"]),
+                only_extrinsics: strings(&["Balances"]),
+                ..Default::default()
+            },
+            Preset::Governance => Filters {
+                only_events: strings(GOVERNANCE_PALLETS),
+                only_extrinsics: strings(GOVERNANCE_PALLETS),
+                ..Default::default()
+            },
+            Preset::Everything => Filters::default(),
+        }
+    }
+}
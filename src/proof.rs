@@ -0,0 +1,99 @@
+//! Storage values with their Merkle proof, on `/proof/:pallet/:entry`, so thin clients can
+//! check a value against the block's state root instead of trusting smolcar.
+//!
+//! The proof comes from `state_getReadProof`, which the light client forwards to full nodes.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use subxt::backend::legacy::LegacyRpcMethods;
+use subxt::dynamic::{self, Value};
+use subxt::utils::{AccountId32, H256};
+use subxt::PolkadotConfig;
+
+use crate::api::AppState;
+use crate::error::Error;
+
+#[derive(Deserialize)]
+pub struct ProofQuery {
+    /// The entry's keys, comma-separated: SS58 addresses, 0x-prefixed bytes or numbers
+    key: Option<String>,
+    /// A block number or hash, the latest finalized block by default
+    at: Option<String>,
+}
+
+fn parse_key(key: &str) -> Option<Value> {
+    if let Some(bytes) = key.strip_prefix("0x") {
+        return hex::decode(bytes).ok().map(Value::from_bytes);
+    }
+    if let Ok(number) = key.parse::<u128>() {
+        return Some(Value::u128(number));
+    }
+    key.parse::<AccountId32>().ok().map(|account| Value::from_bytes(account.0))
+}
+
+// A stored block number, or any block hash
+async fn resolve_at(state: &AppState, at: &str) -> Result<H256, Error> {
+    if let Some(hash) = at.strip_prefix("0x") {
+        let bytes = hex::decode(hash).ok().filter(|bytes| bytes.len() == 32);
+        return bytes
+            .map(|bytes| H256::from_slice(&bytes))
+            .ok_or_else(|| Error::BadRequest(format!("Invalid block hash {:?}", at)));
+    }
+    let number: u32 = at.parse().map_err(|_| Error::BadRequest(format!("Invalid block {:?}", at)))?;
+    let hash = state
+        .db
+        .blocking(move |db| db.get_block_hash(number))
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("Block #{} isn't stored", number)))?;
+    hash.parse().map_err(|_| Error::Other(format!("Stored hash {:?} is invalid", hash)))
+}
+
+/// The entry's value at a block, with the proof and state root to check it against.
+pub async fn get_proof(
+    State(state): State<AppState>,
+    Path((pallet, entry)): Path<(String, String)>,
+    Query(query): Query<ProofQuery>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let mut keys = Vec::new();
+    for key in query.key.iter().flat_map(|keys| keys.split(',')).filter(|key| !key.is_empty()) {
+        keys.push(parse_key(key.trim()).ok_or_else(|| Error::BadRequest(format!("Invalid key {:?}", key)))?);
+    }
+    let address = dynamic::storage(pallet.as_str(), entry.as_str(), keys);
+    let storage_key = state
+        .api
+        .storage()
+        .address_bytes(&address)
+        .map_err(|e| Error::BadRequest(format!("Invalid storage entry: {}", e)))?;
+
+    let block = match &query.at {
+        Some(at) => state.api.blocks().at(resolve_at(&state, at).await?).await,
+        None => state.api.blocks().at_latest().await,
+    }
+    .map_err(|e| Error::Upstream(format!("Failed to fetch the block: {}", e)))?;
+
+    let value = block
+        .storage()
+        .fetch(&address)
+        .await
+        .map_err(|e| Error::Upstream(format!("Failed to query chain state: {}", e)))?;
+    let rpc = LegacyRpcMethods::<PolkadotConfig>::new(state.rpc.clone());
+    let proof = rpc
+        .state_get_read_proof([storage_key.as_slice()], Some(block.hash()))
+        .await
+        .map_err(|e| Error::Upstream(format!("Failed to fetch the read proof: {}", e)))?;
+
+    let decoded = value.as_ref().and_then(|value| value.to_value().ok()).map(|value| value.to_string());
+    Ok(Json(json!({
+        "block_number": block.number(),
+        "block_hash": format!("{:?}", block.hash()),
+        "state_root": format!("{:?}", block.header().state_root),
+        "key": format!("0x{}", hex::encode(&storage_key)),
+        "value": value.map(|value| format!("0x{}", hex::encode(value.encoded()))),
+        "decoded": decoded,
+        "proof": proof.proof.iter().map(|node| format!("0x{}", hex::encode(&node.0))).collect::<Vec<_>>(),
+    })))
+}
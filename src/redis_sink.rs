@@ -0,0 +1,116 @@
+//! Publishes stored blocks to Redis and keeps the most recent ones cached there, so
+//! several web frontends can share one hot cache instead of each hitting smolcar.
+//!
+//! Keys, with the default `smolcar` prefix:
+//! - `smolcar:<chain>:blocks` pub/sub channel, gets every stored block as JSON
+//! - `smolcar:<chain>:block:<number>` the block's JSON, for the last `cache_blocks` blocks
+//! - `smolcar:<chain>:head` the latest stored block number
+
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+
+use crate::config::RedisConfig;
+use crate::indexer::ExtrinsicInfo;
+use crate::sinks::{BlockSummary, Sink};
+
+// Blocks waiting to be written before we start dropping them
+const QUEUE_SIZE: usize = 64;
+
+struct Message {
+    chain: String,
+    number: u32,
+    json: String,
+}
+
+pub struct RedisSink {
+    tx: mpsc::Sender<Message>,
+    // Extrinsics of blocks still being processed, keyed by (chain, block number)
+    pending: Mutex<HashMap<(String, u32), Vec<serde_json::Value>>>,
+}
+
+impl RedisSink {
+    pub async fn connect(config: &RedisConfig) -> Result<Self, String> {
+        let client = redis::Client::open(config.url.as_str())
+            .map_err(|e| format!("invalid Redis url: {}", e))?;
+        let conn = client
+            .get_connection_manager()
+            .await
+            .map_err(|e| format!("failed to connect to Redis: {}", e))?;
+        tracing::info!("Connected to Redis");
+
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(writer(conn, rx, config.key_prefix.clone(), config.cache_blocks));
+
+        Ok(RedisSink {
+            tx,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+impl Sink for RedisSink {
+    fn on_extrinsic(&self, chain: &str, block_number: u32, extrinsic: &ExtrinsicInfo) {
+        self.pending
+            .lock()
+            .unwrap()
+            .entry((chain.to_string(), block_number))
+            .or_default()
+            .push(serde_json::to_value(extrinsic).unwrap());
+    }
+
+    fn on_block(&self, block: &BlockSummary) {
+        let extrinsics = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&(block.chain.clone(), block.number))
+            .unwrap_or_default();
+
+        // Same shape the API serves for /block/:number
+        let json = serde_json::json!({
+            "number": block.number,
+            "hash": block.hash,
+            "extrinsics": extrinsics,
+            "timestamp": block.timestamp,
+        })
+        .to_string();
+
+        let message = Message {
+            chain: block.chain.clone(),
+            number: block.number,
+            json,
+        };
+        if self.tx.try_send(message).is_err() {
+            tracing::warn!(chain = %block.chain, block = block.number, "Redis is falling behind, dropping block");
+        }
+    }
+}
+
+async fn writer(
+    mut conn: ConnectionManager,
+    mut rx: mpsc::Receiver<Message>,
+    prefix: String,
+    cache_blocks: u32,
+) {
+    while let Some(message) = rx.recv().await {
+        let base = format!("{}:{}", prefix, message.chain);
+
+        let mut pipe = redis::pipe();
+        pipe.publish(format!("{}:blocks", base), &message.json).ignore();
+        if cache_blocks > 0 {
+            pipe.set(format!("{}:block:{}", base, message.number), &message.json)
+                .ignore()
+                .set(format!("{}:head", base), message.number)
+                .ignore();
+            if let Some(evicted) = message.number.checked_sub(cache_blocks) {
+                pipe.del(format!("{}:block:{}", base, evicted)).ignore();
+            }
+        }
+
+        if let Err(e) = pipe.query_async::<()>(&mut conn).await {
+            tracing::warn!(chain = %message.chain, block = message.number, "Failed to write block to Redis: {}", e);
+        }
+    }
+}
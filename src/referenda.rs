@@ -0,0 +1,202 @@
+//! Referenda with their proposals decoded, kept in the `referenda` table and served on
+//! `/referenda`.
+//!
+//! Proposals are usually a preimage hash. The preimage is read from the Preimage pallet's
+//! storage when the referendum is submitted or, if it wasn't noted yet, once it is.
+//! Noted preimages are decoded even if no referendum turns out to be waiting for them.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use subxt::dynamic;
+use subxt::events::Events;
+use subxt::ext::codec::Decode;
+use subxt::ext::scale_value::{At, ValueDef};
+use subxt::utils::H256;
+use subxt::{OnlineClient, PolkadotConfig};
+
+use crate::api::AppState;
+use crate::db;
+use crate::error::Error;
+use crate::decode;
+use crate::indexer::TrackerUpdates;
+use crate::reporting;
+
+const MAX_REFERENDA: u32 = 100;
+
+const FAILURE: &str = "Failed to record referendum";
+
+async fn fetch_preimage(
+    api: &OnlineClient<PolkadotConfig>,
+    at: H256,
+    hash: &[u8],
+    len: u32,
+) -> Result<Option<Vec<u8>>, String> {
+    let key = dynamic::Value::unnamed_composite([dynamic::Value::from_bytes(hash), dynamic::Value::u128(len.into())]);
+    let stored = api
+        .storage()
+        .at(at)
+        .fetch(&dynamic::storage("Preimage", "PreimageFor", vec![key]))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    stored
+        .map(|preimage| Vec::<u8>::decode(&mut preimage.encoded()).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+// The decoded proposal as stored JSON, None if the preimage isn't there (yet)
+async fn resolve(
+    api: &OnlineClient<PolkadotConfig>,
+    decode: &decode::Context,
+    at: H256,
+    hash: &[u8],
+    len: u32,
+) -> Result<Option<String>, String> {
+    match fetch_preimage(api, at, hash, len).await? {
+        Some(preimage) => {
+            let call = decode.call(&preimage)?;
+            Ok(Some(serde_json::to_string(&call).unwrap()))
+        }
+        None => Ok(None),
+    }
+}
+
+// How long a noted preimage is, which its storage key needs. Newer runtimes keep it in
+// `RequestStatusFor`, older ones in `StatusFor`, and requested ones only have `maybe_len`
+async fn preimage_len(api: &OnlineClient<PolkadotConfig>, at: H256, hash: &[u8]) -> Result<Option<u32>, String> {
+    let metadata = api.metadata();
+    let Some(storage) = metadata.pallet_by_name("Preimage").and_then(|pallet| pallet.storage()) else {
+        return Ok(None);
+    };
+    let Some(entry) = ["RequestStatusFor", "StatusFor"].into_iter().find(|name| storage.entry_by_name(name).is_some())
+    else {
+        return Ok(None);
+    };
+    let status = api
+        .storage()
+        .at(at)
+        .fetch(&dynamic::storage("Preimage", entry, vec![dynamic::Value::from_bytes(hash)]))
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(status) = status.map(|status| status.to_value()).transpose().map_err(|e| e.to_string())? else {
+        return Ok(None);
+    };
+    let ValueDef::Variant(status) = &status.value else {
+        return Ok(None);
+    };
+    let len = status.values.at("len").or_else(|| status.values.at("maybe_len"));
+    let len = len.and_then(|len| match &len.value {
+        ValueDef::Variant(option) => option.values.at(0)?.as_u128(),
+        _ => len.as_u128(),
+    });
+    Ok(len.map(|n| n as u32))
+}
+
+/// Records the block's `Referenda/Submitted` events, and fills in proposals whose preimage
+/// got noted in it.
+pub async fn record(
+    updates: &mut TrackerUpdates,
+    chain: &str,
+    api: &OnlineClient<PolkadotConfig>,
+    decode: &decode::Context,
+    block_number: u32,
+    block_hash: H256,
+    events: &Events<PolkadotConfig>,
+) {
+    for event in events.iter().flatten() {
+        match (event.pallet_name(), event.variant_name()) {
+            ("Referenda", "Submitted") => {
+                let Ok(fields) = event.field_values() else {
+                    continue;
+                };
+                let number = |name| fields.at(name).and_then(|v| v.as_u128()).map(|n| n as u32);
+                let (Some(index), Some(track), Some(proposal)) =
+                    (number("index"), number("track"), fields.at("proposal"))
+                else {
+                    continue;
+                };
+                let ValueDef::Variant(bounded) = &proposal.value else {
+                    continue;
+                };
+
+                // Bounded<Call>: Inline(bytes), Lookup { hash, len } or Legacy { hash }
+                let hash = bounded.values.at("hash").and_then(decode::bytes);
+                let len = bounded.values.at("len").and_then(|v| v.as_u128()).map(|n| n as u32);
+                let proposal = match (bounded.name.as_str(), &hash, len) {
+                    ("Inline", _, _) => match bounded.values.at(0).and_then(decode::bytes) {
+                        Some(bytes) => decode.call(&bytes).map(|call| Some(serde_json::to_string(&call).unwrap())),
+                        None => Ok(None),
+                    },
+                    ("Lookup", Some(hash), Some(len)) => resolve(api, decode, block_hash, hash, len).await,
+                    // Legacy preimages don't say how long they are, which the storage key needs
+                    _ => Ok(None),
+                };
+                let proposal = proposal.unwrap_or_else(|e| {
+                    reporting::block_error(chain, block_number, &format!("Failed to resolve referendum #{} proposal: {}", index, e));
+                    None
+                });
+
+                let hash = hash.map(|hash| format!("0x{}", hex::encode(hash)));
+                updates.push(FAILURE, move |db| {
+                    db.record_referendum(index, track, block_number, hash.as_deref(), len, proposal.as_deref())
+                });
+            }
+            // Resolved here whether or not a referendum waits for it, since referenda submitted
+            // in blocks still being processed aren't stored yet
+            ("Preimage", "Noted") => {
+                let Some(hash) = event.field_values().ok().and_then(|f| f.at("hash").and_then(decode::bytes)) else {
+                    continue;
+                };
+                let resolved = match preimage_len(api, block_hash, &hash).await {
+                    Ok(Some(len)) => resolve(api, decode, block_hash, &hash, len).await.map(|p| p.zip(Some(len))),
+                    Ok(None) => Ok(None),
+                    Err(e) => Err(e),
+                };
+                let hash = format!("0x{}", hex::encode(&hash));
+                let (proposal, len) = match resolved {
+                    Ok(Some(resolved)) => resolved,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        reporting::block_error(chain, block_number, &format!("Failed to resolve preimage {}: {}", hash, e));
+                        continue;
+                    }
+                };
+                updates.push(FAILURE, move |db| {
+                    for (index, awaiting) in db.referenda_awaiting_preimage(&hash)? {
+                        if awaiting == len {
+                            db.set_referendum_proposal(index, &proposal)?;
+                        }
+                    }
+                    Ok(())
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+    limit: Option<u32>,
+}
+
+/// The latest referenda, newest first.
+pub async fn get_referenda(
+    State(state): State<AppState>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Vec<db::Referendum>>, Error> {
+    let limit = query.limit.unwrap_or(20).min(MAX_REFERENDA);
+    Ok(Json(state.db.blocking(move |db| db.get_referenda(limit)).await?))
+}
+
+pub async fn get_referendum(State(state): State<AppState>, Path(index): Path<u32>) -> Result<Json<db::Referendum>, Error> {
+    state
+        .db
+        .blocking(move |db| db.get_referendum(index))
+        .await?
+        .map(Json)
+        .ok_or_else(|| Error::NotFound(format!("Referendum #{} not found", index)))
+}
@@ -0,0 +1,131 @@
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::db::{migrate, SchemaError};
+
+pub const REGISTRY_FILE: &str = "smolcar.db";
+
+// Before per-chain files existed everything went into this one, and it was always Polkadot
+const LEGACY_DB_FILE: &str = "blocks.db";
+const LEGACY_CHAIN: &str = "polkadot";
+
+const MIGRATIONS: &[(&str, &str)] = &[(
+    "create chains table",
+    "CREATE TABLE IF NOT EXISTS chains (
+        name TEXT PRIMARY KEY,
+        db_file TEXT NOT NULL UNIQUE,
+        genesis_hash TEXT,
+        created_at INTEGER NOT NULL
+    );",
+)];
+
+#[derive(Clone, Debug)]
+pub struct RegisteredChain {
+    pub name: String,
+    pub db_path: PathBuf,
+    pub genesis_hash: Option<String>,
+}
+
+/// Keeps track of which chain lives in which database file inside the data directory.
+pub struct Registry {
+    conn: Mutex<Connection>,
+    data_dir: PathBuf,
+}
+
+impl Registry {
+    pub fn open<P: AsRef<Path>>(data_dir: P) -> Result<Self, SchemaError> {
+        let data_dir = data_dir.as_ref().to_path_buf();
+        let mut conn = Connection::open(data_dir.join(REGISTRY_FILE))?;
+        migrate(&mut conn, MIGRATIONS)?;
+
+        Ok(Registry {
+            conn: Mutex::new(conn),
+            data_dir,
+        })
+    }
+
+    /// For `--read-only`: nothing is migrated or registered, chains have to be indexed already.
+    pub fn open_read_only<P: AsRef<Path>>(data_dir: P) -> Result<Self, rusqlite::Error> {
+        let data_dir = data_dir.as_ref().to_path_buf();
+        let conn = Connection::open_with_flags(
+            data_dir.join(REGISTRY_FILE),
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+
+        Ok(Registry {
+            conn: Mutex::new(conn),
+            data_dir,
+        })
+    }
+
+    /// Returns the chain's entry, registering it with a fresh `<name>.db` file if it's new.
+    pub fn register(&self, name: &str) -> Result<RegisteredChain, rusqlite::Error> {
+        if let Some(chain) = self.get(name)? {
+            return Ok(chain);
+        }
+
+        let conn = self.conn.lock().unwrap();
+
+        let legacy_taken: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM chains WHERE db_file = ?1)",
+            params![LEGACY_DB_FILE],
+            |row| row.get(0),
+        )?;
+        let db_file = if name == LEGACY_CHAIN
+            && !legacy_taken
+            && self.data_dir.join(LEGACY_DB_FILE).exists()
+        {
+            tracing::info!(chain = %name, "Adopting existing {} as the chain's database", LEGACY_DB_FILE);
+            LEGACY_DB_FILE.to_string()
+        } else {
+            format!("{}.db", name)
+        };
+
+        conn.execute(
+            "INSERT INTO chains (name, db_file, created_at) VALUES (?1, ?2, ?3)",
+            params![name, db_file, chrono::Utc::now().timestamp()],
+        )?;
+
+        Ok(RegisteredChain {
+            name: name.to_string(),
+            db_path: self.data_dir.join(db_file),
+            genesis_hash: None,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<RegisteredChain>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT name, db_file, genesis_hash FROM chains WHERE name = ?1",
+            params![name],
+            |row| self.chain_from_row(row),
+        )
+        .optional()
+    }
+
+    pub fn chains(&self) -> Result<Vec<RegisteredChain>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT name, db_file, genesis_hash FROM chains ORDER BY name")?;
+        let rows = stmt.query_map([], |row| self.chain_from_row(row))?;
+        rows.collect()
+    }
+
+    pub fn set_genesis_hash(&self, name: &str, genesis_hash: &str) -> Result<(), rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE chains SET genesis_hash = ?1 WHERE name = ?2",
+            params![genesis_hash, name],
+        )?;
+        Ok(())
+    }
+
+    fn chain_from_row(&self, row: &rusqlite::Row) -> Result<RegisteredChain, rusqlite::Error> {
+        let db_file: String = row.get(1)?;
+        Ok(RegisteredChain {
+            name: row.get(0)?,
+            db_path: self.data_dir.join(db_file),
+            genesis_hash: row.get(2)?,
+        })
+    }
+}
@@ -0,0 +1,94 @@
+//! Error reporting. Errors always go to the log and the last few are kept for `/errors`,
+//! and with the `sentry` feature and a DSN configured they (and panics, e.g. in the spawned
+//! indexer tasks) are also sent to Sentry.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+const RECENT_ERRORS: usize = 50;
+
+static RECENT: Mutex<VecDeque<ReportedError>> = Mutex::new(VecDeque::new());
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReportedError {
+    pub chain: String,
+    pub block_number: Option<u32>,
+    pub message: String,
+    pub timestamp: i64,
+}
+
+#[cfg(feature = "sentry")]
+pub struct Guard {
+    _sentry: Option<sentry::ClientInitGuard>,
+}
+
+#[cfg(not(feature = "sentry"))]
+pub struct Guard;
+
+/// Sets up Sentry if a DSN is given. Keep the guard alive until exit so events get flushed.
+#[cfg(feature = "sentry")]
+pub fn init(dsn: Option<&str>) -> Guard {
+    let Some(dsn) = dsn else {
+        return Guard { _sentry: None };
+    };
+
+    let mut options = sentry::ClientOptions::default();
+    options.release = sentry::release_name!();
+    let guard = sentry::init((dsn, options));
+    tracing::info!("Sentry error reporting enabled");
+    Guard { _sentry: Some(guard) }
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn init(dsn: Option<&str>) -> Guard {
+    if dsn.is_some() {
+        tracing::warn!("A Sentry DSN is configured but smolcar was built without the `sentry` feature, ignoring it");
+    }
+    Guard
+}
+
+/// Reports a failure while processing a block of `chain`.
+pub fn block_error(chain: &str, block_number: u32, message: &str) {
+    tracing::error!(chain, block = block_number, "{}", message);
+    remember(chain, Some(block_number), message);
+
+    #[cfg(feature = "sentry")]
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("chain", chain);
+            scope.set_extra("block_number", block_number.into());
+        },
+        || sentry::capture_message(message, sentry::Level::Error),
+    );
+}
+
+/// Reports a failure that isn't tied to a specific block.
+pub fn error(chain: &str, message: &str) {
+    tracing::error!(chain, "{}", message);
+    remember(chain, None, message);
+
+    #[cfg(feature = "sentry")]
+    sentry::with_scope(
+        |scope| scope.set_tag("chain", chain),
+        || sentry::capture_message(message, sentry::Level::Error),
+    );
+}
+
+/// The most recent errors, newest first.
+pub fn recent_errors() -> Vec<ReportedError> {
+    RECENT.lock().unwrap().iter().cloned().collect()
+}
+
+fn remember(chain: &str, block_number: Option<u32>, message: &str) {
+    let mut recent = RECENT.lock().unwrap();
+    if recent.len() == RECENT_ERRORS {
+        recent.pop_back();
+    }
+    recent.push_front(ReportedError {
+        chain: chain.to_string(),
+        block_number,
+        message: message.to_string(),
+        timestamp: chrono::Utc::now().timestamp(),
+    });
+}
@@ -0,0 +1,133 @@
+use chrono::Timelike;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::archive::Archive;
+use crate::config::{ByteSize, Config};
+use crate::db::Database;
+use crate::reporting;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const PRUNE_BATCH: u32 = 500;
+// Free pages handed back per step of a compaction, writes get the database in between
+const COMPACT_PAGES: u32 = 2000;
+
+/// Keeps a chain's database under `max_db_size` by pruning its oldest blocks, archiving
+/// them first if an archive is set up. Settings are re-read every round, so config
+/// reloads apply without a restart.
+pub async fn run(
+    chain: String,
+    db: Arc<Database>,
+    archive: Option<Arc<Archive>>,
+    config: watch::Receiver<Config>,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    let mut compacted_on = None;
+    loop {
+        interval.tick().await;
+        let (max_size, keep_recent, compact_hour) = {
+            let config = config.borrow();
+            (config.max_db_size, config.keep_recent_blocks, config.compact_hour)
+        };
+        if let Some(max_size) = max_size {
+            if let Err(e) = enforce(&chain, &db, archive.as_deref(), max_size, keep_recent).await {
+                reporting::error(&chain, &format!("Pruning failed: {}", e));
+            }
+        }
+
+        let now = chrono::Utc::now();
+        if compact_hour == Some(now.hour() as u8) && compacted_on != Some(now.date_naive()) {
+            compacted_on = Some(now.date_naive());
+            if let Err(e) = compact(&chain, db.clone()).await {
+                reporting::error(&chain, &format!("Compaction failed: {}", e));
+            }
+        }
+    }
+}
+
+/// Deletes every block below `before`, and what was recorded from them, `PRUNE_BATCH` blocks
+/// per transaction, logging progress as it goes. Returns how many blocks it deleted.
+pub async fn prune_before(chain: &str, db: Arc<Database>, before: u32) -> Result<u64, rusqlite::Error> {
+    let total = db.blocking(move |db| db.count_blocks_before(before)).await?;
+    tracing::info!(chain, before, blocks = total, "Deleting old blocks");
+
+    let mut deleted = 0;
+    loop {
+        let batch = db.blocking(move |db| db.delete_blocks_before(before, PRUNE_BATCH)).await? as u64;
+        if batch == 0 {
+            break;
+        }
+        deleted += batch;
+        tracing::info!(chain, deleted, total, "Prune progress");
+    }
+
+    tracing::info!(chain, deleted, before, "Deleted old blocks");
+    Ok(deleted)
+}
+
+/// Hands the database's free pages back to the OS and returns how many bytes that freed.
+/// Runs off the async runtime, in steps so blocks keep being stored, except for the one full
+/// VACUUM a database created before incremental vacuum needs the first time.
+pub async fn compact(chain: &str, db: Arc<Database>) -> Result<u64, rusqlite::Error> {
+    let before = db.blocking(|db| db.file_bytes()).await?;
+    let converted = db.blocking(|db| db.enable_incremental_vacuum()).await?;
+
+    let mut last_left = u64::MAX;
+    loop {
+        let left = db.blocking(|db| db.incremental_vacuum(COMPACT_PAGES)).await?;
+        // Pruning running alongside can free pages as fast as they're handed back
+        if left == 0 || left >= last_left {
+            break;
+        }
+        last_left = left;
+        tokio::task::yield_now().await;
+    }
+
+    let after = db.blocking(|db| db.file_bytes()).await?;
+    let freed = before.saturating_sub(after);
+    tracing::info!(chain, converted, freed = %ByteSize(freed), size = %ByteSize(after), "Compacted database");
+    Ok(freed)
+}
+
+async fn enforce(
+    chain: &str,
+    db: &Arc<Database>,
+    archive: Option<&Archive>,
+    max_size: ByteSize,
+    keep_recent: u32,
+) -> Result<(), String> {
+    let mut used = db.blocking(|db| db.used_bytes()).await.map_err(|e| e.to_string())?;
+    if used <= max_size.0 {
+        return Ok(());
+    }
+
+    let mut pruned = 0;
+    while used > max_size.0 {
+        let deleted = match archive {
+            Some(archive) => archive.prune(chain, db, PRUNE_BATCH, keep_recent).await?,
+            None => db
+                .blocking(move |db| db.prune_oldest(PRUNE_BATCH, keep_recent))
+                .await
+                .map_err(|e| e.to_string())?,
+        };
+        if deleted == 0 {
+            tracing::warn!(
+                chain,
+                size = %ByteSize(used),
+                limit = %max_size,
+                "Database is over its size limit but only the newest {} blocks are left, not pruning further",
+                keep_recent
+            );
+            break;
+        }
+        pruned += deleted;
+        used = db.blocking(|db| db.used_bytes()).await.map_err(|e| e.to_string())?;
+    }
+
+    if pruned > 0 {
+        tracing::info!(chain, pruned, archived = archive.is_some(), size = %ByteSize(used), limit = %max_size, "Pruned old blocks");
+    }
+
+    Ok(())
+}
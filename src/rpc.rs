@@ -0,0 +1,205 @@
+//! A read-only subset of Substrate's JSON-RPC, answered from the database, so tooling
+//! that speaks the node API can use smolcar as an archive of the blocks it has stored.
+//!
+//! Served over HTTP on `POST /rpc` (and `/<chain>/rpc`), single or batched requests.
+//! Extrinsics in `chain_getBlock` are SCALE-encoded hex like a node returns them, except for
+//! blocks stored before raw extrinsics were kept, which get smolcar's decoded ones instead.
+//! Either way, extrinsics the filters excluded are missing.
+//!
+//! With `rpc_passthrough` set, any other method is forwarded to the light client as is, for
+//! calls smolcar doesn't wrap. Subscriptions can't work over plain HTTP, so they aren't.
+
+use axum::{body::Bytes, extract::State, response::Json};
+use serde_json::{json, Value};
+use subxt::backend::rpc::RpcParams;
+
+use crate::api::AppState;
+use crate::db::{Database, StoredBlock};
+
+const METHODS: &[&str] = &[
+    "chain_getBlock",
+    "chain_getBlockHash",
+    "chain_getFinalizedHead",
+    "chain_getFinalisedHead",
+    "chain_getHeader",
+    "rpc_methods",
+];
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl Into<String>) -> Self {
+        RpcError { code: -32602, message: message.into() }
+    }
+
+    fn internal(e: rusqlite::Error) -> Self {
+        RpcError { code: -32603, message: format!("Database error: {}", e) }
+    }
+}
+
+pub async fn handle(State(state): State<AppState>, body: Bytes) -> Json<Value> {
+    let request: Value = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => return Json(error_response(Value::Null, -32700, &format!("Parse error: {}", e))),
+    };
+
+    match request {
+        Value::Array(batch) if !batch.is_empty() => {
+            let mut responses = Vec::with_capacity(batch.len());
+            for request in batch {
+                responses.push(call(&state, request).await);
+            }
+            Json(Value::Array(responses))
+        }
+        request => Json(call(&state, request).await),
+    }
+}
+
+async fn call(state: &AppState, request: Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return error_response(id, -32600, "Invalid request");
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+    if !METHODS.contains(&method) {
+        if state.rpc_passthrough && !is_subscription(method) {
+            return forward(state, id, method, params).await;
+        }
+        return error_response(id, -32601, &format!("Method not found: {}", method));
+    }
+    let method = method.to_string();
+    let result = state.db.blocking(move |db| answer(db, &method, &params)).await;
+
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => error_response(id, e.code, &e.message),
+    }
+}
+
+// One of `METHODS`, from the database
+fn answer(db: &Database, method: &str, params: &Value) -> Result<Value, RpcError> {
+    match method {
+        "chain_getBlockHash" => get_block_hash(db, params),
+        "chain_getHeader" => block_at(db, params).and_then(|b| b.map_or(Ok(Value::Null), header)),
+        "chain_getBlock" => block_at(db, params).and_then(|b| b.map_or(Ok(Value::Null), signed_block)),
+        "chain_getFinalizedHead" | "chain_getFinalisedHead" => latest_block(db).map(hash_or_null),
+        "rpc_methods" => Ok(json!({ "methods": METHODS })),
+        _ => Err(RpcError { code: -32601, message: format!("Method not found: {}", method) }),
+    }
+}
+
+fn is_subscription(method: &str) -> bool {
+    let method = method.to_lowercase();
+    method.contains("subscribe") || method.ends_with("_follow")
+}
+
+async fn forward(state: &AppState, id: Value, method: &str, params: Value) -> Value {
+    let params = match params {
+        Value::Null => vec![],
+        Value::Array(params) => params,
+        _ => return error_response(id, -32602, "Only positional params can be forwarded"),
+    };
+    let mut rpc_params = RpcParams::new();
+    for param in params {
+        if let Err(e) = rpc_params.push(param) {
+            return error_response(id, -32602, &format!("Invalid params: {}", e));
+        }
+    }
+
+    match state.rpc.request::<Value>(method, rpc_params).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => error_response(id, -32000, &e.to_string()),
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+// Positional params like every client sends them, or `null` for "not given"
+fn param(params: &Value, index: usize) -> Option<&Value> {
+    params.as_array()?.get(index).filter(|v| !v.is_null())
+}
+
+// Block numbers come as JSON numbers or hex strings
+fn block_number(value: &Value) -> Result<u32, RpcError> {
+    let number = match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s
+            .strip_prefix("0x")
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok()),
+        _ => None,
+    };
+    number
+        .and_then(|n| u32::try_from(n).ok())
+        .ok_or_else(|| RpcError::invalid_params(format!("invalid block number {}", value)))
+}
+
+fn latest_block(db: &Database) -> Result<Option<StoredBlock>, RpcError> {
+    match db.get_latest_block_number().map_err(RpcError::internal)? {
+        Some(number) => db.get_block(number).map_err(RpcError::internal),
+        None => Ok(None),
+    }
+}
+
+// The block whose hash is the first param, or the latest one
+fn block_at(db: &Database, params: &Value) -> Result<Option<StoredBlock>, RpcError> {
+    match param(params, 0) {
+        Some(Value::String(hash)) => db
+            .get_block_by_hash(&hash.to_ascii_lowercase())
+            .map_err(RpcError::internal),
+        Some(other) => Err(RpcError::invalid_params(format!("invalid block hash {}", other))),
+        None => latest_block(db),
+    }
+}
+
+fn hash_or_null(block: Option<StoredBlock>) -> Value {
+    block.map_or(Value::Null, |b| Value::from(b.hash))
+}
+
+fn get_block_hash(db: &Database, params: &Value) -> Result<Value, RpcError> {
+    let hash_of = |number: u32| db.get_block(number).map(hash_or_null).map_err(RpcError::internal);
+
+    match param(params, 0) {
+        // A list of numbers gets a list of hashes
+        Some(Value::Array(numbers)) => numbers
+            .iter()
+            .map(|n| block_number(n).and_then(hash_of))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::from),
+        Some(number) => block_number(number).and_then(hash_of),
+        None => latest_block(db).map(hash_or_null),
+    }
+}
+
+fn header(block: StoredBlock) -> Result<Value, RpcError> {
+    let Some(header) = block.header else {
+        return Err(RpcError {
+            code: -32000,
+            message: format!("Block #{} was stored without its header", block.number),
+        });
+    };
+    Ok(json!({
+        "parentHash": header.parent_hash,
+        "number": format!("0x{:x}", block.number),
+        "stateRoot": header.state_root,
+        "extrinsicsRoot": header.extrinsics_root,
+        "digest": { "logs": header.digest },
+    }))
+}
+
+fn signed_block(mut block: StoredBlock) -> Result<Value, RpcError> {
+    let mut extrinsics = std::mem::take(&mut block.extrinsics);
+    let raw: Option<Vec<Value>> = extrinsics.iter().map(|e| e.get("raw").cloned()).collect();
+    if let Some(raw) = raw {
+        extrinsics = raw;
+    }
+    Ok(json!({
+        "block": { "header": header(block)?, "extrinsics": extrinsics },
+        "justifications": null,
+    }))
+}
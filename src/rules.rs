@@ -0,0 +1,44 @@
+//! Matching events against user-defined rules. Shared by everything that reacts to
+//! specific events (alerts, webhooks...).
+
+use serde::Deserialize;
+
+use crate::indexer::EventInfo;
+
+/// Which events a rule fires on. Unset fields match anything.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventRule {
+    /// Only events from this chain
+    pub chain: Option<String>,
+    pub pallet: Option<String>,
+    pub variant: Option<String>,
+    /// Substring of the decoded event data, e.g. an address or "index: 42"
+    pub data_contains: Option<String>,
+}
+
+impl EventRule {
+    /// Whether events of this kind could match, before looking at their data
+    pub fn matches_kind(&self, chain: &str, pallet: &str, variant: &str) -> bool {
+        self.chain.as_deref().is_none_or(|c| c == chain)
+            && self.pallet.as_deref().is_none_or(|p| p == pallet)
+            && self.variant.as_deref().is_none_or(|v| v == variant)
+    }
+
+    pub fn matches(&self, chain: &str, event: &EventInfo) -> bool {
+        self.matches_kind(chain, &event.pallet, &event.variant)
+            && self
+                .data_contains
+                .as_deref()
+                .is_none_or(|needle| event.data.contains(needle))
+    }
+}
+
+/// Where a matching event was found
+#[derive(Clone)]
+pub struct EventContext<'a> {
+    pub chain: &'a str,
+    pub block_number: u32,
+    pub extrinsic_index: u32,
+    pub event: &'a EventInfo,
+}
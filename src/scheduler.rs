@@ -0,0 +1,118 @@
+//! Follows tasks through the Scheduler pallet's events into the `scheduled_tasks` table, so
+//! `/scheduler/upcoming` can show what's queued to run in future blocks.
+//!
+//! Only tasks scheduled by an extrinsic come with their call (found in its call tree).
+//! Ones scheduled from runtime hooks, like referenda enactments, are listed without it.
+
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use subxt::events::{Events, Phase};
+use subxt::ext::scale_value::{At, Value, ValueDef};
+use subxt::PolkadotConfig;
+
+use crate::api::AppState;
+use crate::db;
+use crate::error::Error;
+use crate::decode::Call;
+use crate::indexer::TrackerUpdates;
+
+const MAX_UPCOMING: u32 = 500;
+
+const FAILURE: &str = "Failed to record scheduler event";
+
+/// The calls an extrinsic asks the Scheduler to run, in order, out of its call tree.
+pub fn scheduled_calls(action: &str, calls: &[Call]) -> VecDeque<Call> {
+    fn collect(pallet: &str, name: &str, calls: &[Call], found: &mut VecDeque<Call>) {
+        // schedule, schedule_named, schedule_after, schedule_named_after
+        if pallet == "Scheduler" && name.starts_with("schedule") {
+            found.extend(calls.first().cloned());
+            return;
+        }
+        for call in calls {
+            collect(&call.pallet, &call.call, &call.calls, found);
+        }
+    }
+
+    let mut found = VecDeque::new();
+    let (pallet, name) = action.split_once('/').unwrap_or((action, ""));
+    collect(pallet, name, calls, &mut found);
+    found
+}
+
+fn number(value: Option<&Value<u32>>) -> Option<u32> {
+    value?.as_u128().and_then(|n| u32::try_from(n).ok())
+}
+
+/// Records the block's Scheduler events. `scheduled` holds each extrinsic's
+/// `scheduled_calls`, matched to its `Scheduled` events in order.
+pub fn record(
+    updates: &mut TrackerUpdates,
+    block_number: u32,
+    events: &Events<PolkadotConfig>,
+    mut scheduled: HashMap<u32, VecDeque<Call>>,
+) {
+    for event in events.iter().flatten() {
+        if event.pallet_name() != "Scheduler" {
+            continue;
+        }
+        let Ok(fields) = event.field_values() else {
+            continue;
+        };
+
+        match event.variant_name() {
+            "Scheduled" => {
+                let (Some(when), Some(index)) = (number(fields.at("when")), number(fields.at("index"))) else {
+                    continue;
+                };
+                let call = match event.phase() {
+                    Phase::ApplyExtrinsic(i) => scheduled.get_mut(&i).and_then(VecDeque::pop_front),
+                    _ => None,
+                };
+                let call = call.map(|call| serde_json::to_string(&call).unwrap());
+                updates.push(FAILURE, move |db| db.record_scheduled_task(when, index, block_number, call.as_deref()));
+            }
+            variant @ ("Dispatched" | "Canceled") => {
+                let task = fields.at("task");
+                // Canceled has `when` and `index`, Dispatched a `task` tuple of them
+                let (when, index) = match task {
+                    Some(task) => (number(task.at(0)), number(task.at(1))),
+                    None => (number(fields.at("when")), number(fields.at("index"))),
+                };
+                let (Some(when), Some(index)) = (when, index) else {
+                    continue;
+                };
+                let status = match (variant, fields.at("result").map(|r| &r.value)) {
+                    ("Canceled", _) => "canceled",
+                    (_, Some(ValueDef::Variant(result))) if result.name == "Err" => "failed",
+                    _ => "dispatched",
+                };
+                updates.push(FAILURE, move |db| db.set_scheduled_task_status(when, index, status, block_number));
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpcomingQuery {
+    limit: Option<u32>,
+}
+
+/// Tasks still scheduled for blocks after the latest stored one, soonest first.
+pub async fn get_upcoming(
+    State(state): State<AppState>,
+    Query(query): Query<UpcomingQuery>,
+) -> Result<Json<Vec<db::ScheduledTask>>, Error> {
+    let limit = query.limit.unwrap_or(100).min(MAX_UPCOMING);
+    let tasks = state
+        .db
+        .blocking(move |db| {
+            db.get_latest_block_number().and_then(|latest| db.get_upcoming_tasks(latest.unwrap_or(0), limit))
+        })
+        .await?;
+    Ok(Json(tasks))
+}
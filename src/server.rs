@@ -0,0 +1,214 @@
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::service::TowerToHyperService;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+pub const DEFAULT_PORT: u16 = 8080;
+
+// Connections that haven't finished their TLS handshake by then are dropped
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An address to serve the API on: "0.0.0.0:8080", "[::]:8080", "127.0.0.1", just "8080",
+/// or a Unix socket as "unix:/run/smolcar.sock"
+#[derive(Clone, Debug)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err("unix: needs a socket path, e.g. unix:/run/smolcar.sock".to_string());
+            }
+            return Ok(ListenAddr::Unix(PathBuf::from(path)));
+        }
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(ListenAddr::Tcp(addr));
+        }
+        if let Ok(port) = s.parse::<u16>() {
+            return Ok(ListenAddr::Tcp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))));
+        }
+        // A bare host, with or without IPv6 brackets
+        let host = s.trim_start_matches('[').trim_end_matches(']');
+        host.parse()
+            .map(|ip| ListenAddr::Tcp(SocketAddr::new(ip, DEFAULT_PORT)))
+            .map_err(|_| format!("invalid listen address {:?}", s))
+    }
+}
+
+impl std::fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+fn bind(addr: SocketAddr, v6_only: bool) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+fn bind_unix(path: &Path) -> std::io::Result<UnixListener> {
+    // A socket left behind by a previous run would make bind fail
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if meta.file_type().is_socket() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    UnixListener::bind(path)
+}
+
+/// Loads a PEM certificate chain and private key for serving HTTPS.
+pub fn load_tls(cert: &Path, key: &Path) -> Result<TlsAcceptor, String> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("failed to read TLS certificate {}: {}", cert.display(), e))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("failed to read TLS key {}: {}", key.display(), e))?;
+
+    let mut config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| format!("invalid TLS configuration: {}", e))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn spawn_connection<IO>(io: IO, app: Router)
+where
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = TowerToHyperService::new(app);
+    tokio::spawn(async move {
+        if let Err(e) = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
+            .serve_connection_with_upgrades(TokioIo::new(io), service)
+            .await
+        {
+            tracing::debug!("Connection error: {}", e);
+        }
+    });
+}
+
+// Failing to accept is usually transient (a connection reset before it was accepted, or out of
+// file descriptors for now), so like axum::serve it's logged and accepting goes on, after a
+// pause unless only that one connection failed
+async fn accept_failed(e: std::io::Error) {
+    use std::io::ErrorKind;
+    if matches!(e.kind(), ErrorKind::ConnectionRefused | ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset) {
+        return;
+    }
+    tracing::error!("Failed to accept a connection: {}", e);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
+
+// axum::serve only takes a plain TcpListener, so Unix sockets and TLS are driven through hyper directly
+async fn serve_unix(listener: UnixListener, app: Router) -> std::io::Result<()> {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => spawn_connection(stream, app.clone()),
+            Err(e) => accept_failed(e).await,
+        }
+    }
+}
+
+async fn serve_tls(listener: TcpListener, acceptor: TlsAcceptor, app: Router) -> std::io::Result<()> {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                accept_failed(e).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        // Handshake off the accept loop so a slow client can't hold up everyone else
+        tokio::spawn(async move {
+            match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => spawn_connection(stream, app),
+                Ok(Err(e)) => tracing::debug!(%peer, "TLS handshake failed: {}", e),
+                Err(_) => tracing::debug!(%peer, "TLS handshake timed out"),
+            }
+        });
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// Binds every address up front, so callers know the API is reachable before serving.
+pub fn bind_all(addrs: &[ListenAddr]) -> std::io::Result<Vec<Listener>> {
+    // On Linux "[::]" also grabs IPv4 by default, which clashes with an explicit IPv4 address
+    let v6_only = addrs
+        .iter()
+        .any(|a| matches!(a, ListenAddr::Tcp(addr) if addr.is_ipv4()));
+
+    addrs
+        .iter()
+        .map(|addr| match addr {
+            ListenAddr::Tcp(addr) => bind(*addr, v6_only).map(Listener::Tcp),
+            ListenAddr::Unix(path) => bind_unix(path).map(Listener::Unix),
+        })
+        .collect()
+}
+
+/// Serves `app` on every listener until one of them fails.
+/// With `tls` set, TCP listeners serve HTTPS. Unix sockets are always plain HTTP.
+pub async fn serve(
+    app: Router,
+    listeners: Vec<Listener>,
+    tls: Option<TlsAcceptor>,
+) -> std::io::Result<()> {
+    let mut servers = tokio::task::JoinSet::new();
+    for listener in listeners {
+        let app = app.clone();
+        match listener {
+            Listener::Tcp(listener) => match &tls {
+                Some(acceptor) => {
+                    servers.spawn(serve_tls(listener, acceptor.clone(), app));
+                }
+                None => {
+                    servers.spawn(async move { axum::serve(listener, app).await });
+                }
+            },
+            Listener::Unix(listener) => {
+                servers.spawn(serve_unix(listener, app));
+            }
+        }
+    }
+
+    while let Some(result) = servers.join_next().await {
+        result.map_err(std::io::Error::other)??;
+    }
+
+    Ok(())
+}
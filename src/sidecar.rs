@@ -0,0 +1,278 @@
+//! Substrate API Sidecar compatible routes under `/sidecar` (and `/<chain>/sidecar`), so apps
+//! built against Sidecar can point their base URL at smolcar instead.
+//!
+//! Blocks come from the database. Extrinsic `args` and event `data` are smolcar's decoded
+//! text rather than Sidecar's structured JSON, and fields smolcar doesn't store (signature,
+//! fee info...) are null. Balances are read from chain state through the light
+//! client, at the latest finalized block.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use serde_json::{json, Value};
+use subxt::config::substrate::DigestItem;
+use subxt::dynamic::{self, At};
+use subxt::ext::codec::Decode;
+use subxt::ext::scale_value::{self, ValueDef};
+use subxt::utils::AccountId32;
+
+use crate::api::AppState;
+use crate::db::StoredBlock;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/blocks/head", get(get_head))
+        .route("/blocks/:id", get(get_block))
+        .route("/accounts/:address/balance-info", get(get_balance_info))
+}
+
+// Sidecar's error shape
+fn error(status: StatusCode, message: String) -> Response {
+    (
+        status,
+        Json(json!({ "code": status.as_u16(), "error": status.canonical_reason(), "message": message })),
+    )
+        .into_response()
+}
+
+fn block_response(block: Result<Option<StoredBlock>, rusqlite::Error>, id: &str) -> Response {
+    match block {
+        Ok(Some(block)) => Json(block_json(block)).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, format!("Block {} not found", id)),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)),
+    }
+}
+
+async fn get_head(State(state): State<AppState>) -> Response {
+    let block = state
+        .db
+        .blocking(|db| {
+            db.get_latest_block_number().and_then(|latest| match latest {
+                Some(number) => db.get_block(number),
+                None => Ok(None),
+            })
+        })
+        .await;
+    block_response(block, "head")
+}
+
+/// `id` is a block number or hash, like in Sidecar.
+async fn get_block(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    let block = if id.starts_with("0x") {
+        let hash = id.to_ascii_lowercase();
+        state.db.blocking(move |db| db.get_block_by_hash(&hash)).await
+    } else {
+        match id.parse::<u32>() {
+            Ok(number) => state.db.blocking(move |db| db.get_block(number)).await,
+            Err(_) => {
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    format!("Cannot parse block id {:?}, expected a number or a hash", id),
+                )
+            }
+        }
+    };
+    block_response(block, &id)
+}
+
+// "Balances" -> "balances", "transfer_keep_alive" -> "transferKeepAlive"
+fn lower_camel_case(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut upper_next = false;
+    for (i, c) in name.chars().enumerate() {
+        if c == '_' {
+            upper_next = true;
+        } else if i == 0 {
+            out.extend(c.to_lowercase());
+        } else if upper_next {
+            out.extend(c.to_uppercase());
+            upper_next = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn digest_log(hex_item: &str) -> Value {
+    let decoded = hex::decode(hex_item.trim_start_matches("0x"))
+        .ok()
+        .and_then(|bytes| DigestItem::decode(&mut &bytes[..]).ok());
+    let hex = |bytes: &[u8]| format!("0x{}", hex::encode(bytes));
+    let (kind, index, value) = match decoded {
+        Some(DigestItem::PreRuntime(engine, data)) => ("PreRuntime", 6, json!([hex(&engine), hex(&data)])),
+        Some(DigestItem::Consensus(engine, data)) => ("Consensus", 4, json!([hex(&engine), hex(&data)])),
+        Some(DigestItem::Seal(engine, data)) => ("Seal", 5, json!([hex(&engine), hex(&data)])),
+        Some(DigestItem::Other(data)) => ("Other", 0, json!(hex(&data))),
+        Some(DigestItem::RuntimeEnvironmentUpdated) => ("RuntimeEnvironmentUpdated", 8, Value::Null),
+        None => ("Unknown", 0, json!(hex_item)),
+    };
+    json!({ "type": kind, "index": index.to_string(), "value": value })
+}
+
+// Our stored era in Sidecar's shape
+fn era_json(era: &Value) -> Value {
+    match era {
+        Value::String(immortal) if immortal == "immortal" => json!({ "immortalEra": "0x00" }),
+        Value::Object(mortal) => match mortal.get("mortal") {
+            Some(mortal) => json!({ "mortalEra": [mortal["period"].to_string(), mortal["phase"].to_string()] }),
+            None => Value::Null,
+        },
+        _ => Value::Null,
+    }
+}
+
+fn block_json(block: StoredBlock) -> Value {
+    let extrinsics: Vec<Value> = block
+        .extrinsics
+        .iter()
+        .map(|extrinsic| {
+            let action = extrinsic["action"].as_str().unwrap_or_default();
+            let (pallet, call) = action.split_once('/').unwrap_or((action, ""));
+            let events = extrinsic["events"].as_array().cloned().unwrap_or_default();
+            let outcome = |variant: &str| {
+                events
+                    .iter()
+                    .any(|e| e["pallet"] == "System" && e["variant"] == variant)
+            };
+            // Unknown if the filters dropped both System events
+            let success = if outcome("ExtrinsicSuccess") {
+                json!(true)
+            } else if outcome("ExtrinsicFailed") {
+                json!(false)
+            } else {
+                Value::Null
+            };
+
+            json!({
+                "method": { "pallet": lower_camel_case(pallet), "method": lower_camel_case(call) },
+                "signature": null,
+                "nonce": extrinsic["nonce"].as_u64().map(|nonce| nonce.to_string()),
+                "args": extrinsic["params"],
+                "tip": extrinsic["tip"],
+                "hash": extrinsic["hash"],
+                "info": {},
+                "era": era_json(&extrinsic["era"]),
+                "events": events
+                    .iter()
+                    .map(|event| json!({
+                        "method": {
+                            "pallet": lower_camel_case(event["pallet"].as_str().unwrap_or_default()),
+                            "method": event["variant"],
+                        },
+                        "data": [event["data"]],
+                    }))
+                    .collect::<Vec<_>>(),
+                "success": success,
+                "paysFee": extrinsic["dispatch_info"]["pays_fee"],
+            })
+        })
+        .collect();
+
+    let header = block.header.as_ref();
+    json!({
+        "number": block.number.to_string(),
+        "hash": block.hash,
+        "parentHash": header.map(|h| &h.parent_hash),
+        "stateRoot": header.map(|h| &h.state_root),
+        "extrinsicsRoot": header.map(|h| &h.extrinsics_root),
+        "authorId": null,
+        "logs": header
+            .map(|h| h.digest.iter().map(|item| digest_log(item)).collect::<Vec<_>>())
+            .unwrap_or_default(),
+        "onInitialize": { "events": [] },
+        "extrinsics": extrinsics,
+        "onFinalize": { "events": [] },
+        "finalized": true,
+    })
+}
+
+fn balance(value: Option<&scale_value::Value<u32>>) -> Option<String> {
+    value.and_then(|v| v.as_u128()).map(|v| v.to_string())
+}
+
+fn bytes_hex(value: Option<&scale_value::Value<u32>>) -> Option<String> {
+    match &value?.value {
+        ValueDef::Composite(bytes) => {
+            let bytes: Vec<u8> = bytes.values().filter_map(|b| b.as_u128()).map(|b| b as u8).collect();
+            Some(format!("0x{}", hex::encode(bytes)))
+        }
+        _ => None,
+    }
+}
+
+async fn get_balance_info(State(state): State<AppState>, Path(address): Path<String>) -> Response {
+    let account: AccountId32 = match address.parse() {
+        Ok(account) => account,
+        Err(_) => return error(StatusCode::BAD_REQUEST, format!("Invalid address {:?}", address)),
+    };
+
+    match balance_info(&state, account).await {
+        Ok(info) => Json(info).into_response(),
+        Err(e) => error(StatusCode::BAD_GATEWAY, format!("Failed to query chain state: {}", e)),
+    }
+}
+
+async fn balance_info(state: &AppState, account: AccountId32) -> Result<Value, subxt::Error> {
+    let block = state.api.blocks().at_latest().await?;
+    let storage = block.storage();
+    let key = || vec![dynamic::Value::from_bytes(account.0)];
+
+    let info = match storage.fetch(&dynamic::storage("System", "Account", key())).await? {
+        Some(info) => Some(info.to_value()?),
+        None => None,
+    };
+    let locks = match storage.fetch(&dynamic::storage("Balances", "Locks", key())).await? {
+        Some(locks) => Some(locks.to_value()?),
+        None => None,
+    };
+
+    let info = info.as_ref();
+    let data = info.and_then(|i| i.at("data"));
+    // Runtimes since the fungible migration have `frozen`, older ones the two split fields
+    let (misc_frozen, fee_frozen, frozen) = match data.and_then(|d| d.at("frozen")) {
+        Some(frozen) => (
+            json!("miscFrozen does not exist for this runtime"),
+            json!("feeFrozen does not exist for this runtime"),
+            json!(balance(Some(frozen))),
+        ),
+        None => (
+            json!(balance(data.and_then(|d| d.at("misc_frozen"))).unwrap_or_else(|| "0".into())),
+            json!(balance(data.and_then(|d| d.at("fee_frozen"))).unwrap_or_else(|| "0".into())),
+            json!("frozen does not exist for this runtime"),
+        ),
+    };
+
+    let locks: Vec<Value> = match locks.as_ref().map(|l| &l.value) {
+        Some(ValueDef::Composite(locks)) => locks
+            .values()
+            .map(|lock| {
+                json!({
+                    "id": bytes_hex(lock.at("id")),
+                    "amount": balance(lock.at("amount")),
+                    "reasons": match lock.at("reasons").map(|r| &r.value) {
+                        Some(ValueDef::Variant(reasons)) => json!(reasons.name),
+                        _ => Value::Null,
+                    },
+                })
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    Ok(json!({
+        "at": { "hash": format!("{:?}", block.hash()), "height": block.number().to_string() },
+        "nonce": balance(info.and_then(|i| i.at("nonce"))).unwrap_or_else(|| "0".into()),
+        "tokenSymbol": state.token_symbol,
+        "free": balance(data.and_then(|d| d.at("free"))).unwrap_or_else(|| "0".into()),
+        "reserved": balance(data.and_then(|d| d.at("reserved"))).unwrap_or_else(|| "0".into()),
+        "miscFrozen": misc_frozen,
+        "feeFrozen": fee_frozen,
+        "frozen": frozen,
+        "locks": locks,
+    }))
+}
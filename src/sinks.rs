@@ -0,0 +1,49 @@
+//! Sinks get a copy of what the indexer stores, for pushing it to other systems
+//! (MQTT, Redis, the `/stream` feed...) alongside SQLite. Sinks must not block, they're
+//! called from the indexing loop: queue the work and do the I/O in a background task.
+
+use serde::Serialize;
+
+use crate::indexer::ExtrinsicInfo;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BlockSummary {
+    pub chain: String,
+    pub number: u32,
+    pub hash: String,
+    pub extrinsics_count: usize,
+    pub events_count: usize,
+    pub timestamp: i64,
+}
+
+pub trait Sink: Send + Sync {
+    /// Called for every extrinsic that passed the filters (with its filtered events),
+    /// as the block is processed
+    fn on_extrinsic(&self, chain: &str, block_number: u32, extrinsic: &ExtrinsicInfo);
+
+    /// Called once the block has been stored
+    fn on_block(&self, block: &BlockSummary);
+}
+
+#[derive(Default)]
+pub struct Sinks {
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Sinks {
+    pub fn add(&mut self, sink: Box<dyn Sink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn on_extrinsic(&self, chain: &str, block_number: u32, extrinsic: &ExtrinsicInfo) {
+        for sink in &self.sinks {
+            sink.on_extrinsic(chain, block_number, extrinsic);
+        }
+    }
+
+    pub fn on_block(&self, block: &BlockSummary) {
+        for sink in &self.sinks {
+            sink.on_block(block);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Standby snapshots: the chain registry and every chain database copied whole to
+//! `[standby] dir`, ideally on another disk, every `interval_secs`. Writes aren't mirrored as
+//! they happen, so if the primary disk dies, a smolcar started with `--data-dir` pointing there
+//! picks up from the last snapshot and backfills the blocks indexed since.
+//!
+//! Copies go through `VACUUM INTO` on a separate read-only connection, so writes carry on
+//! meanwhile, and each is renamed into place once whole, so the standby can start at any time.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use rusqlite::{Connection, OpenFlags};
+
+use crate::config::{ByteSize, Config};
+use crate::error::Error;
+use crate::reporting;
+
+/// Snapshots `sources` (chain name and database file) and the registry until smolcar stops.
+/// Settings are re-read every round, and nothing is copied while `[standby]` is unset.
+pub async fn run(registry: PathBuf, sources: Vec<(String, PathBuf)>, config: watch::Receiver<Config>) {
+    loop {
+        let Some(standby) = config.borrow().standby.clone() else {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            continue;
+        };
+
+        if let Err(e) = copy(registry.clone(), &standby.dir).await {
+            tracing::error!(error = %e, "Failed to copy the chain registry to the standby");
+        }
+        for (chain, source) in &sources {
+            let started = Instant::now();
+            match copy(source.clone(), &standby.dir).await {
+                Ok(bytes) => tracing::debug!(
+                    chain,
+                    dir = %standby.dir.display(),
+                    size = %ByteSize(bytes),
+                    took_ms = started.elapsed().as_millis() as u64,
+                    "Copied database to the standby"
+                ),
+                Err(e) => reporting::error(chain, &format!("Failed to copy the database to the standby: {}", e)),
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(standby.interval_secs.max(1))).await;
+    }
+}
+
+// Snapshots `source` into `dir` under the same file name, off the async runtime, returning its size
+async fn copy(source: PathBuf, dir: &Path) -> Result<u64, Error> {
+    let target = dir.join(source.file_name().unwrap_or_default());
+    tokio::task::spawn_blocking(move || snapshot(&source, &target))
+        .await
+        .map_err(|e| Error::Other(e.to_string()))?
+}
+
+fn snapshot(source: &Path, target: &Path) -> Result<u64, Error> {
+    if let Some(dir) = target.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let partial = target.with_extension("db.partial");
+    if partial.exists() {
+        std::fs::remove_file(&partial)?;
+    }
+    let conn = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+    conn.execute("VACUUM INTO ?1", [partial.to_string_lossy()])?;
+    std::fs::rename(&partial, target)?;
+    Ok(std::fs::metadata(target)?.len())
+}
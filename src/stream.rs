@@ -0,0 +1,82 @@
+//! Live feed of stored blocks and their events, served as Server-Sent Events on `/stream`.
+//! Subscribers that can't keep up miss messages instead of slowing the indexer down.
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use serde::Serialize;
+use std::convert::Infallible;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::indexer::ExtrinsicInfo;
+use crate::sinks::{BlockSummary, Sink};
+
+// Messages buffered per subscriber before the slowest ones start skipping
+const BUFFER: usize = 1024;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Block(BlockSummary),
+    Event {
+        chain: String,
+        block_number: u32,
+        extrinsic_index: u32,
+        pallet: String,
+        variant: String,
+        data: String,
+    },
+}
+
+#[derive(Clone)]
+pub struct EventStream {
+    tx: broadcast::Sender<Message>,
+}
+
+impl Default for EventStream {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(BUFFER);
+        EventStream { tx }
+    }
+}
+
+impl EventStream {
+    #[cfg_attr(not(any(feature = "grpc", feature = "graphql")), allow(dead_code))]
+    pub fn receiver(&self) -> broadcast::Receiver<Message> {
+        self.tx.subscribe()
+    }
+
+    /// An SSE response following every chain, with `block` and `event` messages.
+    pub fn subscribe(&self) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+        let messages = BroadcastStream::new(self.receiver()).filter_map(|message| {
+            // Lagging receivers get an error for the skipped messages, just carry on
+            let message = message.ok()?;
+            let kind = match message {
+                Message::Block(_) => "block",
+                Message::Event { .. } => "event",
+            };
+            Some(Ok(Event::default().event(kind).json_data(&message).unwrap()))
+        });
+        Sse::new(messages).keep_alive(KeepAlive::default())
+    }
+}
+
+impl Sink for EventStream {
+    fn on_extrinsic(&self, chain: &str, block_number: u32, extrinsic: &ExtrinsicInfo) {
+        // Sending only fails when nobody is listening
+        for event in &extrinsic.events {
+            let _ = self.tx.send(Message::Event {
+                chain: chain.to_string(),
+                block_number,
+                extrinsic_index: extrinsic.index,
+                pallet: event.pallet.clone(),
+                variant: event.variant.clone(),
+                data: event.data.clone(),
+            });
+        }
+    }
+
+    fn on_block(&self, block: &BlockSummary) {
+        let _ = self.tx.send(Message::Block(block.clone()));
+    }
+}
@@ -0,0 +1,125 @@
+//! Alert rules registered at runtime under `/admin/subscriptions`, like `[[alerts]]` in the
+//! config but kept in the chain's `subscriptions` table, so they survive restarts. They only
+//! match the chain they were registered on.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+
+use crate::alerts::{Alert, Alerts};
+use crate::api::AppState;
+use crate::db::{Database, Subscription};
+use crate::error::Error;
+use crate::rules::EventRule;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewSubscription {
+    name: String,
+    pallet: Option<String>,
+    variant: Option<String>,
+    /// Substring of the decoded event data, e.g. an address or "index: 42"
+    data_contains: Option<String>,
+    /// Names of entries in `[alert_channels]`
+    channels: Vec<String>,
+}
+
+impl NewSubscription {
+    fn validate(&self, alerts: &Alerts) -> Result<(), Error> {
+        if self.name.trim().is_empty() {
+            return Err(Error::BadRequest("name can't be empty".to_string()));
+        }
+        if self.channels.is_empty() {
+            return Err(Error::BadRequest("channels can't be empty".to_string()));
+        }
+        match self.channels.iter().find(|name| !alerts.has_channel(name)) {
+            Some(name) => Err(Error::BadRequest(format!("Channel {:?} isn't defined in [alert_channels]", name))),
+            None => Ok(()),
+        }
+    }
+
+    fn into_subscription(self, id: i64) -> Subscription {
+        Subscription {
+            id,
+            name: self.name,
+            pallet: self.pallet,
+            variant: self.variant,
+            data_contains: self.data_contains,
+            channels: self.channels,
+            created_at: 0,
+        }
+    }
+}
+
+fn alert(chain: &str, subscription: Subscription) -> Alert {
+    Alert {
+        name: subscription.name,
+        rule: EventRule {
+            chain: Some(chain.to_string()),
+            pallet: subscription.pallet,
+            variant: subscription.variant,
+            data_contains: subscription.data_contains,
+        },
+        channels: subscription.channels,
+    }
+}
+
+/// Hands the chain's stored subscriptions to the alerts, on startup and after every change.
+pub fn load(chain: &str, db: &Database, alerts: &Alerts) -> Result<(), rusqlite::Error> {
+    let subscriptions = db.get_subscriptions()?;
+    alerts.set_subscriptions(chain, subscriptions.into_iter().map(|s| alert(chain, s)).collect());
+    Ok(())
+}
+
+async fn reload(state: &AppState) -> Result<(), Error> {
+    let (chain, alerts) = (state.chain.clone(), state.alerts.clone());
+    state.db.blocking(move |db| load(&chain, db, &alerts)).await?;
+    Ok(())
+}
+
+pub async fn list(State(state): State<AppState>) -> Result<Json<Vec<Subscription>>, Error> {
+    Ok(Json(state.db.blocking(|db| db.get_subscriptions()).await?))
+}
+
+pub async fn get(State(state): State<AppState>, Path(id): Path<i64>) -> Result<Json<Subscription>, Error> {
+    let subscriptions = state.db.blocking(|db| db.get_subscriptions()).await?;
+    subscriptions
+        .into_iter()
+        .find(|subscription| subscription.id == id)
+        .map(Json)
+        .ok_or_else(|| Error::NotFound(format!("No subscription {}", id)))
+}
+
+pub async fn add(State(state): State<AppState>, Json(new): Json<NewSubscription>) -> Result<Response, Error> {
+    new.validate(&state.alerts)?;
+    let subscription = new.into_subscription(0);
+    let subscription = state.db.blocking(move |db| db.add_subscription(subscription)).await?;
+    reload(&state).await?;
+    tracing::info!(chain = %state.chain, id = subscription.id, name = %subscription.name, "Added alert subscription");
+    Ok((StatusCode::CREATED, Json(subscription)).into_response())
+}
+
+pub async fn update(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(new): Json<NewSubscription>,
+) -> Result<StatusCode, Error> {
+    new.validate(&state.alerts)?;
+    let subscription = new.into_subscription(id);
+    if !state.db.blocking(move |db| db.update_subscription(&subscription)).await? {
+        return Err(Error::NotFound(format!("No subscription {}", id)));
+    }
+    reload(&state).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn remove(State(state): State<AppState>, Path(id): Path<i64>) -> Result<StatusCode, Error> {
+    if !state.db.blocking(move |db| db.remove_subscription(id)).await? {
+        return Err(Error::NotFound(format!("No subscription {}", id)));
+    }
+    reload(&state).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
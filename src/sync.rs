@@ -0,0 +1,204 @@
+//! `/status/sync`: how far the light client and the indexer have come. smoldot doesn't report
+//! warp sync progress as such, only whether it's still syncing and with how many peers, so
+//! the rest is the finalized head against the last stored block. Catching up is estimated
+//! from how fast both moved over the last few minutes, sampled in the background.
+
+use axum::{extract::State, response::Json};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use subxt::backend::legacy::LegacyRpcMethods;
+use subxt::PolkadotConfig;
+
+use crate::api::AppState;
+use crate::error::Error;
+use crate::indexer::SharedBlockInfo;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+// A head the light client can't tell within this is left out of the sample
+const HEAD_TIMEOUT: Duration = Duration::from_secs(5);
+// Five minutes of samples
+const MAX_SAMPLES: usize = 30;
+// About ten minutes of Polkadot blocks
+const LAG_WARNING: u32 = 100;
+
+#[derive(Clone, Copy)]
+struct Sample {
+    at: Instant,
+    indexed: u32,
+    finalized: u32,
+}
+
+/// Recent indexed and finalized block numbers of one chain.
+#[derive(Default)]
+pub struct SyncTracker {
+    samples: Mutex<VecDeque<Sample>>,
+    heads: Mutex<Heads>,
+    lagging: AtomicBool,
+    /// Blocks the backfills (catching up included) have yet to go through
+    backfill_remaining: AtomicU32,
+}
+
+impl SyncTracker {
+    /// Samples the chain until smolcar stops, warning once the indexer lags more than
+    /// `LAG_WARNING` blocks behind the finalized head. Samples the light client can't answer
+    /// are skipped.
+    pub async fn run(
+        self: Arc<Self>,
+        chain: String,
+        rpc: LegacyRpcMethods<PolkadotConfig>,
+        block_info: SharedBlockInfo,
+    ) {
+        let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let (finalized, best) = tokio::join!(sampled_head(&rpc, true), sampled_head(&rpc, false));
+            *self.heads.lock().unwrap() = Heads { finalized: finalized.clone(), best };
+            let Some(finalized) = finalized.map(|head| head.number) else {
+                continue;
+            };
+            let indexed = block_info.load().number;
+            // Nothing's stored before the first followed block
+            let behind = if indexed == 0 { 0 } else { finalized.saturating_sub(indexed) };
+            let lagging = behind > LAG_WARNING;
+            if self.lagging.swap(lagging, Ordering::Relaxed) != lagging {
+                if lagging {
+                    tracing::warn!(chain = %chain, indexed, finalized, behind, "The indexer is falling behind the finalized head");
+                } else {
+                    tracing::info!(chain = %chain, indexed, finalized, "The indexer caught up with the finalized head");
+                }
+            }
+
+            let sample = Sample { at: Instant::now(), indexed, finalized };
+            let mut samples = self.samples.lock().unwrap();
+            if samples.len() == MAX_SAMPLES {
+                samples.pop_front();
+            }
+            samples.push_back(sample);
+        }
+    }
+
+    /// The light client's heads, last time they were sampled.
+    pub fn heads(&self) -> Heads {
+        self.heads.lock().unwrap().clone()
+    }
+
+    /// Counts `blocks` more for the backfills to go through.
+    pub fn backfill_pending(&self, blocks: u32) {
+        self.backfill_remaining.fetch_add(blocks, Ordering::Relaxed);
+    }
+
+    /// Counts `blocks` the backfills went through, stored or not.
+    pub fn backfilled(&self, blocks: u32) {
+        let _ = self
+            .backfill_remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |remaining| Some(remaining.saturating_sub(blocks)));
+    }
+
+    /// Whether following new blocks lags behind, last time it was sampled. Backfills wait then.
+    pub fn is_lagging(&self) -> bool {
+        self.lagging.load(Ordering::Relaxed)
+    }
+
+    // Blocks per second stored and finalized over the samples, None until there are two
+    fn rates(&self) -> Option<(f64, f64)> {
+        let samples = self.samples.lock().unwrap();
+        let (first, last) = (samples.front()?, samples.back()?);
+        let secs = last.at.duration_since(first.at).as_secs_f64();
+        if secs <= 0.0 {
+            return None;
+        }
+        let rate = |from: u32, to: u32| f64::from(to.saturating_sub(from)) / secs;
+        Some((rate(first.indexed, last.indexed), rate(first.finalized, last.finalized)))
+    }
+}
+
+/// A head of the chain as the light client sees it.
+#[derive(Clone, Debug, Serialize)]
+pub struct Head {
+    pub number: u32,
+    pub hash: String,
+}
+
+/// The finalized and best heads, None where the light client couldn't tell.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Heads {
+    pub finalized: Option<Head>,
+    pub best: Option<Head>,
+}
+
+/// The finalized head, or the best (newest, not necessarily final) one.
+pub async fn head(rpc: &LegacyRpcMethods<PolkadotConfig>, finalized: bool) -> Result<Head, subxt::Error> {
+    let hash = if finalized {
+        rpc.chain_get_finalized_head().await?
+    } else {
+        rpc.chain_get_block_hash(None)
+            .await?
+            .ok_or_else(|| subxt::Error::Other("The light client has no best block yet".to_string()))?
+    };
+    let header = rpc.chain_get_header(Some(hash)).await?;
+    header
+        .map(|header| Head { number: header.number, hash: format!("{:?}", hash) })
+        .ok_or_else(|| subxt::Error::Other("The light client doesn't have the head's header".to_string()))
+}
+
+async fn sampled_head(rpc: &LegacyRpcMethods<PolkadotConfig>, finalized: bool) -> Option<Head> {
+    tokio::time::timeout(HEAD_TIMEOUT, head(rpc, finalized)).await.ok()?.ok()
+}
+
+async fn finalized_number(rpc: &LegacyRpcMethods<PolkadotConfig>) -> Result<u32, subxt::Error> {
+    Ok(head(rpc, true).await?.number)
+}
+
+#[derive(Serialize)]
+pub struct LightClientStatus {
+    /// Still warp syncing, or otherwise not near the head
+    syncing: bool,
+    peers: usize,
+}
+
+#[derive(Serialize)]
+pub struct SyncStatus {
+    light_client: LightClientStatus,
+    /// Last stored block
+    indexed: u32,
+    /// The chain's finalized head, as far as the light client knows
+    finalized: u32,
+    /// Between the last stored block and the finalized head
+    behind: u32,
+    /// Older blocks the backfills and catching up still have to fetch, on top of `behind`
+    backfill_remaining: u32,
+    /// Stored over the last few minutes
+    blocks_per_second: Option<f64>,
+    /// Until `behind` is 0 at the current pace, None when it isn't closing the gap
+    catch_up_secs: Option<u64>,
+}
+
+pub async fn get_sync(State(state): State<AppState>) -> Result<Json<SyncStatus>, Error> {
+    let rpc = LegacyRpcMethods::<PolkadotConfig>::new(state.rpc.clone());
+    let health = rpc.system_health().await?;
+    let finalized = finalized_number(&rpc).await?;
+    let indexed = state.block_info.load().number;
+    let behind = finalized.saturating_sub(indexed);
+
+    let rates = state.sync.rates();
+    let catch_up_secs = match rates {
+        _ if behind == 0 => Some(0),
+        Some((indexing, producing)) if indexing > producing => {
+            Some((f64::from(behind) / (indexing - producing)).ceil() as u64)
+        }
+        _ => None,
+    };
+
+    Ok(Json(SyncStatus {
+        light_client: LightClientStatus { syncing: health.is_syncing, peers: health.peers },
+        indexed,
+        finalized,
+        behind,
+        backfill_remaining: state.sync.backfill_remaining.load(Ordering::Relaxed),
+        blocks_per_second: rates.map(|(indexing, _)| indexing),
+        catch_up_secs,
+    }))
+}
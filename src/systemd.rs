@@ -0,0 +1,42 @@
+//! systemd integration for `Type=notify` / `Type=notify-reload` units. All of this is a
+//! no-op when smolcar isn't started by systemd (NOTIFY_SOCKET unset).
+
+use sd_notify::NotifyState;
+
+fn notify(states: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(states) {
+        tracing::warn!("Failed to notify systemd: {}", e);
+    }
+}
+
+pub fn ready(status: &str) {
+    notify(&[NotifyState::Ready, NotifyState::Status(status)]);
+}
+
+pub fn status(status: &str) {
+    notify(&[NotifyState::Status(status)]);
+}
+
+/// Tells systemd a reload started. Follow up with `ready` once it's done.
+pub fn reloading() {
+    match NotifyState::monotonic_usec_now() {
+        Ok(now) => notify(&[NotifyState::Reloading, now]),
+        Err(_) => notify(&[NotifyState::Reloading]),
+    }
+}
+
+/// Pings the watchdog at half the interval systemd asked for (WatchdogSec=), if any.
+/// Pings come from the async runtime, so a wedged runtime gets the service restarted.
+pub fn spawn_watchdog() {
+    let Some(interval) = sd_notify::watchdog_enabled() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval / 2);
+        loop {
+            ticker.tick().await;
+            notify(&[NotifyState::Watchdog]);
+        }
+    });
+}
@@ -0,0 +1,250 @@
+//! `smolcar tui`: a terminal view of a running smolcar, for when there's no browser around
+//! to open the dashboard. It only talks to the HTTP API, so it works over SSH and against
+//! remote instances alike.
+
+/// Polls the API at `url` until the user quits with `q` or Esc.
+pub async fn run(url: &str, chain: Option<&str>) -> Result<(), String> {
+    #[cfg(feature = "tui")]
+    return app::run(url, chain).await;
+
+    #[cfg(not(feature = "tui"))]
+    {
+        let _ = (url, chain);
+        Err("smolcar was built without the `tui` feature".to_string())
+    }
+}
+
+#[cfg(feature = "tui")]
+mod app {
+    use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+    use ratatui::layout::{Constraint, Layout};
+    use ratatui::style::{Color, Style, Stylize};
+    use ratatui::text::Line;
+    use ratatui::widgets::{Block, Paragraph, Row, Sparkline, Table};
+    use ratatui::{DefaultTerminal, Frame};
+    use serde::Deserialize;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    use crate::config::ByteSize;
+    use crate::db::StoredBlock;
+    use crate::reporting::ReportedError;
+
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+    const RECENT_BLOCKS: u32 = 50;
+    // Events/sec is averaged over this window
+    const RATE_WINDOW_SECS: i64 = 60;
+
+    #[derive(Deserialize)]
+    struct Stats {
+        stored_blocks: u64,
+        used_bytes: u64,
+    }
+
+    #[derive(Default)]
+    struct State {
+        blocks: Vec<StoredBlock>,
+        stats: Option<Stats>,
+        errors: Vec<ReportedError>,
+        // Why the last poll failed, if it did
+        unreachable: Option<String>,
+    }
+
+    struct Api {
+        http: reqwest::Client,
+        base: String,
+        chain_base: String,
+    }
+
+    impl Api {
+        async fn get<T: serde::de::DeserializeOwned>(&self, url: String) -> Result<T, String> {
+            self.http
+                .get(url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| e.to_string())?
+                .json()
+                .await
+                .map_err(|e| e.to_string())
+        }
+
+        async fn poll(&self, state: &mut State) {
+            let polled = async {
+                let blocks = self
+                    .get(format!("{}/blocks/recent?limit={}", self.chain_base, RECENT_BLOCKS))
+                    .await?;
+                let stats = self.get(format!("{}/stats", self.chain_base)).await?;
+                let errors = self.get(format!("{}/errors", self.base)).await?;
+                Ok::<_, String>((blocks, stats, errors))
+            };
+            match polled.await {
+                Ok((blocks, stats, errors)) => {
+                    state.blocks = blocks;
+                    state.stats = Some(stats);
+                    state.errors = errors;
+                    state.unreachable = None;
+                }
+                Err(e) => state.unreachable = Some(e),
+            }
+        }
+    }
+
+    pub async fn run(url: &str, chain: Option<&str>) -> Result<(), String> {
+        let base = url.trim_end_matches('/').to_string();
+        let api = Api {
+            http: reqwest::Client::builder()
+                .timeout(POLL_INTERVAL)
+                .build()
+                .map_err(|e| e.to_string())?,
+            chain_base: match chain {
+                Some(chain) => format!("{}/{}", base, chain),
+                None => base.clone(),
+            },
+            base,
+        };
+
+        // Key presses are read on their own thread so polling never waits on the terminal
+        let (keys_tx, mut keys) = mpsc::unbounded_channel();
+        std::thread::spawn(move || loop {
+            match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    if keys_tx.send(key.code).is_err() {
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        });
+
+        let mut terminal = ratatui::init();
+        let result = draw_loop(&mut terminal, &api, chain, &mut keys).await;
+        ratatui::restore();
+        result
+    }
+
+    async fn draw_loop(
+        terminal: &mut DefaultTerminal,
+        api: &Api,
+        chain: Option<&str>,
+        keys: &mut mpsc::UnboundedReceiver<KeyCode>,
+    ) -> Result<(), String> {
+        let mut state = State::default();
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            terminal
+                .draw(|frame| draw(frame, &state, chain.unwrap_or("default chain")))
+                .map_err(|e| e.to_string())?;
+
+            tokio::select! {
+                _ = interval.tick() => api.poll(&mut state).await,
+                key = keys.recv() => match key {
+                    Some(KeyCode::Char('q') | KeyCode::Esc) | None => return Ok(()),
+                    Some(_) => {}
+                },
+            }
+        }
+    }
+
+    fn events_count(block: &StoredBlock) -> usize {
+        block
+            .extrinsics
+            .iter()
+            .filter_map(|extrinsic| extrinsic["events"].as_array())
+            .map(|events| events.len())
+            .sum()
+    }
+
+    fn draw(frame: &mut Frame, state: &State, chain: &str) {
+        let [header, summary, flow, blocks, errors] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(3),
+            Constraint::Length(6),
+            Constraint::Min(6),
+            Constraint::Length(8),
+        ])
+        .areas(frame.area());
+
+        let status = match &state.unreachable {
+            Some(e) => format!("unreachable: {}", e).red(),
+            None => "live".green(),
+        };
+        frame.render_widget(
+            Line::from(vec![format!("smolcar - {} - ", chain).bold(), status, "  (q to quit)".dark_gray()]),
+            header,
+        );
+
+        let now = chrono::Utc::now().timestamp();
+        let head = state.blocks.first();
+        let recent_events: usize = state
+            .blocks
+            .iter()
+            .filter(|block| now - block.timestamp <= RATE_WINDOW_SECS)
+            .map(events_count)
+            .sum();
+        let figures = [
+            ("Head", head.map(|b| format!("#{}", b.number))),
+            ("Events/sec", Some(format!("{:.1}", recent_events as f64 / RATE_WINDOW_SECS as f64))),
+            ("Lag", head.map(|b| format!("{}s since last block", now - b.timestamp))),
+            ("DB size", state.stats.as_ref().map(|s| ByteSize(s.used_bytes).to_string())),
+            ("Stored blocks", state.stats.as_ref().map(|s| s.stored_blocks.to_string())),
+        ];
+        let columns = Layout::horizontal([Constraint::Fill(1); 5]).split(summary);
+        for ((title, value), area) in figures.into_iter().zip(columns.iter()) {
+            frame.render_widget(
+                Paragraph::new(value.unwrap_or_else(|| "-".to_string()))
+                    .block(Block::bordered().title(title)),
+                *area,
+            );
+        }
+
+        // Oldest on the left, so the newest block comes in on the right
+        let flow_data: Vec<u64> = state.blocks.iter().rev().map(|b| b.extrinsics.len() as u64).collect();
+        frame.render_widget(
+            Sparkline::default()
+                .block(Block::bordered().title("Extrinsics per block"))
+                .data(&flow_data)
+                .style(Style::default().fg(Color::Cyan)),
+            flow,
+        );
+
+        let rows = state.blocks.iter().map(|block| {
+            Row::new(vec![
+                format!("#{}", block.number),
+                block.hash.clone(),
+                block.extrinsics.len().to_string(),
+                events_count(block).to_string(),
+                format!("{}s ago", now - block.timestamp),
+            ])
+        });
+        frame.render_widget(
+            Table::new(
+                rows,
+                [
+                    Constraint::Length(12),
+                    Constraint::Fill(1),
+                    Constraint::Length(10),
+                    Constraint::Length(8),
+                    Constraint::Length(10),
+                ],
+            )
+            .header(Row::new(["Block", "Hash", "Extrinsics", "Events", "Stored"]).bold())
+            .block(Block::bordered().title("Recent blocks")),
+            blocks,
+        );
+
+        let lines: Vec<Line> = state
+            .errors
+            .iter()
+            .map(|error| {
+                let block = error.block_number.map(|n| format!(" #{}", n)).unwrap_or_default();
+                Line::from(format!("{}s ago [{}{}] {}", now - error.timestamp, error.chain, block, error.message))
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title("Recent errors")),
+            errors,
+        );
+    }
+}
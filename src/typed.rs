@@ -0,0 +1,129 @@
+//! Typed fields for the events most clients care about: balance movements, staking and
+//! account lifecycle. Stored next to the generic `data` text as `typed`, in a handful of
+//! shapes shared across pallets, so a client can read an amount without parsing text. Other
+//! events only have `data`, unless a custom decoder takes them (see `decoders.rs`).
+
+use serde::{Serialize, Serializer};
+use subxt::ext::scale_value::{At, Composite, Value};
+
+use crate::decode;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum TypedEvent {
+    /// Balances `Transfer` and `ReserveRepatriated`, Assets `Transferred` and `TransferredApproved`
+    Transfer {
+        /// Only for assets, the native token has none
+        #[serde(skip_serializing_if = "Option::is_none")]
+        asset_id: Option<u32>,
+        from: String,
+        to: String,
+        #[serde(serialize_with = "as_string")]
+        amount: u128,
+    },
+    /// Balances `Deposit`, `Withdraw`, `Endowed`, `Reserved`, `Slashed`..., Staking `Bonded`,
+    /// `Unbonded`, `Withdrawn`, `Rewarded` and `Slashed`
+    Amount {
+        account: String,
+        #[serde(serialize_with = "as_string")]
+        amount: u128,
+    },
+    /// System `NewAccount` and `KilledAccount`, Staking `Chilled`
+    Account { account: String },
+    /// Staking `EraPaid`
+    EraPaid {
+        era: u32,
+        #[serde(serialize_with = "as_string")]
+        validator_payout: u128,
+        #[serde(serialize_with = "as_string")]
+        remainder: u128,
+    },
+    /// Staking `PayoutStarted`
+    PayoutStarted { era: u32, validator: String },
+    /// Staking `Kicked`
+    Kicked { nominator: String, validator: String },
+    /// System `Remarked`
+    Remarked { sender: String, hash: String },
+    /// Whatever the pallet's custom decoder made of it, see `decoders.rs`
+    Custom(serde_json::Value),
+}
+
+// Like the extrinsics' `tip`, amounts may not fit in a JSON number
+fn as_string<S: Serializer>(amount: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(amount)
+}
+
+/// The typed fields of a well-known event, None for any other (or one that doesn't decode
+/// the way it's expected to, e.g. from a runtime that changed it).
+pub fn decode(decode: &decode::Context, pallet: &str, variant: &str, fields: &Composite<u32>) -> Option<TypedEvent> {
+    if let Some(custom) = decode.decoders.get(pallet).and_then(|decoder| decoder.event(decode, variant, fields)) {
+        return Some(TypedEvent::Custom(custom));
+    }
+    // Older runtimes had unnamed fields, in the same order
+    let field = |name: &str, position: usize| match fields {
+        Composite::Named(_) => fields.at(name),
+        Composite::Unnamed(values) => values.get(position),
+    };
+    let account = |name: &str, position: usize| field(name, position).and_then(|value| decode.account(value));
+    let number = |name: &str, position: usize| field(name, position).and_then(Value::as_u128);
+    let era = |position| number("era_index", position).and_then(|era| u32::try_from(era).ok());
+    let amount = |who: &str, amount: &str| {
+        Some(TypedEvent::Amount { account: account(who, 0)?, amount: number(amount, 1)? })
+    };
+
+    match (pallet, variant) {
+        ("Balances", "Transfer" | "ReserveRepatriated") => Some(TypedEvent::Transfer {
+            asset_id: None,
+            from: account("from", 0)?,
+            to: account("to", 1)?,
+            amount: number("amount", 2)?,
+        }),
+        ("Assets", "Transferred") => Some(TypedEvent::Transfer {
+            asset_id: number("asset_id", 0).and_then(|id| u32::try_from(id).ok()),
+            from: account("from", 1)?,
+            to: account("to", 2)?,
+            amount: number("amount", 3)?,
+        }),
+        ("Assets", "TransferredApproved") => Some(TypedEvent::Transfer {
+            asset_id: number("asset_id", 0).and_then(|id| u32::try_from(id).ok()),
+            from: account("owner", 1)?,
+            to: account("destination", 3)?,
+            amount: number("amount", 4)?,
+        }),
+        ("Balances", "Endowed") => amount("account", "free_balance"),
+        ("Balances", "DustLost") => amount("account", "amount"),
+        ("Balances", "BalanceSet") => amount("who", "free"),
+        (
+            "Balances",
+            "Deposit" | "Withdraw" | "Reserved" | "Unreserved" | "Slashed" | "Minted" | "Burned" | "Suspended"
+            | "Restored" | "Locked" | "Unlocked" | "Frozen" | "Thawed",
+        ) => amount("who", "amount"),
+        ("Staking", "Bonded" | "Unbonded" | "Withdrawn") => amount("stash", "amount"),
+        // `dest` came in between, hence the amount's last place
+        ("Staking", "Rewarded") => Some(TypedEvent::Amount {
+            account: account("stash", 0)?,
+            amount: number("amount", fields.values().count().saturating_sub(1))?,
+        }),
+        ("Staking", "Slashed") => amount("staker", "amount"),
+        ("Staking", "Chilled") => Some(TypedEvent::Account { account: account("stash", 0)? }),
+        ("Staking", "EraPaid") => Some(TypedEvent::EraPaid {
+            era: era(0)?,
+            validator_payout: number("validator_payout", 1)?,
+            remainder: number("remainder", 2)?,
+        }),
+        ("Staking", "PayoutStarted") => Some(TypedEvent::PayoutStarted {
+            era: era(0)?,
+            validator: account("validator_stash", 1)?,
+        }),
+        ("Staking", "Kicked") => Some(TypedEvent::Kicked {
+            nominator: account("nominator", 0)?,
+            validator: account("stash", 1)?,
+        }),
+        ("System", "NewAccount" | "KilledAccount") => Some(TypedEvent::Account { account: account("account", 0)? }),
+        ("System", "Remarked") => Some(TypedEvent::Remarked {
+            sender: account("sender", 0)?,
+            hash: format!("0x{}", hex::encode(decode::bytes(field("hash", 1)?)?)),
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,142 @@
+//! Validator performance on `/validators/:address/performance`: blocks authored (from the
+//! authors `babe.rs` resolves), uptime from `ImOnline` session ends (`AllGood`/`SomeOffline`,
+//! stored here), and era points against the average validator (from `era_points.rs`).
+//! Heartbeats are attributed to validators through `Session::KeyOwner`, and sessions that
+//! reported validators offline are served on `/validators/offline` and can fire offline alerts.
+//!
+//! Everything is counted over what smolcar indexed, not the validator's whole history.
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use subxt::dynamic::{self, Value};
+use subxt::events::Events;
+use subxt::ext::scale_value::{At, ValueDef};
+use subxt::utils::{AccountId32, H256};
+use subxt::{OnlineClient, PolkadotConfig};
+
+use crate::api::AppState;
+use crate::db::{self, Cursor};
+use crate::error::Error;
+use crate::decode;
+use crate::indexer::TrackerUpdates;
+use crate::reporting;
+
+const MAX_ERAS: u32 = 200;
+const MAX_REPORTS: u32 = 200;
+
+const IM_ONLINE_KEY_TYPE: &[u8] = b"imon";
+
+// The stash behind an `ImOnline` key, through the session keys it registered
+async fn key_owner(
+    api: &OnlineClient<PolkadotConfig>,
+    at: H256,
+    authority_id: &[u8],
+) -> Result<Option<Vec<u8>>, subxt::Error> {
+    let key = vec![Value::unnamed_composite([Value::from_bytes(IM_ONLINE_KEY_TYPE), Value::from_bytes(authority_id)])];
+    let owner = api.storage().at(at).fetch(&dynamic::storage("Session", "KeyOwner", key)).await?;
+    Ok(match owner {
+        Some(owner) => decode::bytes(&owner.to_value()?),
+        None => None,
+    })
+}
+
+/// Records the block's heartbeats and `ImOnline` session end, and returns the validators
+/// (as SS58 addresses) it reported offline, for alerts.
+pub async fn record(
+    updates: &mut TrackerUpdates,
+    chain: &str,
+    api: &OnlineClient<PolkadotConfig>,
+    decode: &decode::Context,
+    block_number: u32,
+    block_hash: H256,
+    events: &Events<PolkadotConfig>,
+) -> Vec<String> {
+    let mut reported = Vec::new();
+    for event in events.iter().flatten() {
+        if event.pallet_name() != "ImOnline" {
+            continue;
+        }
+        let Ok(fields) = event.field_values() else {
+            continue;
+        };
+
+        match event.variant_name() {
+            "HeartbeatReceived" => {
+                let Some(authority_id) = fields.at("authority_id").and_then(decode::bytes) else {
+                    continue;
+                };
+                let validator = match key_owner(api, block_hash, &authority_id).await {
+                    Ok(owner) => owner.map(|owner| format!("0x{}", hex::encode(owner))),
+                    Err(e) => {
+                        reporting::block_error(chain, block_number, &format!("Failed to look up heartbeat key: {}", e));
+                        None
+                    }
+                };
+                let authority_id = format!("0x{}", hex::encode(&authority_id));
+                updates.push("Failed to record heartbeat", move |db| {
+                    db.record_heartbeat(block_number, &authority_id, validator.as_deref())
+                });
+            }
+            "AllGood" | "SomeOffline" => {
+                // `offline` is a list of (validator, exposure)
+                let offline: Vec<(String, String)> = match fields.at("offline").map(|v| &v.value) {
+                    Some(ValueDef::Composite(offline)) => offline
+                        .values()
+                        .filter_map(|entry| {
+                            let validator = entry.at(0)?;
+                            Some((format!("0x{}", hex::encode(decode::bytes(validator)?)), decode.account(validator)?))
+                        })
+                        .collect(),
+                    _ => vec![],
+                };
+                reported.extend(offline.iter().map(|(_, address)| address.clone()));
+                updates.push("Failed to record heartbeats", move |db| db.record_heartbeat_session(block_number, &offline));
+            }
+            _ => {}
+        }
+    }
+    reported
+}
+
+#[derive(Deserialize)]
+pub struct OfflineQuery {
+    limit: Option<u32>,
+}
+
+/// The latest sessions that ended with validators reported offline, newest first.
+/// Pages with `?before=`/`?after=` block numbers.
+pub async fn get_offline(
+    State(state): State<AppState>,
+    Query(query): Query<OfflineQuery>,
+    Query(cursor): Query<Cursor>,
+) -> Result<Json<Vec<db::OfflineReport>>, Error> {
+    let limit = query.limit.unwrap_or(20).min(MAX_REPORTS);
+    Ok(Json(state.db.blocking(move |db| db.get_offline_reports(cursor, limit)).await?))
+}
+
+#[derive(Deserialize)]
+pub struct PerformanceQuery {
+    eras: Option<u32>,
+}
+
+/// A validator's blocks, uptime and points, with points compared over `?eras=` (default 30).
+pub async fn get_performance(
+    State(state): State<AppState>,
+    Path(address): Path<String>,
+    Query(query): Query<PerformanceQuery>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let validator: AccountId32 =
+        address.parse().map_err(|_| Error::BadRequest(format!("Invalid address {:?}", address)))?;
+
+    let eras = query.eras.unwrap_or(30).min(MAX_ERAS);
+    let key = format!("0x{}", hex::encode(validator.0));
+    let performance = state.db.blocking(move |db| db.get_validator_performance(&key, eras)).await?;
+    Ok(Json(json!({
+        "address": address,
+        "performance": performance,
+    })))
+}
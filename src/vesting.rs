@@ -0,0 +1,150 @@
+//! Vesting schedules of the accounts that `Vesting` events mention, read from the pallet's
+//! storage after each event and served on `/account/:address/vesting`.
+//!
+//! What's still locked is worked out from the schedules at the latest stored block, so it stays
+//! current between events.
+
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use serde_json::json;
+use subxt::dynamic;
+use subxt::events::Events;
+use subxt::ext::scale_value::{At, ValueDef};
+use subxt::utils::{AccountId32, H256};
+use subxt::{OnlineClient, PolkadotConfig};
+
+use crate::api::AppState;
+use crate::db::VestingSchedule;
+use crate::decode;
+use crate::error::Error;
+use crate::indexer::TrackerUpdates;
+use crate::reporting;
+
+async fn fetch_schedules(
+    api: &OnlineClient<PolkadotConfig>,
+    at: H256,
+    account: &[u8],
+) -> Result<Vec<VestingSchedule>, String> {
+    let key = dynamic::Value::from_bytes(account);
+    let stored = api
+        .storage()
+        .at(at)
+        .fetch(&dynamic::storage("Vesting", "Vesting", vec![key]))
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(stored) = stored else {
+        return Ok(vec![]);
+    };
+
+    let schedules = stored.to_value().map_err(|e| e.to_string())?;
+    let ValueDef::Composite(schedules) = &schedules.value else {
+        return Err("unexpected Vesting storage layout".to_string());
+    };
+    Ok(schedules
+        .values()
+        .filter_map(|schedule| {
+            Some(VestingSchedule {
+                locked: schedule.at("locked")?.as_u128()?.to_string(),
+                per_block: schedule.at("per_block")?.as_u128()?.to_string(),
+                starting_block: u32::try_from(schedule.at("starting_block")?.as_u128()?).ok()?,
+            })
+        })
+        .collect())
+}
+
+/// Refreshes the schedules of accounts in the block's `VestingUpdated` and `VestingCompleted`
+/// events.
+pub async fn record(
+    updates: &mut TrackerUpdates,
+    chain: &str,
+    api: &OnlineClient<PolkadotConfig>,
+    block_number: u32,
+    block_hash: H256,
+    events: &Events<PolkadotConfig>,
+) {
+    for event in events.iter().flatten() {
+        if event.pallet_name() != "Vesting" {
+            continue;
+        }
+        let Ok(fields) = event.field_values() else {
+            continue;
+        };
+        let unvested = match event.variant_name() {
+            "VestingUpdated" => fields.at("unvested").and_then(|v| v.as_u128()).map(|n| n.to_string()),
+            "VestingCompleted" => None,
+            _ => continue,
+        };
+        let Some(account) = fields.at("account").and_then(decode::bytes) else {
+            continue;
+        };
+
+        match fetch_schedules(api, block_hash, &account).await {
+            Ok(schedules) => {
+                let account = format!("0x{}", hex::encode(&account));
+                updates.push("Failed to record vesting schedules", move |db| {
+                    db.set_vesting(&account, &schedules, unvested.as_deref(), block_number)
+                });
+            }
+            Err(e) => reporting::block_error(chain, block_number, &format!("Failed to fetch vesting schedules: {}", e)),
+        }
+    }
+}
+
+// Still locked at block `at`, and the block it's all unlocked in
+fn remaining(schedule: &VestingSchedule, at: u32) -> (u128, Option<u32>) {
+    let locked: u128 = schedule.locked.parse().unwrap_or_default();
+    let per_block: u128 = schedule.per_block.parse().unwrap_or_default();
+    let vested = per_block.saturating_mul(at.saturating_sub(schedule.starting_block).into());
+    let unlocks_at = (per_block > 0)
+        .then(|| locked.div_ceil(per_block))
+        .and_then(|blocks| u32::try_from(blocks).ok())
+        .map(|blocks| schedule.starting_block.saturating_add(blocks));
+
+    (locked.saturating_sub(vested), unlocks_at)
+}
+
+pub async fn get_vesting(
+    State(state): State<AppState>,
+    Path(address): Path<String>,
+) -> Result<Json<serde_json::Value>, Error> {
+    let account: AccountId32 =
+        address.parse().map_err(|_| Error::BadRequest(format!("Invalid address {:?}", address)))?;
+
+    let key = format!("0x{}", hex::encode(account.0));
+    let (vesting, at) = state
+        .db
+        .blocking(move |db| {
+            let vesting = db.get_vesting(&key)?;
+            Ok::<_, rusqlite::Error>(vesting.zip(db.get_latest_block_number()?))
+        })
+        .await?
+        .ok_or_else(|| Error::NotFound(format!("No vesting events seen for {}", address)))?;
+
+    let mut total = 0u128;
+    let schedules: Vec<_> = vesting
+        .schedules
+        .iter()
+        .map(|schedule| {
+            let (remaining, unlocks_at) = remaining(schedule, at);
+            total = total.saturating_add(remaining);
+            json!({
+                "locked": schedule.locked,
+                "per_block": schedule.per_block,
+                "starting_block": schedule.starting_block,
+                "remaining": remaining.to_string(),
+                "unlocks_at": unlocks_at,
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "address": address,
+        "at": at,
+        "locked": total.to_string(),
+        "schedules": schedules,
+        "unvested": vesting.unvested,
+        "updated_in": vesting.updated_in,
+    })))
+}
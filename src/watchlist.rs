@@ -0,0 +1,60 @@
+//! Accounts of interest, registered with `POST /watchlist`. Extrinsics they sign and events
+//! that mention them are stored whatever the filters say, and each extrinsic touching one can
+//! post to the alert channels it was registered with.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use subxt::utils::AccountId32;
+
+use crate::api::AppState;
+use crate::db::Watch;
+use crate::error::Error;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewWatch {
+    /// SS58, in any format
+    address: String,
+    label: Option<String>,
+    /// Names of entries in `[alert_channels]`
+    #[serde(default)]
+    channels: Vec<String>,
+}
+
+/// Adds an account to the watchlist, or updates its label and channels.
+pub async fn add(State(state): State<AppState>, Json(new): Json<NewWatch>) -> Result<Response, Error> {
+    let Ok(account) = new.address.parse::<AccountId32>() else {
+        return Err(Error::BadRequest(format!("Invalid address {:?}", new.address)));
+    };
+    if let Some(name) = new.channels.iter().find(|name| !state.alerts.has_channel(name)) {
+        return Err(Error::BadRequest(format!("Channel {:?} isn't defined in [alert_channels]", name)));
+    }
+
+    let watch = Watch {
+        address: new.address,
+        label: new.label,
+        channels: new.channels,
+        added_at: chrono::Utc::now().timestamp(),
+    };
+    let stored = watch.clone();
+    state.db.blocking(move |db| db.watch(account.0, stored)).await?;
+    Ok((StatusCode::CREATED, Json(watch)).into_response())
+}
+
+pub async fn list(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.db.get_watchlist())
+}
+
+pub async fn remove(State(state): State<AppState>, Path(address): Path<String>) -> Result<StatusCode, Error> {
+    let Ok(account) = address.parse::<AccountId32>() else {
+        return Err(Error::BadRequest(format!("Invalid address {:?}", address)));
+    };
+    if !state.db.blocking(move |db| db.unwatch(&account.0)).await? {
+        return Err(Error::NotFound(format!("{} isn't on the watchlist", address)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,82 @@
+//! Webhooks bound to an account, registered with `POST /webhooks`. Every extrinsic the
+//! account signs, or with a transfer to or from it, is POSTed decoded to the webhook's URL.
+//! Like watched accounts, their extrinsics are stored whatever the filters say.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use serde_json::json;
+use subxt::utils::AccountId32;
+
+use crate::api::AppState;
+use crate::db::Webhook;
+use crate::error::Error;
+use crate::indexer::ExtrinsicInfo;
+
+/// Events that move funds between the accounts in them
+pub fn is_transfer(variant: &str) -> bool {
+    matches!(variant, "Transfer" | "Transferred" | "TransferredApproved")
+}
+
+/// POSTs the extrinsic to each of the account's webhooks, in the background.
+pub fn notify(
+    http: &reqwest::Client,
+    chain: &str,
+    block_number: u32,
+    webhooks: Vec<Webhook>,
+    signed: bool,
+    extrinsic: &ExtrinsicInfo,
+) {
+    for webhook in webhooks {
+        let payload = json!({
+            "chain": chain,
+            "block_number": block_number,
+            "address": webhook.address,
+            "signed": signed,
+            "extrinsic": extrinsic,
+        });
+        tokio::spawn(deliver(http.clone(), webhook, payload));
+    }
+}
+
+async fn deliver(http: reqwest::Client, webhook: Webhook, payload: serde_json::Value) {
+    match http.post(&webhook.url).json(&payload).send().await.and_then(|r| r.error_for_status()) {
+        Ok(_) => tracing::debug!(webhook = webhook.id, "Webhook delivered"),
+        // The URL can contain secrets, so don't log it
+        Err(e) => tracing::warn!(webhook = webhook.id, "Failed to deliver webhook: {}", e.without_url()),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewWebhook {
+    /// SS58, in any format
+    address: String,
+    url: String,
+}
+
+pub async fn add(State(state): State<AppState>, Json(new): Json<NewWebhook>) -> Result<Response, Error> {
+    let Ok(account) = new.address.parse::<AccountId32>() else {
+        return Err(Error::BadRequest(format!("Invalid address {:?}", new.address)));
+    };
+    if !reqwest::Url::parse(&new.url).is_ok_and(|url| matches!(url.scheme(), "http" | "https")) {
+        return Err(Error::BadRequest("url must be an http(s) URL".to_string()));
+    }
+
+    let webhook = state.db.blocking(move |db| db.add_webhook(account.0, &new.address, &new.url)).await?;
+    Ok((StatusCode::CREATED, Json(webhook)).into_response())
+}
+
+pub async fn list(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.db.get_webhooks())
+}
+
+pub async fn remove(State(state): State<AppState>, Path(id): Path<i64>) -> Result<StatusCode, Error> {
+    if !state.db.blocking(move |db| db.remove_webhook(id)).await? {
+        return Err(Error::NotFound(format!("No webhook {}", id)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,143 @@
+//! XCM delivery tracking. Each chain keeps the messages it sent (`PolkadotXcm`/`XcmPallet`
+//! `Sent`) and processed (`MessageQueue` `Processed`/`ProcessingFailed`) in `xcm_messages`,
+//! and `/xcm` matches them up by message id across every indexed chain.
+//!
+//! The ids only line up when the sender gives messages a topic (`SetTopic`), which current
+//! system chain runtimes do. A message whose destination isn't indexed stays "pending".
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use subxt::events::{Events, Phase};
+use subxt::ext::scale_value::At;
+use subxt::PolkadotConfig;
+
+use crate::api::AppState;
+use crate::db::{Database, XcmMessage};
+use crate::decode;
+use crate::error::Error;
+use crate::indexer::TrackerUpdates;
+
+const MAX_MESSAGES: u32 = 200;
+
+type Chains = Arc<Vec<(String, Arc<Database>)>>;
+
+/// Records the block's sent and processed XCM messages.
+pub fn record(updates: &mut TrackerUpdates, block_number: u32, events: &Events<PolkadotConfig>) {
+    for event in events.iter().flatten() {
+        let (direction, id_field, counterparty_field) = match (event.pallet_name(), event.variant_name()) {
+            ("PolkadotXcm" | "XcmPallet", "Sent") => ("sent", "message_id", "destination"),
+            ("MessageQueue", "Processed" | "ProcessingFailed") => ("received", "id", "origin"),
+            _ => continue,
+        };
+        let Ok(fields) = event.field_values() else {
+            continue;
+        };
+        let Some(message_id) = fields.at(id_field).and_then(decode::bytes) else {
+            continue;
+        };
+
+        let success = match event.variant_name() {
+            "Processed" => fields.at("success").and_then(|v| v.as_bool()),
+            "ProcessingFailed" => Some(false),
+            _ => None,
+        };
+        let message = XcmMessage {
+            block_number,
+            extrinsic_index: match event.phase() {
+                Phase::ApplyExtrinsic(i) => Some(i),
+                _ => None,
+            },
+            message_id: format!("0x{}", hex::encode(message_id)),
+            direction: direction.to_string(),
+            counterparty: fields.at(counterparty_field).map(|v| v.to_string()),
+            success,
+        };
+        let event_index = event.index();
+        updates.push("Failed to record XCM message", move |db| db.record_xcm_message(event_index, &message));
+    }
+}
+
+pub fn router(chains: &[(String, AppState)]) -> Router {
+    let chains: Chains = Arc::new(chains.iter().map(|(name, state)| (name.clone(), state.db.clone())).collect());
+    Router::new()
+        .route("/xcm", get(get_recent))
+        .route("/xcm/:id", get(get_message))
+        .with_state(chains)
+}
+
+// Where a message was sent from and processed, across all chains
+async fn delivery(chains: &Chains, message_id: &str) -> Result<Value, rusqlite::Error> {
+    let mut sent = Vec::new();
+    let mut received = Vec::new();
+    for (chain, db) in chains.iter() {
+        let id = message_id.to_string();
+        for message in db.blocking(move |db| db.get_xcm_messages(&id)).await? {
+            let mut entry = json!(message);
+            entry["chain"] = json!(chain);
+            match message.direction.as_str() {
+                "sent" => sent.push(entry),
+                _ => received.push(entry),
+            }
+        }
+    }
+
+    let status = if received.iter().any(|r| r["success"] == json!(true)) {
+        "delivered"
+    } else if !received.is_empty() {
+        "failed"
+    } else {
+        "pending"
+    };
+    Ok(json!({
+        "message_id": message_id,
+        "status": status,
+        "sent": sent,
+        "received": received,
+    }))
+}
+
+/// One message's journey: `delivered`, `failed` or `pending`.
+async fn get_message(State(chains): State<Chains>, Path(id): Path<String>) -> Result<Json<Value>, Error> {
+    let id = id.to_ascii_lowercase();
+    let message = delivery(&chains, &id).await?;
+    if message["sent"] == json!([]) && message["received"] == json!([]) {
+        return Err(Error::NotFound(format!("XCM message {} not found", id)));
+    }
+    Ok(Json(message))
+}
+
+#[derive(Deserialize)]
+struct RecentQuery {
+    /// Defaults to the first chain
+    chain: Option<String>,
+    limit: Option<u32>,
+}
+
+/// The latest messages a chain sent, with their delivery status.
+async fn get_recent(
+    State(chains): State<Chains>,
+    Query(query): Query<RecentQuery>,
+) -> Result<Json<Vec<Value>>, Error> {
+    let found = match &query.chain {
+        Some(name) => chains.iter().find(|(chain, _)| chain == name),
+        None => chains.first(),
+    };
+    let Some((_, db)) = found else {
+        return Err(Error::NotFound(format!("Unknown chain {:?}", query.chain.unwrap_or_default())));
+    };
+
+    let limit = query.limit.unwrap_or(50).min(MAX_MESSAGES);
+    let sent = db.blocking(move |db| db.get_sent_xcm_messages(limit)).await?;
+    let mut messages = Vec::with_capacity(sent.len());
+    for message in &sent {
+        messages.push(delivery(&chains, &message.message_id).await?);
+    }
+    Ok(Json(messages))
+}